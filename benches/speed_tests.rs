@@ -14,7 +14,7 @@ use prio::vdaf::prio3::Prio3Aes128CountVecMultithreaded;
 use prio::vdaf::{
     prio3::{
         Prio3Aes128Count, Prio3Aes128CountVec, Prio3Aes128Histogram, Prio3Aes128Sum,
-        Prio3InputShare,
+        Prio3Aes128SumVec, Prio3InputShare,
     },
     Client as Prio3Client,
 };
@@ -172,6 +172,22 @@ pub fn prio3_client(c: &mut Criterion) {
         })
     });
 
+    let bits = 8;
+    let len = 100;
+    let prio3 = Prio3Aes128SumVec::new(num_shares, bits, len).unwrap();
+    let measurement = vec![255; len];
+    println!(
+        "prio3 sumvec ({} bits, {} len) size = {}",
+        bits,
+        len,
+        prio3_input_share_size(&prio3.shard(&(), &measurement).unwrap())
+    );
+    c.bench_function(&format!("prio3 sumvec ({} bits, {} len)", bits, len), |b| {
+        b.iter(|| {
+            prio3.shard(&(), &measurement).unwrap();
+        })
+    });
+
     let len = 1000;
     let prio3 = Prio3Aes128CountVec::new(num_shares, len).unwrap();
     let measurement = vec![0; len];
@@ -1,17 +1,190 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use color_eyre::eyre::{Result, WrapErr};
-use prio::test_vector::Priov2TestVector;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use prio::{
+    field::{Field128, Field64},
+    test_vector::Priov2TestVector,
+    vdaf::{
+        poplar1::{Poplar1, TreeIdpf},
+        prg::PrgAes128,
+        prio3::{
+            Prio3Aes128BoundedSum, Prio3Aes128CategoricalHistogram, Prio3Aes128Count,
+            Prio3Aes128CountVec, Prio3Aes128CountVecWithWeight,
+            Prio3Aes128FixedPointBoundedL2VecSum, Prio3Aes128Histogram, Prio3Aes128MeanVariance,
+            Prio3Aes128SignedSum, Prio3Aes128Sum, Prio3Aes128SumVec, Prio3CShake128Count,
+            Prio3CShake128Histogram, Prio3CShake128Sum, Prio3Shake256Count, Prio3Shake256Histogram,
+            Prio3Shake256Sum,
+        },
+        test_vector::{generate_test_vector, TestVectorVdaf},
+    },
+};
 use structopt::StructOpt;
 
+/// The Poplar1 instance for which test vectors are generated.
+type Poplar1Aes128 = Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16>;
+
 #[derive(Debug, StructOpt)]
-#[structopt(about = "Generate Priov2 test vector", rename_all = "kebab-case")]
+#[structopt(about = "Generate test vector", rename_all = "kebab-case")]
 enum Subcommand {
+    /// Generate a Priov2 test vector from random inputs
     Priov2 {
         /// Dimension (number of bins) of the inputs
         #[structopt(short, long, required = true)]
         dimension: usize,
     },
+    /// Generate a Prio3Aes128Count test vector
+    Prio3Aes128Count(Prio3Options),
+    /// Generate a Prio3CShake128Count test vector
+    Prio3CShake128Count(Prio3Options),
+    /// Generate a Prio3Shake256Count test vector
+    Prio3Shake256Count(Prio3Options),
+    /// Generate a Prio3Aes128CountVec test vector
+    Prio3Aes128CountVec {
+        /// Length of each measurement
+        #[structopt(short, long, required = true)]
+        len: usize,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128CountVecWithWeight test vector
+    Prio3Aes128CountVecWithWeight {
+        /// Length of each measurement
+        #[structopt(short, long, required = true)]
+        len: usize,
+        /// Maximum number of ones in each measurement
+        #[structopt(short, long, required = true)]
+        weight: usize,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128Sum test vector
+    Prio3Aes128Sum {
+        /// Bit length of each measurement
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3CShake128Sum test vector
+    Prio3CShake128Sum {
+        /// Bit length of each measurement
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Shake256Sum test vector
+    Prio3Shake256Sum {
+        /// Bit length of each measurement
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128BoundedSum test vector
+    Prio3Aes128BoundedSum {
+        /// Smallest valid measurement
+        #[structopt(long, required = true)]
+        min: u64,
+        /// Largest valid measurement
+        #[structopt(long, required = true)]
+        max: u64,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128SignedSum test vector
+    Prio3Aes128SignedSum {
+        /// Bit length of each measurement, including the sign bit
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128MeanVariance test vector
+    Prio3Aes128MeanVariance {
+        /// Bit length of each measurement
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128SumVec test vector
+    Prio3Aes128SumVec {
+        /// Bit length of each entry of a measurement
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        /// Length of each measurement
+        #[structopt(short, long, required = true)]
+        len: usize,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128FixedPointBoundedL2VecSum test vector
+    Prio3Aes128FixedPointBoundedL2VecSum {
+        /// Bit length of each entry of a measurement, including the sign bit
+        #[structopt(short, long, required = true)]
+        bits: u32,
+        /// Length of each measurement
+        #[structopt(short, long, required = true)]
+        len: usize,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128Histogram test vector
+    Prio3Aes128Histogram {
+        /// Upper bound of each bucket except the last
+        #[structopt(long, required = true, use_delimiter = true)]
+        buckets: Vec<u64>,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3CShake128Histogram test vector
+    Prio3CShake128Histogram {
+        /// Upper bound of each bucket except the last
+        #[structopt(long, required = true, use_delimiter = true)]
+        buckets: Vec<u64>,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Shake256Histogram test vector
+    Prio3Shake256Histogram {
+        /// Upper bound of each bucket except the last
+        #[structopt(long, required = true, use_delimiter = true)]
+        buckets: Vec<u64>,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Prio3Aes128CategoricalHistogram test vector
+    Prio3Aes128CategoricalHistogram {
+        /// Label of each bucket. Each measurement is the index of a bucket.
+        #[structopt(long, required = true, use_delimiter = true)]
+        labels: Vec<String>,
+        #[structopt(flatten)]
+        options: Prio3Options,
+    },
+    /// Generate a Poplar1 test vector with AES-128 as the PRG
+    Poplar1Aes128 {
+        /// Bit length of each measurement
+        #[structopt(short, long, required = true)]
+        bits: usize,
+        /// Level of the prefix tree to aggregate. The candidate prefixes are the distinct prefixes
+        /// of the measurements at this level.
+        #[structopt(long, required = true)]
+        level: usize,
+        /// Measurements, each an integer whose most significant bit is the first bit of the input
+        #[structopt(required = true)]
+        measurements: Vec<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct Prio3Options {
+    /// Number of aggregators
+    #[structopt(short = "a", long, default_value = "2")]
+    num_aggregators: u8,
+    /// Measurements, each encoded as JSON
+    #[structopt(required = true)]
+    measurements: Vec<String>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -22,9 +195,9 @@ enum Subcommand {
     version = env!("CARGO_PKG_VERSION"),
 )]
 struct Options {
-    /// Number of inputs to generate
-    #[structopt(short, long, required = true)]
-    number_of_inputs: usize,
+    /// Number of inputs to generate (Priov2 only)
+    #[structopt(short, long)]
+    number_of_inputs: Option<usize>,
     /// Subcommand determines what kind of vector to construct
     #[structopt(subcommand)]
     command: Subcommand,
@@ -40,13 +213,161 @@ fn generate_and_print_priov2_vector(number_of_inputs: usize, dimension: usize) -
     Ok(())
 }
 
+fn generate_and_print_vdaf_vector<V>(
+    vdaf: &V,
+    agg_param: &V::TestVecAggParam,
+    measurements: &[String],
+) -> Result<()>
+where
+    V: TestVectorVdaf,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    let measurements = measurements
+        .iter()
+        .map(|measurement| {
+            serde_json::from_str(measurement)
+                .wrap_err_with(|| format!("failed to decode measurement {:?}", measurement))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let test_vector = generate_test_vector(vdaf, agg_param, &measurements)
+        .wrap_err("failed to create test vector")?;
+    let json =
+        serde_json::to_string(&test_vector).wrap_err("failed to encode test vector to JSON")?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+fn generate_and_print_prio3_vector<V>(
+    vdaf: Result<V, prio::vdaf::VdafError>,
+    options: &Prio3Options,
+) -> Result<()>
+where
+    V: TestVectorVdaf<TestVecAggParam = ()>,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    let vdaf = vdaf.wrap_err("failed to construct VDAF")?;
+    generate_and_print_vdaf_vector(&vdaf, &(), &options.measurements)
+}
+
+fn generate_and_print_poplar1_vector(
+    bits: usize,
+    level: usize,
+    measurements: &[String],
+) -> Result<()> {
+    if level >= bits {
+        return Err(eyre!("level must be less than the number of bits"));
+    }
+
+    // The candidate prefixes are the distinct prefixes of the measurements, in ascending order.
+    let mut prefixes = measurements
+        .iter()
+        .map(|measurement| {
+            let measurement: u128 = serde_json::from_str(measurement)
+                .wrap_err_with(|| format!("failed to decode measurement {:?}", measurement))?;
+            Ok(measurement >> (bits - 1 - level))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    prefixes.sort_unstable();
+    prefixes.dedup();
+
+    generate_and_print_vdaf_vector(&Poplar1Aes128::new(bits), &(level, prefixes), measurements)
+}
+
 fn main() -> Result<()> {
     color_eyre::install()?;
     let options = Options::from_args();
 
     match options.command {
-        Subcommand::Priov2 { dimension } => {
-            generate_and_print_priov2_vector(dimension, options.number_of_inputs)
+        Subcommand::Priov2 { dimension } => generate_and_print_priov2_vector(
+            dimension,
+            options
+                .number_of_inputs
+                .ok_or_else(|| eyre!("--number-of-inputs is required for Priov2"))?,
+        ),
+        Subcommand::Prio3Aes128Count(options) => generate_and_print_prio3_vector(
+            Prio3Aes128Count::new(options.num_aggregators),
+            &options,
+        ),
+        Subcommand::Prio3CShake128Count(options) => generate_and_print_prio3_vector(
+            Prio3CShake128Count::new(options.num_aggregators),
+            &options,
+        ),
+        Subcommand::Prio3Shake256Count(options) => generate_and_print_prio3_vector(
+            Prio3Shake256Count::new(options.num_aggregators),
+            &options,
+        ),
+        Subcommand::Prio3Aes128CountVec { len, options } => generate_and_print_prio3_vector(
+            Prio3Aes128CountVec::new(options.num_aggregators, len),
+            &options,
+        ),
+        Subcommand::Prio3Aes128CountVecWithWeight {
+            len,
+            weight,
+            options,
+        } => generate_and_print_prio3_vector(
+            Prio3Aes128CountVecWithWeight::new(options.num_aggregators, len, weight),
+            &options,
+        ),
+        Subcommand::Prio3Aes128Sum { bits, options } => generate_and_print_prio3_vector(
+            Prio3Aes128Sum::new(options.num_aggregators, bits),
+            &options,
+        ),
+        Subcommand::Prio3CShake128Sum { bits, options } => generate_and_print_prio3_vector(
+            Prio3CShake128Sum::new(options.num_aggregators, bits),
+            &options,
+        ),
+        Subcommand::Prio3Shake256Sum { bits, options } => generate_and_print_prio3_vector(
+            Prio3Shake256Sum::new(options.num_aggregators, bits),
+            &options,
+        ),
+        Subcommand::Prio3Aes128BoundedSum { min, max, options } => generate_and_print_prio3_vector(
+            Prio3Aes128BoundedSum::new(options.num_aggregators, min, max),
+            &options,
+        ),
+        Subcommand::Prio3Aes128SignedSum { bits, options } => generate_and_print_prio3_vector(
+            Prio3Aes128SignedSum::new(options.num_aggregators, bits),
+            &options,
+        ),
+        Subcommand::Prio3Aes128MeanVariance { bits, options } => generate_and_print_prio3_vector(
+            Prio3Aes128MeanVariance::new(options.num_aggregators, bits),
+            &options,
+        ),
+        Subcommand::Prio3Aes128SumVec { bits, len, options } => generate_and_print_prio3_vector(
+            Prio3Aes128SumVec::new(options.num_aggregators, bits, len),
+            &options,
+        ),
+        Subcommand::Prio3Aes128FixedPointBoundedL2VecSum { bits, len, options } => {
+            generate_and_print_prio3_vector(
+                Prio3Aes128FixedPointBoundedL2VecSum::new(options.num_aggregators, bits, len),
+                &options,
+            )
+        }
+        Subcommand::Prio3Aes128Histogram { buckets, options } => generate_and_print_prio3_vector(
+            Prio3Aes128Histogram::new(options.num_aggregators, &buckets),
+            &options,
+        ),
+        Subcommand::Prio3CShake128Histogram { buckets, options } => {
+            generate_and_print_prio3_vector(
+                Prio3CShake128Histogram::new(options.num_aggregators, &buckets),
+                &options,
+            )
+        }
+        Subcommand::Prio3Shake256Histogram { buckets, options } => generate_and_print_prio3_vector(
+            Prio3Shake256Histogram::new(options.num_aggregators, &buckets),
+            &options,
+        ),
+        Subcommand::Prio3Aes128CategoricalHistogram { labels, options } => {
+            let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+            generate_and_print_prio3_vector(
+                Prio3Aes128CategoricalHistogram::new(options.num_aggregators, &labels),
+                &options,
+            )
         }
+        Subcommand::Poplar1Aes128 {
+            bits,
+            level,
+            measurements,
+        } => generate_and_print_poplar1_vector(bits, level, &measurements),
     }
 }
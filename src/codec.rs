@@ -303,6 +303,44 @@ pub fn decode_u24_items<P, D: ParameterizedDecode<P>>(
     decode_items(length, decoding_parameter, bytes)
 }
 
+/// Encode `items` into `bytes` as a [variable-length vector][1] with a maximum length of
+/// `0xffffffff`.
+///
+/// [1]: https://datatracker.ietf.org/doc/html/rfc8446#section-3.4
+pub fn encode_u32_items<P, E: ParameterizedEncode<P>>(
+    bytes: &mut Vec<u8>,
+    encoding_parameter: &P,
+    items: &[E],
+) {
+    // Reserve space to later write length
+    let len_offset = bytes.len();
+    0u32.encode(bytes);
+
+    for item in items {
+        item.encode_with_param(encoding_parameter, bytes);
+    }
+
+    let len = bytes.len() - len_offset - 4;
+    assert!(len <= u32::MAX as usize);
+    for (offset, byte) in u32::to_be_bytes(len as u32).iter().enumerate() {
+        bytes[len_offset + offset] = *byte;
+    }
+}
+
+/// Decode `bytes` into a vector of `D` values, treating `bytes` as a [variable-length vector][1] of
+/// maximum length `0xffffffff`.
+///
+/// [1]: https://datatracker.ietf.org/doc/html/rfc8446#section-3.4
+pub fn decode_u32_items<P, D: ParameterizedDecode<P>>(
+    decoding_parameter: &P,
+    bytes: &mut Cursor<&[u8]>,
+) -> Result<Vec<D>, CodecError> {
+    // Read four bytes to get length of opaque byte vector
+    let length = u32::decode(bytes)? as usize;
+
+    decode_items(length, decoding_parameter, bytes)
+}
+
 /// Decode the next `length` bytes from `bytes` into as many instances of `D` as possible.
 fn decode_items<P, D: ParameterizedDecode<P>>(
     length: usize,
@@ -570,6 +608,30 @@ mod tests {
         assert_eq!(values, decoded);
     }
 
+    #[test]
+    fn roundtrip_variable_length_u32() {
+        let values = messages_vec();
+        let mut bytes = vec![];
+        encode_u32_items(&mut bytes, &(), &values);
+
+        assert_eq!(
+            bytes.len(),
+            // Length of opaque vector
+            4 +
+            // 3 TestMessage values
+            3 * TestMessage::encoded_length()
+        );
+
+        // Check endianness of encoded length
+        assert_eq!(
+            bytes[0..4],
+            [0, 0, 0, 3 * TestMessage::encoded_length() as u8]
+        );
+
+        let decoded = decode_u32_items(&(), &mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(values, decoded);
+    }
+
     #[test]
     fn decode_items_overflow() {
         let encoded = vec![1u8];
@@ -56,6 +56,7 @@ use std::any::Any;
 use std::convert::TryFrom;
 use std::fmt::Debug;
 
+pub mod circuit;
 pub mod gadgets;
 pub mod types;
 
@@ -322,6 +323,33 @@ pub trait Type: Sized + Eq + Clone + Debug {
         query_rand: &[Self::Field],
         joint_rand: &[Self::Field],
         num_shares: usize,
+    ) -> Result<Vec<Self::Field>, FlpError> {
+        self.query_with_scratch(
+            &mut QueryScratch::new(),
+            input,
+            proof,
+            query_rand,
+            joint_rand,
+            num_shares,
+        )
+    }
+
+    /// Like [`Type::query`], except that the buffers used by the verifier, including the
+    /// evaluations of each gadget polynomial, are kept in `scratch` and reused by subsequent calls
+    /// with the same scratch space. This avoids allocating them anew for each proof when many
+    /// proofs are queried.
+    ///
+    /// The scratch space records the arity, degree and number of calls of each gadget it was built
+    /// for. If these do not match the gadgets of this type, then it is rebuilt.
+    #[allow(clippy::needless_range_loop)]
+    fn query_with_scratch(
+        &self,
+        scratch: &mut QueryScratch<Self::Field>,
+        input: &[Self::Field],
+        proof: &[Self::Field],
+        query_rand: &[Self::Field],
+        joint_rand: &[Self::Field],
+        num_shares: usize,
     ) -> Result<Vec<Self::Field>, FlpError> {
         if input.len() != self.input_len() {
             return Err(FlpError::Query(format!(
@@ -355,41 +383,46 @@ pub trait Type: Sized + Eq + Clone + Debug {
             )));
         }
 
+        let gadgets = self.gadget();
+        if !scratch.matches(&gadgets) {
+            scratch.shim = gadgets
+                .iter()
+                .map(|gadget| {
+                    Box::new(QueryShimGadget::new(gadget.as_ref())) as Box<dyn Gadget<Self::Field>>
+                })
+                .collect();
+        }
+        let QueryScratch { shim, f } = scratch;
+
         let mut proof_len = 0;
-        let mut shim = self
-            .gadget()
-            .into_iter()
-            .enumerate()
-            .map(|(idx, gadget)| {
-                let gadget_degree = gadget.degree();
-                let gadget_arity = gadget.arity();
-                let m = (1 + gadget.calls()).next_power_of_two();
-                let r = query_rand[idx];
-
-                // Make sure the query randomness isn't a root of unity. Evaluating the gadget
-                // polynomial at any of these points would be a privacy violation, since these points
-                // were used by the prover to construct the wire polynomials.
-                if r.pow(<Self::Field as FieldElement>::Integer::try_from(m).unwrap())
-                    == Self::Field::one()
-                {
-                    return Err(FlpError::Query(format!(
-                        "invalid query randomness: encountered 2^{}-th root of unity",
-                        m
-                    )));
-                }
+        for idx in 0..shim.len() {
+            let gadget = shim[idx]
+                .as_any()
+                .downcast_mut::<QueryShimGadget<Self::Field>>()
+                .unwrap();
+            let m = (1 + gadget.calls()).next_power_of_two();
+            let r = query_rand[idx];
 
-                // Compute the length of the sub-proof corresponding to the `idx`-th gadget.
-                let next_len = gadget_arity + gadget_degree * (m - 1) + 1;
-                let proof_data = &proof[proof_len..proof_len + next_len];
-                proof_len += next_len;
+            // Make sure the query randomness isn't a root of unity. Evaluating the gadget
+            // polynomial at any of these points would be a privacy violation, since these points
+            // were used by the prover to construct the wire polynomials.
+            if r.pow(<Self::Field as FieldElement>::Integer::try_from(m).unwrap())
+                == Self::Field::one()
+            {
+                return Err(FlpError::Query(format!(
+                    "invalid query randomness: encountered 2^{}-th root of unity",
+                    m
+                )));
+            }
 
-                Ok(Box::new(QueryShimGadget::new(gadget, r, proof_data)?)
-                    as Box<dyn Gadget<Self::Field>>)
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+            // Compute the length of the sub-proof corresponding to the `idx`-th gadget.
+            let next_len = gadget.arity() + gadget.degree() * (m - 1) + 1;
+            gadget.reset(r, &proof[proof_len..proof_len + next_len])?;
+            proof_len += next_len;
+        }
 
         // Create a buffer for the verifier data. This includes the output of the validity circuit and,
-        // for each gadget `shim[idx].inner`, the wire polynomials evaluated at the query randomness
+        // for each gadget `shim[idx]`, the wire polynomials evaluated at the query randomness
         // `query_rand[idx]` and the gadget polynomial evaluated at `query_rand[idx]`.
         let data_len = 1
             + (0..shim.len())
@@ -405,7 +438,7 @@ pub trait Type: Sized + Eq + Clone + Debug {
         // equal to the output of the last gadget evaluation. Here we relax this assumption. This
         // should be OK, since it's possible to transform any circuit into one for which this is true.
         // (Needs security analysis.)
-        let validity = self.valid(&mut shim, input, joint_rand, num_shares)?;
+        let validity = self.valid(shim, input, joint_rand, num_shares)?;
         verifier.push(validity);
 
         // Fill the buffer with the verifier message.
@@ -422,11 +455,11 @@ pub trait Type: Sized + Eq + Clone + Debug {
             let m_inv =
                 Self::Field::from(<Self::Field as FieldElement>::Integer::try_from(m).unwrap())
                     .inv();
-            let mut f = vec![Self::Field::zero(); m];
+            f.resize(m, Self::Field::zero());
             for wire in 0..gadget.arity() {
-                discrete_fourier_transform(&mut f, &gadget.f_vals[wire], m)?;
-                discrete_fourier_transform_inv_finish(&mut f, m, m_inv);
-                verifier.push(poly_eval(&f, r));
+                discrete_fourier_transform(f, &gadget.f_vals[wire], m)?;
+                discrete_fourier_transform_inv_finish(f, m, m_inv);
+                verifier.push(poly_eval(f, r));
             }
 
             // Add the value of the gadget polynomial evaluated at `r`.
@@ -556,11 +589,57 @@ impl<F: FieldElement> Gadget<F> for ProveShimGadget<F> {
     }
 }
 
+/// Scratch space used by [`Type::query_with_scratch`] to check many proofs for the same type.
+#[derive(Debug)]
+pub struct QueryScratch<F: FieldElement> {
+    /// The "shim" gadgets used to query the proof, one for each gadget of the type. Each shim
+    /// records the arity, degree and number of calls of the gadget it was built for.
+    shim: Vec<Box<dyn Gadget<F>>>,
+
+    /// Buffer used to reconstruct the wire polynomials.
+    f: Vec<F>,
+}
+
+impl<F: FieldElement> QueryScratch<F> {
+    /// Returns an empty scratch space. Its buffers are allocated on first use.
+    pub fn new() -> Self {
+        Self {
+            shim: Vec::new(),
+            f: Vec::new(),
+        }
+    }
+
+    /// Returns true if the shims were built for gadgets with the same arity, degree and number of
+    /// calls as `gadgets`.
+    fn matches(&self, gadgets: &[Box<dyn Gadget<F>>]) -> bool {
+        self.shim.len() == gadgets.len()
+            && self.shim.iter().zip(gadgets.iter()).all(|(shim, gadget)| {
+                shim.arity() == gadget.arity()
+                    && shim.degree() == gadget.degree()
+                    && shim.calls() == gadget.calls()
+            })
+    }
+}
+
+impl<F: FieldElement> Default for QueryScratch<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // A "shim" gadget used during proof verification to record the points at which the intermediate
-// proof polynomials are evaluated.
+// proof polynomials are evaluated. The shim is reset before each proof is queried, so that its
+// buffers can be reused.
 #[derive(Debug)]
 struct QueryShimGadget<F: FieldElement> {
-    inner: Box<dyn Gadget<F>>,
+    /// The arity of the gadget being checked.
+    arity: usize,
+
+    /// The degree of the gadget being checked.
+    degree: usize,
+
+    /// The number of times the gadget being checked is called.
+    calls: usize,
 
     /// Points at which intermediate proof polynomials are interpolated.
     f_vals: Vec<Vec<F>>,
@@ -579,40 +658,48 @@ struct QueryShimGadget<F: FieldElement> {
 }
 
 impl<F: FieldElement> QueryShimGadget<F> {
-    fn new(inner: Box<dyn Gadget<F>>, r: F, proof_data: &[F]) -> Result<Self, FlpError> {
+    fn new(inner: &dyn Gadget<F>) -> Self {
         let gadget_degree = inner.degree();
         let gadget_arity = inner.arity();
         let m = (1 + inner.calls()).next_power_of_two();
         let p = m * gadget_degree;
 
+        // The step is used to compute the element of `p_val` that will be returned by a call to
+        // the gadget.
+        let step = (1 << (log2(p as u128) - log2(m as u128))) as usize;
+
+        Self {
+            arity: gadget_arity,
+            degree: gadget_degree,
+            calls: inner.calls(),
+            f_vals: vec![vec![F::zero(); 1 + inner.calls()]; gadget_arity],
+            p_vals: vec![F::zero(); p.next_power_of_two()],
+            p_at_r: F::zero(),
+            step,
+            ct: 1,
+        }
+    }
+
+    /// Prepares the shim for querying the sub-proof `proof_data` at query randomness `r`.
+    fn reset(&mut self, r: F, proof_data: &[F]) -> Result<(), FlpError> {
         // Each call to this gadget records the values at which intermediate proof polynomials were
         // interpolated. The first point was a random value chosen by the prover and transmitted in
         // the proof.
-        let mut f_vals = vec![vec![F::zero(); 1 + inner.calls()]; gadget_arity];
-        for wire in 0..gadget_arity {
-            f_vals[wire][0] = proof_data[wire];
+        for (f_vals, seed) in self.f_vals.iter_mut().zip(proof_data[..self.arity].iter()) {
+            f_vals[0] = *seed;
+            for x in f_vals[1..].iter_mut() {
+                *x = F::zero();
+            }
         }
 
         // Evaluate the gadget polynomial at roots of unity.
-        let size = p.next_power_of_two();
-        let mut p_vals = vec![F::zero(); size];
-        discrete_fourier_transform(&mut p_vals, &proof_data[gadget_arity..], size)?;
-
-        // The step is used to compute the element of `p_val` that will be returned by a call to
-        // the gadget.
-        let step = (1 << (log2(p as u128) - log2(m as u128))) as usize;
+        let size = self.p_vals.len();
+        discrete_fourier_transform(&mut self.p_vals, &proof_data[self.arity..], size)?;
 
         // Evaluate the gadget polynomial `p` at query randomness `r`.
-        let p_at_r = poly_eval(&proof_data[gadget_arity..], r);
-
-        Ok(Self {
-            inner,
-            f_vals,
-            p_vals,
-            p_at_r,
-            step,
-            ct: 1,
-        })
+        self.p_at_r = poly_eval(&proof_data[self.arity..], r);
+        self.ct = 1;
+        Ok(())
     }
 }
 
@@ -632,15 +719,15 @@ impl<F: FieldElement> Gadget<F> for QueryShimGadget<F> {
     }
 
     fn arity(&self) -> usize {
-        self.inner.arity()
+        self.arity
     }
 
     fn degree(&self) -> usize {
-        self.inner.degree()
+        self.degree
     }
 
     fn calls(&self) -> usize {
-        self.inner.calls()
+        self.calls
     }
 
     fn as_any(&mut self) -> &mut dyn Any {
@@ -653,6 +740,7 @@ mod tests {
     use super::*;
     use crate::field::{random_vector, split_vector, Field128};
     use crate::flp::gadgets::{Mul, PolyEval};
+    use crate::flp::types::Count;
     use crate::polynomial::poly_range_check;
 
     use std::marker::PhantomData;
@@ -707,6 +795,42 @@ mod tests {
         assert!(typ.decide(&verifier).unwrap());
     }
 
+    #[test]
+    fn test_query_with_scratch() {
+        let typ: TestType<Field128> = TestType::new();
+        let mut scratch = QueryScratch::new();
+        for x in [2, 3, 4, 3] {
+            let input = typ.encode(&x).unwrap();
+            let joint_rand = random_vector(typ.joint_rand_len()).unwrap();
+            let prove_rand = random_vector(typ.prove_rand_len()).unwrap();
+            let query_rand = random_vector(typ.query_rand_len()).unwrap();
+            let proof = typ.prove(&input, &prove_rand, &joint_rand).unwrap();
+
+            // Reusing the scratch space across queries yields the same verifier message.
+            let want = typ
+                .query(&input, &proof, &query_rand, &joint_rand, 1)
+                .unwrap();
+            let got = typ
+                .query_with_scratch(&mut scratch, &input, &proof, &query_rand, &joint_rand, 1)
+                .unwrap();
+            assert_eq!(got, want);
+            assert!(typ.decide(&got).unwrap());
+        }
+
+        // The scratch space is rebuilt when it is used with a type whose gadgets differ.
+        let typ: Count<Field128> = Count::new();
+        let input = typ.encode(&1).unwrap();
+        let prove_rand = random_vector(typ.prove_rand_len()).unwrap();
+        let query_rand = random_vector(typ.query_rand_len()).unwrap();
+        let proof = typ.prove(&input, &prove_rand, &[]).unwrap();
+        let want = typ.query(&input, &proof, &query_rand, &[], 1).unwrap();
+        let got = typ
+            .query_with_scratch(&mut scratch, &input, &proof, &query_rand, &[], 1)
+            .unwrap();
+        assert_eq!(got, want);
+        assert!(typ.decide(&got).unwrap());
+    }
+
     /// A toy type used for testing the functionality in this module. Valid inputs of this type
     /// consist of a pair of field elements `(x, y)` where `2 <= x < 5` and `x^3 == y`.
     #[derive(Clone, Debug, PartialEq, Eq)]
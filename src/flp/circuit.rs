@@ -0,0 +1,598 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! A builder for validity circuits of user-defined [`Type`]s.
+//!
+//! Implementing [`Type`] by hand requires computing the proof, verifier and randomness lengths
+//! from the gadgets used by the validity circuit. A mistake in any of these only shows up as
+//! proofs that fail to verify. Instead, a [`CircuitBuilder`] is used to describe the layout of the
+//! encoded measurement and the constraints it must satisfy. The resulting [`Circuit`] implements
+//! [`Type`] and derives its gadgets and lengths from the constraints. For example:
+//!
+//! ```
+//! use prio::flp::circuit::CircuitBuilder;
+//! use prio::flp::Type;
+//! use prio::field::{random_vector, FieldElement, Field64};
+//!
+//! // Each measurement is a 4-bit integer `x`, followed by `x`, followed by `x^2`.
+//! let mut builder = CircuitBuilder::<Field64>::new();
+//! let bits = builder.add_input(4);
+//! let x = builder.add_input(1).start;
+//! let x_squared = builder.add_input(1).start;
+//! builder.bit_check(bits.clone());
+//! builder.linear_check(
+//!     bits.clone()
+//!         .zip([1, 2, 4, 8])
+//!         .map(|(i, w)| (i, Field64::from(w)))
+//!         .chain([(x, -Field64::one())])
+//!         .collect(),
+//!     Field64::zero(),
+//! );
+//! builder.product_check(x, x, x_squared);
+//! builder.output(x..x_squared + 1);
+//! let circuit = builder.build().unwrap();
+//!
+//! let measurement = [1, 1, 0, 0, 3, 9].iter().map(|v| Field64::from(*v)).collect();
+//! let input = circuit.encode(&measurement).unwrap();
+//! let joint_rand = random_vector(circuit.joint_rand_len()).unwrap();
+//! let prove_rand = random_vector(circuit.prove_rand_len()).unwrap();
+//! let proof = circuit.prove(&input, &prove_rand, &joint_rand).unwrap();
+//!
+//! let query_rand = random_vector(circuit.query_rand_len()).unwrap();
+//! let verifier = circuit.query(&input, &proof, &query_rand, &joint_rand, 1).unwrap();
+//! assert!(circuit.decide(&verifier).unwrap());
+//! ```
+
+use crate::field::FieldElement;
+use crate::flp::gadgets::{Mul, PolyEval};
+use crate::flp::types::{truncate_call_check, valid_call_check};
+use crate::flp::{FlpError, Gadget, Type};
+use crate::polynomial::poly_range_check;
+
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// A constraint on the encoded measurement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Constraint<F> {
+    /// Each element of `wires` is in `[min, max)`.
+    Range {
+        wires: Range<usize>,
+        min: usize,
+        max: usize,
+    },
+
+    /// `input[a] * input[b] == input[c]`.
+    Product { a: usize, b: usize, c: usize },
+
+    /// The sum of `coeff * input[i]` for each `(i, coeff)` in `terms` is equal to `constant`.
+    Linear { terms: Vec<(usize, F)>, constant: F },
+}
+
+/// Describes the layout of an encoded measurement and the constraints on it. See the
+/// [module documentation](self) for an example.
+#[derive(Clone, Debug)]
+pub struct CircuitBuilder<F> {
+    input_len: usize,
+    constraints: Vec<Constraint<F>>,
+    output: Vec<Range<usize>>,
+}
+
+impl<F: FieldElement> CircuitBuilder<F> {
+    /// Returns a builder for a circuit with no inputs and no constraints.
+    pub fn new() -> Self {
+        Self {
+            input_len: 0,
+            constraints: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Appends `len` elements to the encoded measurement and returns their indices.
+    pub fn add_input(&mut self, len: usize) -> Range<usize> {
+        let wires = self.input_len..self.input_len + len;
+        self.input_len += len;
+        wires
+    }
+
+    /// Requires each of the given elements to be `0` or `1`.
+    pub fn bit_check(&mut self, wires: Range<usize>) {
+        self.range_check(wires, 0, 2);
+    }
+
+    /// Requires each of the given elements to be an integer in `[min, max)`. The degree of the
+    /// range check polynomial is `max - min`, so large ranges should instead be expressed as a bit
+    /// decomposition using [`Self::bit_check`] and [`Self::linear_check`].
+    pub fn range_check(&mut self, wires: Range<usize>, min: usize, max: usize) {
+        self.constraints.push(Constraint::Range { wires, min, max });
+    }
+
+    /// Requires `input[a] * input[b] == input[c]`.
+    pub fn product_check(&mut self, a: usize, b: usize, c: usize) {
+        self.constraints.push(Constraint::Product { a, b, c });
+    }
+
+    /// Requires the sum of `coeff * input[i]` for each `(i, coeff)` in `terms` to be equal to
+    /// `constant`.
+    pub fn linear_check(&mut self, terms: Vec<(usize, F)>, constant: F) {
+        self.constraints
+            .push(Constraint::Linear { terms, constant });
+    }
+
+    /// Appends the given elements to the output of the circuit, i.e., the part of the encoded
+    /// measurement that is aggregated. If this method is never called, then the output is the
+    /// entire encoded measurement.
+    pub fn output(&mut self, wires: Range<usize>) {
+        self.output.push(wires);
+    }
+
+    /// Returns the circuit described by this builder.
+    pub fn build(&self) -> Result<Circuit<F>, FlpError> {
+        let check_wire = |i: usize| {
+            if i >= self.input_len {
+                return Err(FlpError::Encode(format!(
+                    "wire index ({}) exceeds input length ({})",
+                    i, self.input_len
+                )));
+            }
+            Ok(())
+        };
+        let check_wires = |wires: &Range<usize>| {
+            if wires.start > wires.end || wires.end > self.input_len {
+                return Err(FlpError::Encode(format!(
+                    "wire range ({:?}) exceeds input length ({})",
+                    wires, self.input_len
+                )));
+            }
+            Ok(())
+        };
+
+        // Each distinct range is checked by its own gadget. The `Mul` gadget, if needed, comes
+        // after the range check gadgets.
+        let mut range_gadgets: Vec<(usize, usize, usize)> = Vec::new();
+        let mut mul_calls = 0;
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Range { wires, min, max } => {
+                    check_wires(wires)?;
+                    if min >= max {
+                        return Err(FlpError::Encode(format!(
+                            "invalid range: [{}, {}) is empty",
+                            min, max
+                        )));
+                    }
+                    F::Integer::try_from(*max)
+                        .ok()
+                        .filter(|max| *max < F::modulus())
+                        .ok_or_else(|| {
+                            FlpError::Encode(format!("range bound ({}) exceeds field modulus", max))
+                        })?;
+
+                    let calls = wires.end - wires.start;
+                    match range_gadgets
+                        .iter_mut()
+                        .find(|(gmin, gmax, _)| gmin == min && gmax == max)
+                    {
+                        Some((_, _, gadget_calls)) => *gadget_calls += calls,
+                        None => range_gadgets.push((*min, *max, calls)),
+                    }
+                }
+                Constraint::Product { a, b, c } => {
+                    check_wire(*a)?;
+                    check_wire(*b)?;
+                    check_wire(*c)?;
+                    mul_calls += 1;
+                }
+                Constraint::Linear { terms, .. } => {
+                    for (i, _) in terms.iter() {
+                        check_wire(*i)?;
+                    }
+                }
+            }
+        }
+
+        // A range check on an empty set of wires does not need a gadget.
+        range_gadgets.retain(|(_, _, calls)| *calls > 0);
+
+        for wires in self.output.iter() {
+            check_wires(wires)?;
+        }
+        let mut output = self.output.clone();
+        if output.is_empty() {
+            output.push(0..self.input_len);
+        }
+
+        // The gadgets are built once here and cloned for each proof or query.
+        let range_gadgets = range_gadgets
+            .into_iter()
+            .map(|(min, max, calls)| (min, max, PolyEval::new(poly_range_check(min, max), calls)))
+            .collect();
+        let mul_gadget = if mul_calls > 0 {
+            Some(Mul::new(mul_calls))
+        } else {
+            None
+        };
+
+        Ok(Circuit {
+            input_len: self.input_len,
+            constraints: self.constraints.clone(),
+            output,
+            range_gadgets,
+            mul_gadget,
+        })
+    }
+}
+
+impl<F: FieldElement> Default for CircuitBuilder<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A validity circuit built by a [`CircuitBuilder`]. Each measurement is the encoded input itself,
+/// i.e., a vector of field elements with the layout described by the builder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Circuit<F: FieldElement> {
+    input_len: usize,
+    constraints: Vec<Constraint<F>>,
+    output: Vec<Range<usize>>,
+    range_gadgets: Vec<(usize, usize, PolyEval<F>)>,
+    mul_gadget: Option<Mul<F>>,
+}
+
+impl<F: FieldElement> Circuit<F> {
+    /// Returns the index of the gadget used to check the given range.
+    fn range_gadget(&self, min: usize, max: usize) -> usize {
+        self.range_gadgets
+            .iter()
+            .position(|(gmin, gmax, _)| *gmin == min && *gmax == max)
+            .unwrap()
+    }
+
+    /// Returns the index of the `Mul` gadget.
+    fn mul_gadget(&self) -> usize {
+        self.range_gadgets.len()
+    }
+
+    /// Returns the gadgets of the circuit in the order expected by [`Type::valid`].
+    fn gadgets(&self) -> impl Iterator<Item = &dyn Gadget<F>> {
+        self.range_gadgets
+            .iter()
+            .map(|(_, _, gadget)| gadget as &dyn Gadget<F>)
+            .chain(
+                self.mul_gadget
+                    .iter()
+                    .map(|gadget| gadget as &dyn Gadget<F>),
+            )
+    }
+}
+
+impl<F: FieldElement> Type for Circuit<F> {
+    type Measurement = Vec<F>;
+    type Field = F;
+
+    fn encode(&self, measurement: &Vec<F>) -> Result<Vec<F>, FlpError> {
+        if measurement.len() != self.input_len {
+            return Err(FlpError::Encode(format!(
+                "unexpected measurement length: got {}; want {}",
+                measurement.len(),
+                self.input_len
+            )));
+        }
+
+        // Check the constraints on the plaintext measurement so that the client learns about an
+        // invalid measurement before it is sent.
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Range { wires, min, max } => {
+                    let min = F::Integer::try_from(*min).unwrap();
+                    let max = F::Integer::try_from(*max).unwrap();
+                    for x in measurement[wires.clone()].iter() {
+                        let x = F::Integer::from(*x);
+                        if x < min || x >= max {
+                            return Err(FlpError::Encode(format!(
+                                "value ({:?}) is out of range [{:?}, {:?})",
+                                x, min, max
+                            )));
+                        }
+                    }
+                }
+                Constraint::Product { a, b, c } => {
+                    if measurement[*a] * measurement[*b] != measurement[*c] {
+                        return Err(FlpError::Encode(format!(
+                            "product check failed: input[{}] * input[{}] != input[{}]",
+                            a, b, c
+                        )));
+                    }
+                }
+                Constraint::Linear { terms, constant } => {
+                    let mut sum = F::zero();
+                    for (i, coeff) in terms.iter() {
+                        sum += *coeff * measurement[*i];
+                    }
+                    if sum != *constant {
+                        return Err(FlpError::Encode("linear check failed".to_string()));
+                    }
+                }
+            }
+        }
+
+        Ok(measurement.clone())
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        let mut gadgets: Vec<Box<dyn Gadget<F>>> = self
+            .range_gadgets
+            .iter()
+            .map(|(_, _, gadget)| Box::new(gadget.clone()) as Box<dyn Gadget<F>>)
+            .collect();
+        if let Some(gadget) = &self.mul_gadget {
+            gadgets.push(Box::new(gadget.clone()));
+        }
+        gadgets
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        let shares_inv = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+
+        // Take a random linear combination of the checks.
+        let mut outp = F::zero();
+        let mut r = joint_rand[0];
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Range { wires, .. } if wires.is_empty() => (),
+                Constraint::Range { wires, min, max } => {
+                    let idx = self.range_gadget(*min, *max);
+                    for x in input[wires.clone()].iter() {
+                        outp += r * g[idx].call(std::slice::from_ref(x))?;
+                        r *= joint_rand[0];
+                    }
+                }
+                Constraint::Product { a, b, c } => {
+                    let idx = self.mul_gadget();
+                    outp += r * (g[idx].call(&[input[*a], input[*b]])? - input[*c]);
+                    r *= joint_rand[0];
+                }
+                Constraint::Linear { terms, constant } => {
+                    let mut linear_check = -(*constant * shares_inv);
+                    for (i, coeff) in terms.iter() {
+                        linear_check += *coeff * input[*i];
+                    }
+                    outp += r * linear_check;
+                    r *= joint_rand[0];
+                }
+            }
+        }
+
+        Ok(outp)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(self
+            .output
+            .iter()
+            .flat_map(|wires| input[wires.clone()].iter().copied())
+            .collect())
+    }
+
+    fn input_len(&self) -> usize {
+        self.input_len
+    }
+
+    fn proof_len(&self) -> usize {
+        self.gadgets()
+            .map(|g| g.arity() + g.degree() * ((1 + g.calls()).next_power_of_two() - 1) + 1)
+            .sum()
+    }
+
+    fn verifier_len(&self) -> usize {
+        1 + self.gadgets().map(|g| g.arity() + 1).sum::<usize>()
+    }
+
+    fn output_len(&self) -> usize {
+        self.output
+            .iter()
+            .map(|wires| wires.end - wires.start)
+            .sum()
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        1
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        self.gadgets().map(|g| g.arity()).sum()
+    }
+
+    fn query_rand_len(&self) -> usize {
+        self.gadgets().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{random_vector, split_vector, Field64 as TestField};
+
+    // Number of shares to split input and proofs into in `run_circuit`.
+    const NUM_SHARES: usize = 3;
+
+    /// Proves and verifies the given input with secret-shared input and proof, and returns the
+    /// decision and the output.
+    fn run_circuit(
+        circuit: &Circuit<TestField>,
+        input: &[TestField],
+    ) -> Result<(bool, Vec<TestField>), FlpError> {
+        let joint_rand = random_vector(circuit.joint_rand_len()).unwrap();
+        let prove_rand = random_vector(circuit.prove_rand_len()).unwrap();
+        let query_rand = random_vector(circuit.query_rand_len()).unwrap();
+
+        let proof = circuit.prove(input, &prove_rand, &joint_rand)?;
+        assert_eq!(proof.len(), circuit.proof_len());
+
+        let input_shares = split_vector(input, NUM_SHARES).unwrap();
+        let proof_shares = split_vector(&proof, NUM_SHARES).unwrap();
+        let mut verifier = vec![TestField::zero(); circuit.verifier_len()];
+        let mut output = vec![TestField::zero(); circuit.output_len()];
+        for (input_share, proof_share) in input_shares.into_iter().zip(proof_shares) {
+            let verifier_share = circuit.query(
+                &input_share,
+                &proof_share,
+                &query_rand,
+                &joint_rand,
+                NUM_SHARES,
+            )?;
+            for (x, y) in verifier.iter_mut().zip(verifier_share) {
+                *x += y;
+            }
+
+            let output_share = circuit.truncate(input_share)?;
+            for (x, y) in output.iter_mut().zip(output_share) {
+                *x += y;
+            }
+        }
+
+        Ok((circuit.decide(&verifier)?, output))
+    }
+
+    fn field_vec(values: &[u64]) -> Vec<TestField> {
+        values.iter().map(|v| TestField::from(*v)).collect()
+    }
+
+    #[test]
+    fn test_circuit() {
+        // Each measurement is `(b_0, b_1, b_2, x, y, z)` where `b_i` is a bit, `x` is the integer
+        // with bit decomposition `b`, `y` is in `[3, 6)` and `z = x * y`.
+        let mut builder = CircuitBuilder::<TestField>::new();
+        let bits = builder.add_input(3);
+        let x = builder.add_input(1).start;
+        let y = builder.add_input(1).start;
+        let z = builder.add_input(1).start;
+        builder.bit_check(bits.clone());
+        builder.linear_check(
+            vec![
+                (bits.start, TestField::from(1)),
+                (bits.start + 1, TestField::from(2)),
+                (bits.start + 2, TestField::from(4)),
+                (x, -TestField::one()),
+            ],
+            TestField::zero(),
+        );
+        builder.range_check(y..y + 1, 3, 6);
+        builder.product_check(x, y, z);
+        builder.output(x..x + 1);
+        builder.output(z..z + 1);
+        let circuit = builder.build().unwrap();
+
+        assert_eq!(circuit.input_len(), 6);
+        assert_eq!(circuit.output_len(), 2);
+        assert_eq!(circuit.query_rand_len(), 3);
+        assert_eq!(circuit.prove_rand_len(), 1 + 1 + 2);
+
+        // Test on valid inputs.
+        for (x, y) in [(0, 3), (5, 4), (7, 5)] {
+            let measurement = field_vec(&[x & 1, (x >> 1) & 1, (x >> 2) & 1, x, y, x * y]);
+            let input = circuit.encode(&measurement).unwrap();
+            assert_eq!(
+                run_circuit(&circuit, &input).unwrap(),
+                (true, field_vec(&[x, x * y]))
+            );
+        }
+
+        // Test on invalid inputs. Each one violates exactly one of the constraints.
+        for input in [
+            [1, 1, 2, 11, 4, 44],
+            [1, 1, 1, 6, 4, 24],
+            [1, 1, 1, 7, 6, 42],
+            [1, 1, 1, 7, 4, 27],
+        ] {
+            let input = field_vec(&input);
+            circuit.encode(&input).unwrap_err();
+            assert!(!run_circuit(&circuit, &input).unwrap().0);
+        }
+
+        // Try encoding a measurement with the wrong length.
+        circuit.encode(&field_vec(&[0, 0, 0, 0, 3])).unwrap_err();
+    }
+
+    #[test]
+    fn test_circuit_without_gadgets() {
+        // Each measurement is a pair of elements that sum to 10. The output defaults to the entire
+        // input.
+        let mut builder = CircuitBuilder::<TestField>::new();
+        let wires = builder.add_input(2);
+        builder.linear_check(
+            wires.map(|i| (i, TestField::one())).collect(),
+            TestField::from(10),
+        );
+        let circuit = builder.build().unwrap();
+        assert_eq!(circuit.gadget().len(), 0);
+        assert_eq!(circuit.proof_len(), 0);
+
+        let input = circuit.encode(&field_vec(&[3, 7])).unwrap();
+        assert_eq!(
+            run_circuit(&circuit, &input).unwrap(),
+            (true, field_vec(&[3, 7]))
+        );
+        assert!(!run_circuit(&circuit, &field_vec(&[3, 8])).unwrap().0);
+    }
+
+    #[test]
+    fn test_circuit_shared_gadgets() {
+        // Range checks with the same range share a gadget.
+        let mut builder = CircuitBuilder::<TestField>::new();
+        let a = builder.add_input(2);
+        let b = builder.add_input(3);
+        builder.bit_check(a);
+        builder.range_check(b.clone(), 1, 4);
+        builder.bit_check(b.start..b.start + 1);
+        builder.range_check(b.end..b.end, 0, 5);
+        let circuit = builder.build().unwrap();
+        assert_eq!(circuit.gadget().len(), 2);
+        assert_eq!(circuit.gadget()[0].calls(), 3);
+        assert_eq!(circuit.gadget()[1].calls(), 3);
+
+        let input = circuit.encode(&field_vec(&[0, 1, 1, 3, 2])).unwrap();
+        assert!(run_circuit(&circuit, &input).unwrap().0);
+        assert!(
+            !run_circuit(&circuit, &field_vec(&[0, 1, 2, 3, 2]))
+                .unwrap()
+                .0
+        );
+    }
+
+    #[test]
+    fn test_circuit_builder_errors() {
+        let mut builder = CircuitBuilder::<TestField>::new();
+        builder.add_input(2);
+        builder.product_check(0, 1, 2);
+        builder.build().unwrap_err();
+
+        let mut builder = CircuitBuilder::<TestField>::new();
+        builder.add_input(2);
+        builder.bit_check(1..3);
+        builder.build().unwrap_err();
+
+        let mut builder = CircuitBuilder::<TestField>::new();
+        builder.add_input(2);
+        builder.range_check(0..2, 3, 3);
+        builder.build().unwrap_err();
+
+        let mut builder = CircuitBuilder::<TestField>::new();
+        builder.add_input(2);
+        builder.linear_check(vec![(2, TestField::one())], TestField::zero());
+        builder.build().unwrap_err();
+
+        let mut builder = CircuitBuilder::<TestField>::new();
+        builder.add_input(2);
+        builder.output(0..3);
+        builder.build().unwrap_err();
+    }
+}
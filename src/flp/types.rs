@@ -7,6 +7,7 @@ use crate::flp::gadgets::{BlindPolyEval, Mul, ParallelSumGadget, PolyEval};
 use crate::flp::{FlpError, Gadget, Type};
 use crate::polynomial::poly_range_check;
 
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::marker::PhantomData;
 
@@ -225,60 +226,96 @@ impl<F: FieldElement> Type for Sum<F> {
     }
 }
 
-/// The histogram type. Each measurement is a non-negative integer and the aggregate is a histogram
-/// approximating the distribution of the measurements.
+/// The bounded sum type. Each measurement is an integer in `[min, max]` for arbitrary bounds and
+/// the aggregate is the sum of the measurements.
+///
+/// The measurement is encoded as the bit decompositions of both `x - min` and `max - x`, followed
+/// by a "count" element that is equal to `1`. Let `bits` be the bit length of `max - min`. The
+/// validity circuit checks that each bit is `0` or `1` and that the two decompositions sum to
+/// `max - min`. Together these imply that `0 <= x - min <= max - min`. The count is used to add
+/// `min` back into the output share.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Histogram<F: FieldElement> {
-    buckets: Vec<F::Integer>,
+pub struct BoundedSum<F: FieldElement> {
+    min: F::Integer,
+    max: F::Integer,
+    bits: usize,
+    one: F::Integer,
     range_checker: Vec<F>,
 }
 
-impl<F: FieldElement> Histogram<F> {
-    /// Return a new [`Histogram`] type with the given buckets.
-    pub fn new(buckets: Vec<F::Integer>) -> Result<Self, FlpError> {
-        if buckets.len() >= u32::MAX as usize {
+impl<F: FieldElement> BoundedSum<F> {
+    /// Return a new [`BoundedSum`] type parameter. Each value of this type is an integer in range
+    /// `[min, max]`.
+    pub fn new(min: F::Integer, max: F::Integer) -> Result<Self, FlpError> {
+        if max < min {
             return Err(FlpError::Encode(
-                "invalid buckets: number of buckets exceeds maximum permitted".to_string(),
+                "invalid bounds: minimum exceeds maximum".to_string(),
             ));
         }
 
-        if !buckets.is_empty() {
-            for i in 0..buckets.len() - 1 {
-                if buckets[i + 1] <= buckets[i] {
-                    return Err(FlpError::Encode(
-                        "invalid buckets: out-of-order boundary".to_string(),
-                    ));
-                }
-            }
+        if max >= F::modulus() {
+            return Err(FlpError::Encode(
+                "invalid bounds: maximum exceeds field modulus".to_string(),
+            ));
+        }
+
+        let zero = F::Integer::from(F::zero());
+        let one = F::Integer::from(F::one());
+        let range = max - min;
+        let mut bits = 0;
+        while bits < 8 * F::ENCODED_SIZE && range >> F::Integer::try_from(bits).unwrap() != zero {
+            bits += 1;
+        }
+
+        // The sum of the two bit decompositions must not wrap around the field modulus.
+        if bits + 1 >= 8 * F::ENCODED_SIZE
+            || F::modulus() >> F::Integer::try_from(bits + 1).unwrap() == zero
+        {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) of range exceeds field modulus",
+                bits,
+            )));
         }
 
         Ok(Self {
-            buckets,
+            min,
+            max,
+            bits,
+            one,
             range_checker: poly_range_check(0, 2),
         })
     }
+
+    fn encode_bits(&self, value: F::Integer, encoded: &mut Vec<F>) {
+        for l in 0..self.bits {
+            let l = F::Integer::try_from(l).unwrap();
+            encoded.push(F::from((value >> l) & self.one));
+        }
+    }
 }
 
-impl<F: FieldElement> Type for Histogram<F> {
+impl<F: FieldElement> Type for BoundedSum<F> {
     type Measurement = F::Integer;
     type Field = F;
 
-    fn encode(&self, measurement: &F::Integer) -> Result<Vec<F>, FlpError> {
-        let mut data = vec![F::zero(); self.buckets.len() + 1];
-
-        let bucket = match self.buckets.binary_search(measurement) {
-            Ok(i) => i,  // on a bucket boundary
-            Err(i) => i, // smaller than the i-th bucket boundary
-        };
+    fn encode(&self, summand: &F::Integer) -> Result<Vec<F>, FlpError> {
+        if *summand < self.min || *summand > self.max {
+            return Err(FlpError::Encode(
+                "value of summand is out of bounds".to_string(),
+            ));
+        }
 
-        data[bucket] = F::one();
-        Ok(data)
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        self.encode_bits(*summand - self.min, &mut encoded);
+        self.encode_bits(self.max - *summand, &mut encoded);
+        encoded.push(F::one());
+        Ok(encoded)
     }
 
     fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
         vec![Box::new(PolyEval::new(
-            self.range_checker.to_vec(),
-            self.input_len(),
+            self.range_checker.clone(),
+            2 * self.bits,
         ))]
     }
 
@@ -290,37 +327,54 @@ impl<F: FieldElement> Type for Histogram<F> {
         num_shares: usize,
     ) -> Result<F, FlpError> {
         valid_call_check(self, input, joint_rand)?;
+        let shares_inv = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
 
-        // Check that each element of `data` is a 0 or 1.
+        // Check that each bit of both decompositions is a 0 or 1.
         let mut range_check = F::zero();
         let mut r = joint_rand[0];
-        for chunk in input.chunks(1) {
+        for chunk in input[..2 * self.bits].chunks(1) {
             range_check += r * g[0].call(chunk)?;
             r *= joint_rand[0];
         }
 
-        // Check that the elements of `data` sum to 1.
-        let mut sum_check = -(F::one() / F::from(F::Integer::try_from(num_shares).unwrap()));
-        for val in input.iter() {
-            sum_check += *val;
+        // Check that the decompositions sum to `max - min`.
+        let mut bound_check = -(F::from(self.max - self.min) * shares_inv);
+        let mut w = F::one();
+        for (lower, upper) in input[..self.bits]
+            .iter()
+            .zip(input[self.bits..2 * self.bits].iter())
+        {
+            bound_check += w * (*lower + *upper);
+            w += w;
         }
 
-        // Take a random linear combination of both checks.
-        let out = joint_rand[1] * range_check + (joint_rand[1] * joint_rand[1]) * sum_check;
-        Ok(out)
+        // Check that the count is equal to 1.
+        let count_check = input[2 * self.bits] - shares_inv;
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        let r2 = r * r;
+        Ok(r * range_check + r2 * bound_check + (r2 * r) * count_check)
     }
 
     fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
         truncate_call_check(self, &input)?;
-        Ok(input)
+
+        let mut decoded = F::from(self.min) * input[2 * self.bits];
+        let mut w = F::one();
+        for bit in input[..self.bits].iter() {
+            decoded += w * *bit;
+            w += w;
+        }
+        Ok(vec![decoded])
     }
 
     fn input_len(&self) -> usize {
-        self.buckets.len() + 1
+        2 * self.bits + 1
     }
 
     fn proof_len(&self) -> usize {
-        2 * ((1 + self.input_len()).next_power_of_two() - 1) + 2
+        2 * ((1 + 2 * self.bits).next_power_of_two() - 1) + 2
     }
 
     fn verifier_len(&self) -> usize {
@@ -328,7 +382,7 @@ impl<F: FieldElement> Type for Histogram<F> {
     }
 
     fn output_len(&self) -> usize {
-        self.input_len()
+        1
     }
 
     fn joint_rand_len(&self) -> usize {
@@ -344,109 +398,70 @@ impl<F: FieldElement> Type for Histogram<F> {
     }
 }
 
-fn valid_call_check<T: Type>(
-    typ: &T,
-    input: &[T::Field],
-    joint_rand: &[T::Field],
-) -> Result<(), FlpError> {
-    if input.len() != typ.input_len() {
-        return Err(FlpError::Valid(format!(
-            "unexpected input length: got {}; want {}",
-            input.len(),
-            typ.input_len(),
-        )));
-    }
-
-    if joint_rand.len() != typ.joint_rand_len() {
-        return Err(FlpError::Valid(format!(
-            "unexpected joint randomness length: got {}; want {}",
-            joint_rand.len(),
-            typ.joint_rand_len()
-        )));
-    }
-
-    Ok(())
-}
-
-/// A sequence of counters. This type uses a neat trick from [[BBCG+19], Corollary 4.9] to reduce
-/// the proof size to roughly the square root of the input size.
+/// The signed sum type. Each measurement is an integer in `[-2^(bits-1), 2^(bits-1))` and the
+/// aggregate is the sum of the measurements.
 ///
-/// [BBCG+19]: https://eprint.iacr.org/2019/188
-#[derive(Debug, PartialEq, Eq)]
-pub struct CountVec<F, S> {
+/// The measurement `x` is encoded as the bit decomposition of `x + 2^(bits-1)`, followed by a
+/// "count" element that is equal to `1`. The validity circuit checks that each bit is `0` or `1`
+/// and that the count is equal to `1`. The output consists of the sum of the offset measurements
+/// and the offset `2^(bits-1)` times the count, so that the offset can be removed from the
+/// aggregate no matter how many measurements were aggregated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedSum<F: FieldElement> {
+    bits: usize,
     range_checker: Vec<F>,
-    len: usize,
-    chunk_len: usize,
-    gadget_calls: usize,
-    phantom: PhantomData<S>,
 }
 
-impl<F: FieldElement, S: ParallelSumGadget<F, BlindPolyEval<F>>> CountVec<F, S> {
-    /// Returns a new [`CountVec`] with the given length.
-    pub fn new(len: usize) -> Self {
-        // The optimal chunk length is the square root of the input length. If the input length is
-        // not a perfect square, then round down. If the result is 0, then let the chunk length be
-        // 1 so that the underlying gadget can still be called.
-        let chunk_len = std::cmp::max(1, (len as f64).sqrt() as usize);
-
-        let mut gadget_calls = len / chunk_len;
-        if len % chunk_len != 0 {
-            gadget_calls += 1;
+impl<F: FieldElement> SignedSum<F> {
+    /// Return a new [`SignedSum`] type parameter. Each value of this type is an integer in range
+    /// `[-2^(bits-1), 2^(bits-1))`.
+    pub fn new(bits: usize) -> Result<Self, FlpError> {
+        if bits == 0 || bits > 64 {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) must be in [1, 64]",
+                bits
+            )));
         }
 
-        Self {
-            range_checker: poly_range_check(0, 2),
-            len,
-            chunk_len,
-            gadget_calls,
-            phantom: PhantomData,
+        if bits >= 8 * F::ENCODED_SIZE
+            || F::modulus() >> F::Integer::try_from(bits).unwrap() == F::Integer::from(F::zero())
+        {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) exceeds field modulus",
+                bits,
+            )));
         }
-    }
-}
 
-impl<F: FieldElement, S> Clone for CountVec<F, S> {
-    fn clone(&self) -> Self {
-        Self {
-            range_checker: self.range_checker.clone(),
-            len: self.len,
-            chunk_len: self.chunk_len,
-            gadget_calls: self.gadget_calls,
-            phantom: PhantomData,
-        }
+        Ok(Self {
+            bits,
+            range_checker: poly_range_check(0, 2),
+        })
     }
 }
 
-impl<F, S> Type for CountVec<F, S>
-where
-    F: FieldElement,
-    S: ParallelSumGadget<F, BlindPolyEval<F>> + Eq + 'static,
-{
-    type Measurement = Vec<F::Integer>;
+impl<F: FieldElement> Type for SignedSum<F> {
+    type Measurement = i64;
     type Field = F;
 
-    fn encode(&self, measurement: &Vec<F::Integer>) -> Result<Vec<F>, FlpError> {
-        if measurement.len() != self.len {
-            return Err(FlpError::Encode(format!(
-                "unexpected measurement length: got {}; want {}",
-                measurement.len(),
-                self.len
-            )));
-        }
-
-        let max = F::Integer::from(F::one());
-        for value in measurement {
-            if *value > max {
-                return Err(FlpError::Encode("Count value must be 0 or 1".to_string()));
-            }
+    fn encode(&self, summand: &i64) -> Result<Vec<F>, FlpError> {
+        let offset = 1_i128 << (self.bits - 1);
+        let summand = i128::from(*summand);
+        if summand < -offset || summand >= offset {
+            return Err(FlpError::Encode(
+                "value of summand exceeds bit length".to_string(),
+            ));
         }
 
-        Ok(measurement.iter().map(|value| F::from(*value)).collect())
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        push_bits(&mut encoded, (summand + offset) as u128, self.bits);
+        encoded.push(F::one());
+        Ok(encoded)
     }
 
     fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
-        vec![Box::new(S::new(
-            BlindPolyEval::new(self.range_checker.clone(), self.gadget_calls),
-            self.chunk_len,
+        vec![Box::new(PolyEval::new(
+            self.range_checker.clone(),
+            self.bits,
         ))]
     }
 
@@ -458,58 +473,54 @@ where
         num_shares: usize,
     ) -> Result<F, FlpError> {
         valid_call_check(self, input, joint_rand)?;
+        let shares_inv = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
 
-        let s = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+        // Check that each bit is a 0 or 1.
+        let mut range_check = F::zero();
         let mut r = joint_rand[0];
-        let mut outp = F::zero();
-        let mut padded_chunk = vec![F::zero(); 2 * self.chunk_len];
-        for chunk in input.chunks(self.chunk_len) {
-            let d = chunk.len();
-            for i in 0..self.chunk_len {
-                if i < d {
-                    padded_chunk[2 * i] = chunk[i];
-                } else {
-                    // If the chunk is smaller than the chunk length, then copy the last element of
-                    // the chunk into the remaining slots.
-                    padded_chunk[2 * i] = chunk[d - 1];
-                }
-                padded_chunk[2 * i + 1] = r * s;
-                r *= joint_rand[0];
-            }
-
-            outp += g[0].call(&padded_chunk)?;
+        for chunk in input[..self.bits].chunks(1) {
+            range_check += r * g[0].call(chunk)?;
+            r *= joint_rand[0];
         }
 
-        Ok(outp)
+        // Check that the count is equal to 1.
+        let count_check = input[self.bits] - shares_inv;
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        Ok(r * range_check + (r * r) * count_check)
     }
 
     fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
         truncate_call_check(self, &input)?;
-        Ok(input)
+        Ok(vec![
+            decode_bits(&input[..self.bits]),
+            pow2::<F>(self.bits - 1) * input[self.bits],
+        ])
     }
 
     fn input_len(&self) -> usize {
-        self.len
+        self.bits + 1
     }
 
     fn proof_len(&self) -> usize {
-        (self.chunk_len * 2) + 3 * ((1 + self.gadget_calls).next_power_of_two() - 1) + 1
+        2 * ((1 + self.bits).next_power_of_two() - 1) + 2
     }
 
     fn verifier_len(&self) -> usize {
-        2 + self.chunk_len * 2
+        3
     }
 
     fn output_len(&self) -> usize {
-        self.len
+        2
     }
 
     fn joint_rand_len(&self) -> usize {
-        1
+        2
     }
 
     fn prove_rand_len(&self) -> usize {
-        self.chunk_len * 2
+        1
     }
 
     fn query_rand_len(&self) -> usize {
@@ -517,65 +528,2209 @@ where
     }
 }
 
-fn truncate_call_check<T: Type>(typ: &T, input: &[T::Field]) -> Result<(), FlpError> {
-    if input.len() != typ.input_len() {
-        return Err(FlpError::Truncate(format!(
-            "Unexpected input length: got {}; want {}",
-            input.len(),
-            typ.input_len()
-        )));
-    }
-
-    Ok(())
+/// The mean-and-variance type. Each measurement is an integer `x` in `[0, 2^bits)`. The aggregate
+/// is the number of measurements, the sum of the measurements and the sum of their squares, from
+/// which the mean and variance can be computed.
+///
+/// The measurement is encoded as the bit decomposition of `x`, followed by `x^2` and a "count"
+/// element that is equal to `1`. The validity circuit uses the [`Mul`] gadget both to check that
+/// each bit is `0` or `1` and that the second-to-last element is the square of `x`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeanVariance<F: FieldElement> {
+    bits: usize,
+    one: F::Integer,
+    max_summand: F::Integer,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::field::{random_vector, split_vector, Field64 as TestField};
-    use crate::flp::gadgets::ParallelSum;
-    #[cfg(feature = "multithreaded")]
-    use crate::flp::gadgets::ParallelSumMultithreaded;
+impl<F: FieldElement> MeanVariance<F> {
+    /// Return a new [`MeanVariance`] type parameter. Each value of this type is an integer in
+    /// range `[0, 2^bits)`.
+    pub fn new(bits: usize) -> Result<Self, FlpError> {
+        let zero = F::Integer::from(F::zero());
 
-    // Number of shares to split input and proofs into in `flp_test`.
-    const NUM_SHARES: usize = 3;
+        // The square of each measurement must not wrap around the field modulus.
+        if 2 * bits >= 8 * F::ENCODED_SIZE
+            || F::modulus() >> F::Integer::try_from(2 * bits).unwrap() == zero
+        {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) of squared measurement exceeds field modulus",
+                2 * bits,
+            )));
+        }
 
-    struct ValidityTestCase<F> {
-        expect_valid: bool,
-        expected_output: Option<Vec<F>>,
+        let one = F::Integer::from(F::one());
+        let max_summand = (one << F::Integer::try_from(bits).unwrap()) - one;
+
+        Ok(Self {
+            bits,
+            one,
+            max_summand,
+        })
     }
+}
 
-    #[test]
-    fn test_count() {
-        let count: Count<TestField> = Count::new();
-        let zero = TestField::zero();
-        let one = TestField::one();
+impl<F: FieldElement> Type for MeanVariance<F> {
+    type Measurement = F::Integer;
+    type Field = F;
 
-        // Test FLP on valid input.
-        flp_validity_test(
-            &count,
-            &count.encode(&1).unwrap(),
-            &ValidityTestCase::<TestField> {
-                expect_valid: true,
-                expected_output: Some(vec![one]),
-            },
-        )
-        .unwrap();
+    fn encode(&self, summand: &F::Integer) -> Result<Vec<F>, FlpError> {
+        if *summand > self.max_summand {
+            return Err(FlpError::Encode(
+                "value of summand exceeds bit length".to_string(),
+            ));
+        }
 
-        flp_validity_test(
-            &count,
-            &count.encode(&0).unwrap(),
-            &ValidityTestCase::<TestField> {
-                expect_valid: true,
-                expected_output: Some(vec![zero]),
-            },
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        for l in 0..self.bits {
+            let l = F::Integer::try_from(l).unwrap();
+            encoded.push(F::from((*summand >> l) & self.one));
+        }
+
+        let x = F::from(*summand);
+        encoded.push(x * x);
+        encoded.push(F::one());
+        Ok(encoded)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(Mul::new(self.bits + 1))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+
+        // Check that each bit of `x` is a 0 or 1 and recover `x` from its bits.
+        let mut range_check = F::zero();
+        let mut r = joint_rand[0];
+        let mut x = F::zero();
+        let mut w = F::one();
+        for bit in input[..self.bits].iter() {
+            range_check += r * (g[0].call(&[*bit, *bit])? - *bit);
+            r *= joint_rand[0];
+            x += w * *bit;
+            w += w;
+        }
+
+        // Check that the second-to-last element is the square of `x`.
+        let square_check = g[0].call(&[x, x])? - input[self.bits];
+
+        // Check that the count is equal to 1.
+        let count_check =
+            input[self.bits + 1] - F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        let r2 = r * r;
+        Ok(r * range_check + r2 * square_check + (r2 * r) * count_check)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+
+        let mut x = F::zero();
+        let mut w = F::one();
+        for bit in input[..self.bits].iter() {
+            x += w * *bit;
+            w += w;
+        }
+        Ok(vec![x, input[self.bits], input[self.bits + 1]])
+    }
+
+    fn input_len(&self) -> usize {
+        self.bits + 2
+    }
+
+    fn proof_len(&self) -> usize {
+        2 * ((1 + self.bits + 1).next_power_of_two() - 1) + 3
+    }
+
+    fn verifier_len(&self) -> usize {
+        4
+    }
+
+    fn output_len(&self) -> usize {
+        3
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+/// The weighted sum type. Each measurement is a pair `(x, w)` of a value `x` in `[0, 2^value_bits)`
+/// and a weight `w` in `[0, 2^weight_bits)`. The aggregate is the sum of `w * x` and the sum of the
+/// weights, from which the weighted mean can be computed.
+///
+/// The measurement is encoded as the bit decompositions of `x` and `w`, followed by the product
+/// `w * x`. Unlike the other types in this module, the validity circuit uses two different gadgets:
+/// the [`BlindPolyEval`] gadget checks that each bit is `0` or `1` and the [`Mul`] gadget checks
+/// that the last element is the product of `x` and `w`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WeightedSum<F: FieldElement> {
+    value_bits: usize,
+    weight_bits: usize,
+    range_checker: Vec<F>,
+}
+
+impl<F: FieldElement> WeightedSum<F> {
+    /// Return a new [`WeightedSum`] type parameter. Each value is an integer in range
+    /// `[0, 2^value_bits)` and each weight is an integer in range `[0, 2^weight_bits)`.
+    pub fn new(value_bits: usize, weight_bits: usize) -> Result<Self, FlpError> {
+        if value_bits == 0 || weight_bits == 0 {
+            return Err(FlpError::Encode(
+                "bit lengths of value and weight must be positive".to_string(),
+            ));
+        }
+
+        // The product of the value and weight must not wrap around the field modulus.
+        let bits = value_bits + weight_bits;
+        if bits >= 8 * F::ENCODED_SIZE
+            || F::modulus() >> F::Integer::try_from(bits).unwrap() == F::Integer::from(F::zero())
+        {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) of weighted value exceeds field modulus",
+                bits,
+            )));
+        }
+
+        Ok(Self {
+            value_bits,
+            weight_bits,
+            range_checker: poly_range_check(0, 2),
+        })
+    }
+
+    fn range_bits(&self) -> usize {
+        self.value_bits + self.weight_bits
+    }
+}
+
+impl<F: FieldElement> Type for WeightedSum<F> {
+    type Measurement = (F::Integer, F::Integer);
+    type Field = F;
+
+    fn encode(&self, measurement: &(F::Integer, F::Integer)) -> Result<Vec<F>, FlpError> {
+        let (value, weight) = *measurement;
+        let one = F::Integer::from(F::one());
+        for (x, bits) in [(value, self.value_bits), (weight, self.weight_bits)] {
+            if x > (one << F::Integer::try_from(bits).unwrap()) - one {
+                return Err(FlpError::Encode(
+                    "value or weight exceeds bit length".to_string(),
+                ));
+            }
+        }
+
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        for (x, bits) in [(value, self.value_bits), (weight, self.weight_bits)] {
+            for l in 0..bits {
+                let l = F::Integer::try_from(l).unwrap();
+                encoded.push(F::from((x >> l) & one));
+            }
+        }
+        encoded.push(F::from(value) * F::from(weight));
+        Ok(encoded)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![
+            Box::new(BlindPolyEval::new(
+                self.range_checker.clone(),
+                self.range_bits(),
+            )),
+            Box::new(Mul::new(1)),
+        ]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        let s = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+
+        // Check that each bit of the value and weight is a 0 or 1.
+        let mut range_check = F::zero();
+        let mut r = joint_rand[0];
+        for bit in input[..self.range_bits()].iter() {
+            range_check += g[0].call(&[*bit, r * s])?;
+            r *= joint_rand[0];
+        }
+
+        // Check that the last element is the product of the value and weight.
+        let value = decode_bits(&input[..self.value_bits]);
+        let weight = decode_bits(&input[self.value_bits..self.range_bits()]);
+        let product_check = g[1].call(&[value, weight])? - input[self.range_bits()];
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        Ok(r * range_check + (r * r) * product_check)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(vec![
+            input[self.range_bits()],
+            decode_bits(&input[self.value_bits..self.range_bits()]),
+        ])
+    }
+
+    fn input_len(&self) -> usize {
+        self.range_bits() + 1
+    }
+
+    fn proof_len(&self) -> usize {
+        let range_proof_len = 2 + 3 * ((1 + self.range_bits()).next_power_of_two() - 1) + 1;
+        let product_proof_len = 2 + 2 * ((1 + 1_usize).next_power_of_two() - 1) + 1;
+        range_proof_len + product_proof_len
+    }
+
+    fn verifier_len(&self) -> usize {
+        1 + (2 + 1) + (2 + 1)
+    }
+
+    fn output_len(&self) -> usize {
+        2
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        2 + 2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        2
+    }
+}
+
+/// The histogram type. Each measurement is a non-negative integer and the aggregate is a histogram
+/// approximating the distribution of the measurements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram<F: FieldElement> {
+    buckets: Vec<F::Integer>,
+    range_checker: Vec<F>,
+}
+
+impl<F: FieldElement> Histogram<F> {
+    /// Return a new [`Histogram`] type with the given buckets.
+    pub fn new(buckets: Vec<F::Integer>) -> Result<Self, FlpError> {
+        if buckets.len() >= u32::MAX as usize {
+            return Err(FlpError::Encode(
+                "invalid buckets: number of buckets exceeds maximum permitted".to_string(),
+            ));
+        }
+
+        if !buckets.is_empty() {
+            for i in 0..buckets.len() - 1 {
+                if buckets[i + 1] <= buckets[i] {
+                    return Err(FlpError::Encode(
+                        "invalid buckets: out-of-order boundary".to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            buckets,
+            range_checker: poly_range_check(0, 2),
+        })
+    }
+}
+
+impl<F: FieldElement> Type for Histogram<F> {
+    type Measurement = F::Integer;
+    type Field = F;
+
+    fn encode(&self, measurement: &F::Integer) -> Result<Vec<F>, FlpError> {
+        let mut data = vec![F::zero(); self.buckets.len() + 1];
+
+        let bucket = match self.buckets.binary_search(measurement) {
+            Ok(i) => i,  // on a bucket boundary
+            Err(i) => i, // smaller than the i-th bucket boundary
+        };
+
+        data[bucket] = F::one();
+        Ok(data)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(PolyEval::new(
+            self.range_checker.to_vec(),
+            self.input_len(),
+        ))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        one_hot_valid(g, input, joint_rand, num_shares)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(input)
+    }
+
+    fn input_len(&self) -> usize {
+        self.buckets.len() + 1
+    }
+
+    fn proof_len(&self) -> usize {
+        2 * ((1 + self.input_len()).next_power_of_two() - 1) + 2
+    }
+
+    fn verifier_len(&self) -> usize {
+        3
+    }
+
+    fn output_len(&self) -> usize {
+        self.input_len()
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        1
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+/// The categorical histogram type. Unlike [`Histogram`], the buckets are a list of labels, such as
+/// browser names or error codes, rather than numeric boundaries. Each measurement is the index of
+/// a bucket and the aggregate is the number of measurements in each bucket.
+///
+/// The measurement is encoded as a one-hot vector. As in [`Histogram`], the validity circuit
+/// checks that each element is `0` or `1` and that the elements sum to `1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CategoricalHistogram<F: FieldElement> {
+    labels: Vec<String>,
+    range_checker: Vec<F>,
+}
+
+impl<F: FieldElement> CategoricalHistogram<F> {
+    /// Return a new [`CategoricalHistogram`] type with the given bucket labels.
+    pub fn new(labels: Vec<String>) -> Result<Self, FlpError> {
+        if labels.is_empty() {
+            return Err(FlpError::Encode(
+                "invalid buckets: at least one label is required".to_string(),
+            ));
+        }
+
+        if labels.len() >= u32::MAX as usize {
+            return Err(FlpError::Encode(
+                "invalid buckets: number of buckets exceeds maximum permitted".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::with_capacity(labels.len());
+        if let Some(label) = labels.iter().find(|label| !seen.insert(*label)) {
+            return Err(FlpError::Encode(format!(
+                "invalid buckets: duplicate label {:?}",
+                label
+            )));
+        }
+
+        Ok(Self {
+            labels,
+            range_checker: poly_range_check(0, 2),
+        })
+    }
+
+    /// Returns the bucket labels.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Returns the index of the bucket with the given label, if any.
+    pub fn bucket_index(&self, label: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l == label)
+    }
+}
+
+impl<F: FieldElement> Type for CategoricalHistogram<F> {
+    type Measurement = usize;
+    type Field = F;
+
+    fn encode(&self, measurement: &usize) -> Result<Vec<F>, FlpError> {
+        if *measurement >= self.labels.len() {
+            return Err(FlpError::Encode(format!(
+                "bucket index ({}) exceeds number of buckets ({})",
+                measurement,
+                self.labels.len()
+            )));
+        }
+
+        let mut data = vec![F::zero(); self.labels.len()];
+        data[*measurement] = F::one();
+        Ok(data)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(PolyEval::new(
+            self.range_checker.to_vec(),
+            self.input_len(),
+        ))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        one_hot_valid(g, input, joint_rand, num_shares)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(input)
+    }
+
+    fn input_len(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn proof_len(&self) -> usize {
+        2 * ((1 + self.input_len()).next_power_of_two() - 1) + 2
+    }
+
+    fn verifier_len(&self) -> usize {
+        3
+    }
+
+    fn output_len(&self) -> usize {
+        self.input_len()
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        1
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+pub(crate) fn valid_call_check<T: Type>(
+    typ: &T,
+    input: &[T::Field],
+    joint_rand: &[T::Field],
+) -> Result<(), FlpError> {
+    if input.len() != typ.input_len() {
+        return Err(FlpError::Valid(format!(
+            "unexpected input length: got {}; want {}",
+            input.len(),
+            typ.input_len(),
+        )));
+    }
+
+    if joint_rand.len() != typ.joint_rand_len() {
+        return Err(FlpError::Valid(format!(
+            "unexpected joint randomness length: got {}; want {}",
+            joint_rand.len(),
+            typ.joint_rand_len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// A sequence of counters. This type uses a neat trick from [[BBCG+19], Corollary 4.9] to reduce
+/// the proof size to roughly the square root of the input size.
+///
+/// [BBCG+19]: https://eprint.iacr.org/2019/188
+#[derive(Debug, PartialEq, Eq)]
+pub struct CountVec<F, S> {
+    range_checker: Vec<F>,
+    len: usize,
+    chunk_len: usize,
+    gadget_calls: usize,
+    phantom: PhantomData<S>,
+}
+
+impl<F: FieldElement, S: ParallelSumGadget<F, BlindPolyEval<F>>> CountVec<F, S> {
+    /// Returns a new [`CountVec`] with the given length.
+    pub fn new(len: usize) -> Self {
+        let (chunk_len, gadget_calls) = parallel_sum_params(len);
+
+        Self {
+            range_checker: poly_range_check(0, 2),
+            len,
+            chunk_len,
+            gadget_calls,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: FieldElement, S> Clone for CountVec<F, S> {
+    fn clone(&self) -> Self {
+        Self {
+            range_checker: self.range_checker.clone(),
+            len: self.len,
+            chunk_len: self.chunk_len,
+            gadget_calls: self.gadget_calls,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, S> Type for CountVec<F, S>
+where
+    F: FieldElement,
+    S: ParallelSumGadget<F, BlindPolyEval<F>> + Eq + 'static,
+{
+    type Measurement = Vec<F::Integer>;
+    type Field = F;
+
+    fn encode(&self, measurement: &Vec<F::Integer>) -> Result<Vec<F>, FlpError> {
+        if measurement.len() != self.len {
+            return Err(FlpError::Encode(format!(
+                "unexpected measurement length: got {}; want {}",
+                measurement.len(),
+                self.len
+            )));
+        }
+
+        let max = F::Integer::from(F::one());
+        for value in measurement {
+            if *value > max {
+                return Err(FlpError::Encode("Count value must be 0 or 1".to_string()));
+            }
+        }
+
+        Ok(measurement.iter().map(|value| F::from(*value)).collect())
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(S::new(
+            BlindPolyEval::new(self.range_checker.clone(), self.gadget_calls),
+            self.chunk_len,
+        ))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        parallel_range_check(&mut g[0], input, self.chunk_len, joint_rand[0], num_shares)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(input)
+    }
+
+    fn input_len(&self) -> usize {
+        self.len
+    }
+
+    fn proof_len(&self) -> usize {
+        (self.chunk_len * 2) + 3 * ((1 + self.gadget_calls).next_power_of_two() - 1) + 1
+    }
+
+    fn verifier_len(&self) -> usize {
+        2 + self.chunk_len * 2
+    }
+
+    fn output_len(&self) -> usize {
+        self.len
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        1
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        self.chunk_len * 2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+/// A sequence of counters with a bound on the number of counters that are set, i.e., a "k-hot"
+/// vector. Each measurement is a vector of `len` integers in `[0, 2)` whose Hamming weight is at
+/// most (or exactly) `weight`. The aggregate is the element-wise sum.
+///
+/// If the weight is an upper bound, then the entries are followed by the bit decomposition of the
+/// slack `weight - w`, where `w` is the Hamming weight of the measurement. The validity circuit
+/// checks that every entry and every bit of the slack is `0` or `1` using the same construction as
+/// [`CountVec`], and that the sum of the entries and the slack is equal to `weight`. If the weight
+/// is exact, then there is no slack and the sum of the entries is checked against `weight`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CountVecWithWeight<F, S> {
+    range_checker: Vec<F>,
+    len: usize,
+    weight: usize,
+    weight_bits: usize,
+    range_bits: usize,
+    chunk_len: usize,
+    gadget_calls: usize,
+    phantom: PhantomData<S>,
+}
+
+impl<F: FieldElement, S: ParallelSumGadget<F, BlindPolyEval<F>>> CountVecWithWeight<F, S> {
+    /// Returns a new [`CountVecWithWeight`] with the given length whose Hamming weight is at most
+    /// `weight`.
+    pub fn new(len: usize, weight: usize) -> Result<Self, FlpError> {
+        let weight_bits = (usize::BITS - weight.leading_zeros()) as usize;
+        Self::with_weight_bits(len, weight, weight_bits)
+    }
+
+    /// Returns a new [`CountVecWithWeight`] with the given length whose Hamming weight is exactly
+    /// `weight`.
+    pub fn new_exact(len: usize, weight: usize) -> Result<Self, FlpError> {
+        Self::with_weight_bits(len, weight, 0)
+    }
+
+    fn with_weight_bits(len: usize, weight: usize, weight_bits: usize) -> Result<Self, FlpError> {
+        if weight > len {
+            return Err(FlpError::Encode(format!(
+                "weight ({}) exceeds length ({})",
+                weight, len
+            )));
+        }
+
+        // The sum of the entries and the slack must not wrap around the field modulus. The slack
+        // is less than `2 * weight + 1`.
+        let max_sum = weight
+            .checked_mul(2)
+            .and_then(|w| w.checked_add(1))
+            .and_then(|slack| len.checked_add(slack))
+            .and_then(|max_sum| F::Integer::try_from(max_sum).ok());
+        if max_sum.map_or(true, |max_sum| max_sum >= F::modulus()) {
+            return Err(FlpError::Encode(format!(
+                "length ({}) exceeds field modulus",
+                len
+            )));
+        }
+
+        let range_bits = len + weight_bits;
+        let (chunk_len, gadget_calls) = parallel_sum_params(range_bits);
+
+        Ok(Self {
+            range_checker: poly_range_check(0, 2),
+            len,
+            weight,
+            weight_bits,
+            range_bits,
+            chunk_len,
+            gadget_calls,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<F: FieldElement, S> Clone for CountVecWithWeight<F, S> {
+    fn clone(&self) -> Self {
+        Self {
+            range_checker: self.range_checker.clone(),
+            len: self.len,
+            weight: self.weight,
+            weight_bits: self.weight_bits,
+            range_bits: self.range_bits,
+            chunk_len: self.chunk_len,
+            gadget_calls: self.gadget_calls,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, S> Type for CountVecWithWeight<F, S>
+where
+    F: FieldElement,
+    S: ParallelSumGadget<F, BlindPolyEval<F>> + Eq + 'static,
+{
+    type Measurement = Vec<F::Integer>;
+    type Field = F;
+
+    fn encode(&self, measurement: &Vec<F::Integer>) -> Result<Vec<F>, FlpError> {
+        if measurement.len() != self.len {
+            return Err(FlpError::Encode(format!(
+                "unexpected measurement length: got {}; want {}",
+                measurement.len(),
+                self.len
+            )));
+        }
+
+        let zero = F::Integer::from(F::zero());
+        let one = F::Integer::from(F::one());
+        let mut weight = 0;
+        for value in measurement {
+            if *value > one {
+                return Err(FlpError::Encode("Count value must be 0 or 1".to_string()));
+            }
+            if *value != zero {
+                weight += 1;
+            }
+        }
+
+        if weight > self.weight || (self.weight_bits == 0 && weight != self.weight) {
+            return Err(FlpError::Encode(format!(
+                "Hamming weight of measurement ({}) does not match the bound ({})",
+                weight, self.weight
+            )));
+        }
+
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        encoded.extend(measurement.iter().map(|value| F::from(*value)));
+        push_bits(
+            &mut encoded,
+            (self.weight - weight) as u128,
+            self.weight_bits,
+        );
+        Ok(encoded)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(S::new(
+            BlindPolyEval::new(self.range_checker.clone(), self.gadget_calls),
+            self.chunk_len,
+        ))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        let s = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+
+        // Check that each entry and each bit of the slack is a 0 or 1.
+        let range_check =
+            parallel_range_check(&mut g[0], input, self.chunk_len, joint_rand[0], num_shares)?;
+
+        // Check that the Hamming weight plus the slack is equal to the weight.
+        let weight = F::from(F::Integer::try_from(self.weight).unwrap());
+        let mut weight_check = decode_bits(&input[self.len..]) - weight * s;
+        for entry in input[..self.len].iter() {
+            weight_check += *entry;
+        }
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        Ok(r * range_check + (r * r) * weight_check)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+        Ok(input[..self.len].to_vec())
+    }
+
+    fn input_len(&self) -> usize {
+        self.range_bits
+    }
+
+    fn proof_len(&self) -> usize {
+        (self.chunk_len * 2) + 3 * ((1 + self.gadget_calls).next_power_of_two() - 1) + 1
+    }
+
+    fn verifier_len(&self) -> usize {
+        2 + self.chunk_len * 2
+    }
+
+    fn output_len(&self) -> usize {
+        self.len
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        self.chunk_len * 2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+/// A vector of summands. Each measurement is a vector of `len` integers in `[0, 2^bits)` and the
+/// aggregate is the element-wise sum. Each entry is bit-decomposed and the range of every bit is
+/// checked using the same construction as [`CountVec`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct SumVec<F: FieldElement, S> {
+    range_checker: Vec<F>,
+    len: usize,
+    bits: usize,
+    flattened_len: usize,
+    one: F::Integer,
+    max_summand: F::Integer,
+    chunk_len: usize,
+    gadget_calls: usize,
+    phantom: PhantomData<S>,
+}
+
+impl<F: FieldElement, S: ParallelSumGadget<F, BlindPolyEval<F>>> SumVec<F, S> {
+    /// Returns a new [`SumVec`] with the given length and bit length of each entry.
+    pub fn new(bits: usize, len: usize) -> Result<Self, FlpError> {
+        let bits_int = F::Integer::try_from(bits).map_err(|err| {
+            FlpError::Encode(format!(
+                "bit length ({}) cannot be represented as a field element: {:?}",
+                bits, err,
+            ))
+        })?;
+
+        if bits >= 8 * F::ENCODED_SIZE || F::modulus() >> bits_int == F::Integer::from(F::zero()) {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) exceeds field modulus",
+                bits,
+            )));
+        }
+
+        let flattened_len = bits.checked_mul(len).ok_or_else(|| {
+            FlpError::Encode("length of the encoded measurement overflows".to_string())
+        })?;
+
+        let (chunk_len, gadget_calls) = parallel_sum_params(flattened_len);
+
+        let one = F::Integer::from(F::one());
+        let max_summand = (one << bits_int) - one;
+
+        Ok(Self {
+            range_checker: poly_range_check(0, 2),
+            len,
+            bits,
+            flattened_len,
+            one,
+            max_summand,
+            chunk_len,
+            gadget_calls,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<F: FieldElement, S> Clone for SumVec<F, S> {
+    fn clone(&self) -> Self {
+        Self {
+            range_checker: self.range_checker.clone(),
+            len: self.len,
+            bits: self.bits,
+            flattened_len: self.flattened_len,
+            one: self.one,
+            max_summand: self.max_summand,
+            chunk_len: self.chunk_len,
+            gadget_calls: self.gadget_calls,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, S> Type for SumVec<F, S>
+where
+    F: FieldElement,
+    S: ParallelSumGadget<F, BlindPolyEval<F>> + Eq + 'static,
+{
+    type Measurement = Vec<F::Integer>;
+    type Field = F;
+
+    fn encode(&self, measurement: &Vec<F::Integer>) -> Result<Vec<F>, FlpError> {
+        if measurement.len() != self.len {
+            return Err(FlpError::Encode(format!(
+                "unexpected measurement length: got {}; want {}",
+                measurement.len(),
+                self.len
+            )));
+        }
+
+        let mut encoded: Vec<F> = Vec::with_capacity(self.flattened_len);
+        for summand in measurement {
+            if *summand > self.max_summand {
+                return Err(FlpError::Encode(
+                    "value of summand exceeds bit length".to_string(),
+                ));
+            }
+
+            for l in 0..self.bits {
+                let l = F::Integer::try_from(l).unwrap();
+                encoded.push(F::from((*summand >> l) & self.one));
+            }
+        }
+
+        Ok(encoded)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![Box::new(S::new(
+            BlindPolyEval::new(self.range_checker.clone(), self.gadget_calls),
+            self.chunk_len,
+        ))]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+
+        // Check that each bit of each entry is a 0 or 1.
+        parallel_range_check(&mut g[0], input, self.chunk_len, joint_rand[0], num_shares)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+
+        // If the bit length is zero, then every entry is zero.
+        if self.bits == 0 {
+            return Ok(vec![F::zero(); self.len]);
+        }
+
+        Ok(input.chunks(self.bits).map(decode_bits).collect())
+    }
+
+    fn input_len(&self) -> usize {
+        self.flattened_len
+    }
+
+    fn proof_len(&self) -> usize {
+        (self.chunk_len * 2) + 3 * ((1 + self.gadget_calls).next_power_of_two() - 1) + 1
+    }
+
+    fn verifier_len(&self) -> usize {
+        2 + self.chunk_len * 2
+    }
+
+    fn output_len(&self) -> usize {
+        self.len
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        1
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        self.chunk_len * 2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        1
+    }
+}
+
+/// A vector of fixed-point numbers with bounded L2 norm. Each measurement is a vector of `len`
+/// real numbers in `[-1, 1)` whose squared L2 norm is at most `1`. The aggregate is the
+/// element-wise sum of the measurements, where each entry is a signed fixed-point number with
+/// `bits - 1` fractional bits.
+///
+/// Each entry `x` is encoded as the `bits`-bit integer `(x + 1) * 2^(bits-1)`, followed by the
+/// bit decomposition of the slack `2^(2*bits-2) - ||x||^2 * 2^(2*bits-2)` and a "count" element
+/// that is equal to `1`. The validity circuit uses one gadget to check that every bit is `0` or
+/// `1` and another to compute the squared norm, which is then checked against the slack. Both
+/// gadgets wrap their inner gadget in a [`ParallelSumGadget`] in order to reduce the proof size
+/// to roughly the square root of the input size, as in [`CountVec`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct FixedPointBoundedL2VecSum<F: FieldElement, SPoly, SMul> {
+    bits: usize,
+    len: usize,
+    norm_bits: usize,
+    range_bits: usize,
+    range_checker: Vec<F>,
+    range_chunk_len: usize,
+    range_gadget_calls: usize,
+    norm_chunk_len: usize,
+    norm_gadget_calls: usize,
+    phantom: PhantomData<(SPoly, SMul)>,
+}
+
+impl<F, SPoly, SMul> FixedPointBoundedL2VecSum<F, SPoly, SMul>
+where
+    F: FieldElement,
+    SPoly: ParallelSumGadget<F, BlindPolyEval<F>>,
+    SMul: ParallelSumGadget<F, Mul<F>>,
+{
+    /// Returns a new [`FixedPointBoundedL2VecSum`] with the given number of bits per entry and
+    /// length of each measurement.
+    pub fn new(bits: usize, len: usize) -> Result<Self, FlpError> {
+        if bits == 0 || bits > 64 {
+            return Err(FlpError::Encode(format!(
+                "bit length ({}) must be in [1, 64]",
+                bits
+            )));
+        }
+
+        // The squared norm, plus the slack, must not wrap around the field modulus. Each entry
+        // contributes at most `2^(2*bits-2)` to the squared norm and the slack is less than
+        // `2^(2*bits-1)`.
+        let len_bits = len
+            .checked_add(2)
+            .map(|n| (usize::BITS - n.leading_zeros()) as usize)
+            .ok_or_else(|| {
+                FlpError::Encode("length of the encoded measurement overflows".to_string())
+            })?;
+        let bound_bits = 2 * bits - 2 + len_bits;
+        if bound_bits >= 8 * F::ENCODED_SIZE
+            || F::modulus() >> F::Integer::try_from(bound_bits).unwrap()
+                == F::Integer::from(F::zero())
+        {
+            return Err(FlpError::Encode(format!(
+                "squared norm of a vector of length {} with {}-bit entries exceeds field modulus",
+                len, bits,
+            )));
+        }
+
+        let norm_bits = 2 * bits - 1;
+        let range_bits = len
+            .checked_mul(bits)
+            .and_then(|entry_bits| entry_bits.checked_add(norm_bits))
+            .ok_or_else(|| {
+                FlpError::Encode("length of the encoded measurement overflows".to_string())
+            })?;
+
+        let (range_chunk_len, range_gadget_calls) = parallel_sum_params(range_bits);
+        let (norm_chunk_len, norm_gadget_calls) = parallel_sum_params(len);
+
+        Ok(Self {
+            bits,
+            len,
+            norm_bits,
+            range_bits,
+            range_checker: poly_range_check(0, 2),
+            range_chunk_len,
+            range_gadget_calls,
+            norm_chunk_len,
+            norm_gadget_calls,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the number of bits used to encode each entry. The aggregate of the (truncated)
+    /// entries must be divided by `2^(bits-1)` in order to recover the sum of the real numbers.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// Returns `2^(bits-1)`, the integer that encodes the real number `0`.
+    fn offset(&self) -> F {
+        pow2(self.bits - 1)
+    }
+
+    /// Returns `2^(2*bits-2)`, the integer that encodes a squared norm of `1`.
+    fn norm_bound(&self) -> F {
+        pow2(2 * self.bits - 2)
+    }
+}
+
+impl<F: FieldElement, SPoly, SMul> Clone for FixedPointBoundedL2VecSum<F, SPoly, SMul> {
+    fn clone(&self) -> Self {
+        Self {
+            bits: self.bits,
+            len: self.len,
+            norm_bits: self.norm_bits,
+            range_bits: self.range_bits,
+            range_checker: self.range_checker.clone(),
+            range_chunk_len: self.range_chunk_len,
+            range_gadget_calls: self.range_gadget_calls,
+            norm_chunk_len: self.norm_chunk_len,
+            norm_gadget_calls: self.norm_gadget_calls,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, SPoly, SMul> Type for FixedPointBoundedL2VecSum<F, SPoly, SMul>
+where
+    F: FieldElement,
+    SPoly: ParallelSumGadget<F, BlindPolyEval<F>> + Eq + 'static,
+    SMul: ParallelSumGadget<F, Mul<F>> + Eq + 'static,
+{
+    type Measurement = Vec<f64>;
+    type Field = F;
+
+    fn encode(&self, measurement: &Vec<f64>) -> Result<Vec<F>, FlpError> {
+        if measurement.len() != self.len {
+            return Err(FlpError::Encode(format!(
+                "unexpected measurement length: got {}; want {}",
+                measurement.len(),
+                self.len
+            )));
+        }
+
+        let offset = 1_u128 << (self.bits - 1);
+        let max_entry = (1_u128 << self.bits) - 1;
+        let mut encoded: Vec<F> = Vec::with_capacity(self.input_len());
+        let mut norm: u128 = 0;
+        for value in measurement {
+            if !(-1.0..1.0).contains(value) {
+                return Err(FlpError::Encode(format!(
+                    "entry ({}) is not in range [-1, 1)",
+                    value
+                )));
+            }
+
+            let entry = std::cmp::min(((value + 1.0) * offset as f64) as u128, max_entry);
+            push_bits(&mut encoded, entry, self.bits);
+
+            let magnitude = if entry < offset {
+                offset - entry
+            } else {
+                entry - offset
+            };
+            norm = norm
+                .checked_add(magnitude * magnitude)
+                .ok_or_else(|| FlpError::Encode("squared norm overflows".to_string()))?;
+        }
+
+        let norm_bound = 1_u128 << (2 * self.bits - 2);
+        if norm > norm_bound {
+            return Err(FlpError::Encode(
+                "squared L2 norm of measurement exceeds 1".to_string(),
+            ));
+        }
+
+        push_bits(&mut encoded, norm_bound - norm, self.norm_bits);
+        encoded.push(F::one());
+        Ok(encoded)
+    }
+
+    fn gadget(&self) -> Vec<Box<dyn Gadget<F>>> {
+        vec![
+            Box::new(SPoly::new(
+                BlindPolyEval::new(self.range_checker.clone(), self.range_gadget_calls),
+                self.range_chunk_len,
+            )),
+            Box::new(SMul::new(
+                Mul::new(self.norm_gadget_calls),
+                self.norm_chunk_len,
+            )),
+        ]
+    }
+
+    fn valid(
+        &self,
+        g: &mut Vec<Box<dyn Gadget<F>>>,
+        input: &[F],
+        joint_rand: &[F],
+        num_shares: usize,
+    ) -> Result<F, FlpError> {
+        valid_call_check(self, input, joint_rand)?;
+        let s = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+
+        // Check that each bit of each entry and of the slack is a 0 or 1.
+        let range_check = parallel_range_check(
+            &mut g[0],
+            &input[..self.range_bits],
+            self.range_chunk_len,
+            joint_rand[0],
+            num_shares,
+        )?;
+
+        // Compute the squared norm of the (offset-corrected) entries.
+        let offset = self.offset() * s;
+        let mut norm = F::zero();
+        let mut padded_chunk = vec![F::zero(); 2 * self.norm_chunk_len];
+        for chunk in input[..self.len * self.bits].chunks(self.norm_chunk_len * self.bits) {
+            for (i, entry_bits) in chunk.chunks(self.bits).enumerate() {
+                let entry = decode_bits(entry_bits) - offset;
+                padded_chunk[2 * i] = entry;
+                padded_chunk[2 * i + 1] = entry;
+            }
+            for x in padded_chunk[2 * (chunk.len() / self.bits)..].iter_mut() {
+                *x = F::zero();
+            }
+
+            norm += g[1].call(&padded_chunk)?;
+        }
+
+        // Check that the squared norm plus the slack is equal to the bound.
+        let slack_start = self.len * self.bits;
+        let slack = decode_bits(&input[slack_start..slack_start + self.norm_bits]);
+        let norm_check = norm + slack - self.norm_bound() * s;
+
+        // Check that the count is equal to 1.
+        let count_check = input[self.range_bits] - s;
+
+        // Take a random linear combination of the checks.
+        let r = joint_rand[1];
+        let r2 = r * r;
+        Ok(r * range_check + r2 * norm_check + (r2 * r) * count_check)
+    }
+
+    fn truncate(&self, input: Vec<F>) -> Result<Vec<F>, FlpError> {
+        truncate_call_check(self, &input)?;
+
+        // Subtract the offset from each entry. The count is used so that the output shares sum to
+        // the offset-corrected entries.
+        let offset = self.offset() * input[self.range_bits];
+        Ok(input[..self.len * self.bits]
+            .chunks(self.bits)
+            .map(|entry_bits| decode_bits(entry_bits) - offset)
+            .collect())
+    }
+
+    fn input_len(&self) -> usize {
+        self.range_bits + 1
+    }
+
+    fn proof_len(&self) -> usize {
+        let range_proof_len = (self.range_chunk_len * 2)
+            + 3 * ((1 + self.range_gadget_calls).next_power_of_two() - 1)
+            + 1;
+        let norm_proof_len = (self.norm_chunk_len * 2)
+            + 2 * ((1 + self.norm_gadget_calls).next_power_of_two() - 1)
+            + 1;
+        range_proof_len + norm_proof_len
+    }
+
+    fn verifier_len(&self) -> usize {
+        3 + self.range_chunk_len * 2 + self.norm_chunk_len * 2
+    }
+
+    fn output_len(&self) -> usize {
+        self.len
+    }
+
+    fn joint_rand_len(&self) -> usize {
+        2
+    }
+
+    fn prove_rand_len(&self) -> usize {
+        self.range_chunk_len * 2 + self.norm_chunk_len * 2
+    }
+
+    fn query_rand_len(&self) -> usize {
+        2
+    }
+}
+
+/// Returns the chunk length and number of gadget calls for a [`ParallelSumGadget`] applied to an
+/// input of the given length. The optimal chunk length is the square root of the input length. If
+/// the input length is not a perfect square, then round down. If the result is 0, then let the
+/// chunk length be 1 so that the underlying gadget can still be called.
+fn parallel_sum_params(len: usize) -> (usize, usize) {
+    let chunk_len = std::cmp::max(1, (len as f64).sqrt() as usize);
+
+    let mut gadget_calls = len / chunk_len;
+    if len % chunk_len != 0 {
+        gadget_calls += 1;
+    }
+
+    (chunk_len, gadget_calls)
+}
+
+/// Checks that each element of (a share of) `input` is a 0 or 1 using a [`ParallelSumGadget`] with
+/// the given chunk length that wraps a [`BlindPolyEval`] of the range-check polynomial. The result
+/// is a random linear combination, with powers of `r` as coefficients, of the range check of each
+/// element. If the last chunk is smaller than the chunk length, then it is padded with zeros, which
+/// pass the range check.
+fn parallel_range_check<F: FieldElement>(
+    gadget: &mut Box<dyn Gadget<F>>,
+    input: &[F],
+    chunk_len: usize,
+    r: F,
+    num_shares: usize,
+) -> Result<F, FlpError> {
+    let s = F::from(F::Integer::try_from(num_shares).unwrap()).inv();
+    let mut rpow = r;
+    let mut outp = F::zero();
+    let mut padded_chunk = vec![F::zero(); 2 * chunk_len];
+    for chunk in input.chunks(chunk_len) {
+        for i in 0..chunk_len {
+            padded_chunk[2 * i] = chunk.get(i).copied().unwrap_or_else(F::zero);
+            padded_chunk[2 * i + 1] = rpow * s;
+            rpow *= r;
+        }
+
+        outp += gadget.call(&padded_chunk)?;
+    }
+
+    Ok(outp)
+}
+
+/// Returns `2^exp` as a field element.
+fn pow2<F: FieldElement>(exp: usize) -> F {
+    let mut w = F::one();
+    for _ in 0..exp {
+        w += w;
+    }
+    w
+}
+
+/// Appends the `bits` least significant bits of `value` to `encoded`.
+fn push_bits<F: FieldElement>(encoded: &mut Vec<F>, value: u128, bits: usize) {
+    for l in 0..bits {
+        encoded.push(if (value >> l) & 1 == 1 {
+            F::one()
+        } else {
+            F::zero()
+        });
+    }
+}
+
+/// Checks that (a share of) `input` is a one-hot vector, i.e., that each element is `0` or `1` and
+/// that the elements sum to `1`. This is the validity circuit of [`Histogram`] and
+/// [`CategoricalHistogram`].
+fn one_hot_valid<F: FieldElement>(
+    g: &mut [Box<dyn Gadget<F>>],
+    input: &[F],
+    joint_rand: &[F],
+    num_shares: usize,
+) -> Result<F, FlpError> {
+    // Check that each element of `data` is a 0 or 1.
+    let mut range_check = F::zero();
+    let mut r = joint_rand[0];
+    for chunk in input.chunks(1) {
+        range_check += r * g[0].call(chunk)?;
+        r *= joint_rand[0];
+    }
+
+    // Check that the elements of `data` sum to 1.
+    let mut sum_check = -(F::one() / F::from(F::Integer::try_from(num_shares).unwrap()));
+    for val in input.iter() {
+        sum_check += *val;
+    }
+
+    // Take a random linear combination of both checks.
+    Ok(joint_rand[1] * range_check + (joint_rand[1] * joint_rand[1]) * sum_check)
+}
+
+/// Recovers an integer from (a share of) its little-endian bit decomposition.
+fn decode_bits<F: FieldElement>(bits: &[F]) -> F {
+    let mut decoded = F::zero();
+    let mut w = F::one();
+    for bit in bits {
+        decoded += w * *bit;
+        w += w;
+    }
+    decoded
+}
+
+pub(crate) fn truncate_call_check<T: Type>(typ: &T, input: &[T::Field]) -> Result<(), FlpError> {
+    if input.len() != typ.input_len() {
+        return Err(FlpError::Truncate(format!(
+            "Unexpected input length: got {}; want {}",
+            input.len(),
+            typ.input_len()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{random_vector, split_vector, Field128, Field64 as TestField};
+    use crate::flp::gadgets::ParallelSum;
+    #[cfg(feature = "multithreaded")]
+    use crate::flp::gadgets::ParallelSumMultithreaded;
+    use serde::Deserialize;
+
+    // Number of shares to split input and proofs into in `flp_test`.
+    const NUM_SHARES: usize = 3;
+
+    struct ValidityTestCase<F> {
+        expect_valid: bool,
+        expected_output: Option<Vec<F>>,
+    }
+
+    #[test]
+    fn test_count() {
+        let count: Count<TestField> = Count::new();
+        let zero = TestField::zero();
+        let one = TestField::one();
+
+        // Test FLP on valid input.
+        flp_validity_test(
+            &count,
+            &count.encode(&1).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![one]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &count,
+            &count.encode(&0).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![zero]),
+            },
+        )
+        .unwrap();
+
+        // Test FLP on invalid input.
+        flp_validity_test(
+            &count,
+            &[TestField::from(1337)],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // Try running the validity circuit on an input that's too short.
+        count.valid(&mut count.gadget(), &[], &[], 1).unwrap_err();
+        count
+            .valid(&mut count.gadget(), &[1.into(), 2.into()], &[], 1)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_sum() {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let nine = TestField::from(9);
+
+        // TODO(cjpatton) Try encoding invalid measurements.
+
+        // Test FLP on valid input.
+        let sum = Sum::new(11).unwrap();
+        flp_validity_test(
+            &sum,
+            &sum.encode(&1337).unwrap(),
+            &ValidityTestCase {
+                expect_valid: true,
+                expected_output: Some(vec![TestField::from(1337)]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &Sum::new(0).unwrap(),
+            &[],
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![zero]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &Sum::new(2).unwrap(),
+            &[one, zero],
+            &ValidityTestCase {
+                expect_valid: true,
+                expected_output: Some(vec![one]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &Sum::new(9).unwrap(),
+            &[one, zero, one, one, zero, one, one, one, zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![TestField::from(237)]),
+            },
+        )
+        .unwrap();
+
+        // Test FLP on invalid input.
+        flp_validity_test(
+            &Sum::new(3).unwrap(),
+            &[one, nine, zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &Sum::new(5).unwrap(),
+            &[zero, zero, zero, zero, nine],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_bounded_sum() {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let two = TestField::from(2);
+
+        let bounded_sum = BoundedSum::new(10, 20).unwrap();
+        assert_eq!(bounded_sum.input_len(), 9);
+
+        // `x - min` and `max - x`, followed by the count.
+        assert_eq!(
+            bounded_sum.encode(&13).unwrap(),
+            &[one, one, zero, zero, one, one, one, zero, one]
+        );
+
+        // Test FLP on valid input.
+        for x in [10, 13, 17, 20] {
+            flp_validity_test(
+                &bounded_sum,
+                &bounded_sum.encode(&x).unwrap(),
+                &ValidityTestCase {
+                    expect_valid: true,
+                    expected_output: Some(vec![TestField::from(x)]),
+                },
+            )
+            .unwrap();
+        }
+
+        let bounded_sum = BoundedSum::new(1337, 1337).unwrap();
+        flp_validity_test(
+            &bounded_sum,
+            &bounded_sum.encode(&1337).unwrap(),
+            &ValidityTestCase {
+                expect_valid: true,
+                expected_output: Some(vec![TestField::from(1337)]),
+            },
+        )
+        .unwrap();
+
+        let bounded_sum = BoundedSum::new(0, 1000).unwrap();
+        flp_validity_test(
+            &bounded_sum,
+            &bounded_sum.encode(&999).unwrap(),
+            &ValidityTestCase {
+                expect_valid: true,
+                expected_output: Some(vec![TestField::from(999)]),
+            },
+        )
+        .unwrap();
+
+        // Test FLP on invalid input.
+        let bounded_sum = BoundedSum::new(10, 20).unwrap();
+
+        // `x - min` is 11 and `max - x` is 15.
+        flp_validity_test(
+            &bounded_sum,
+            &[one, one, zero, one, one, one, one, one, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // `x - min` is 12 and `max - x` is -2, represented with a non-bit element.
+        flp_validity_test(
+            &bounded_sum,
+            &[zero, zero, one, one, -two, zero, zero, zero, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // The count is not equal to 1.
+        flp_validity_test(
+            &bounded_sum,
+            &[one, one, zero, zero, one, one, one, zero, two],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // Try encoding invalid measurements.
+        bounded_sum.encode(&9).unwrap_err();
+        bounded_sum.encode(&21).unwrap_err();
+
+        // Invalid bounds.
+        BoundedSum::<TestField>::new(20, 10).unwrap_err();
+        BoundedSum::<TestField>::new(0, TestField::modulus()).unwrap_err();
+        BoundedSum::<TestField>::new(0, TestField::modulus() - 1).unwrap_err();
+    }
+
+    #[test]
+    fn test_signed_sum() {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let eight = TestField::from(8);
+
+        let signed_sum = SignedSum::new(4).unwrap();
+        assert_eq!(signed_sum.input_len(), 5);
+
+        // `x + 8`, followed by the count.
+        assert_eq!(
+            signed_sum.encode(&-3).unwrap(),
+            &[one, zero, one, zero, one]
+        );
+
+        // Test FLP on valid input.
+        for x in [-8, -3, 0, 5, 7] {
+            flp_validity_test(
+                &signed_sum,
+                &signed_sum.encode(&x).unwrap(),
+                &ValidityTestCase {
+                    expect_valid: true,
+                    expected_output: Some(vec![TestField::from((x + 8) as u64), eight]),
+                },
+            )
+            .unwrap();
+        }
+
+        let signed_sum = SignedSum::new(1).unwrap();
+        flp_validity_test(
+            &signed_sum,
+            &signed_sum.encode(&-1).unwrap(),
+            &ValidityTestCase {
+                expect_valid: true,
+                expected_output: Some(vec![zero, one]),
+            },
+        )
+        .unwrap();
+
+        // Test FLP on invalid input.
+        let signed_sum = SignedSum::new(4).unwrap();
+        flp_validity_test(
+            &signed_sum,
+            &[one, TestField::from(2), zero, zero, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // The count is not equal to 1.
+        flp_validity_test(
+            &signed_sum,
+            &[one, zero, one, zero, zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // Try encoding invalid measurements.
+        signed_sum.encode(&-9).unwrap_err();
+        signed_sum.encode(&8).unwrap_err();
+
+        // Invalid bit lengths.
+        SignedSum::<TestField>::new(0).unwrap_err();
+        SignedSum::<TestField>::new(64).unwrap_err();
+        SignedSum::<Field128>::new(64).unwrap();
+        SignedSum::<Field128>::new(65).unwrap_err();
+    }
+
+    #[test]
+    fn test_mean_variance() {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let two = TestField::from(2);
+
+        let mean_variance = MeanVariance::new(4).unwrap();
+        assert_eq!(
+            mean_variance.encode(&5).unwrap(),
+            &[one, zero, one, zero, TestField::from(25), one]
+        );
+
+        // Test FLP on valid input.
+        for x in [0, 1, 5, 15] {
+            flp_validity_test(
+                &mean_variance,
+                &mean_variance.encode(&x).unwrap(),
+                &ValidityTestCase {
+                    expect_valid: true,
+                    expected_output: Some(vec![TestField::from(x), TestField::from(x * x), one]),
+                },
+            )
+            .unwrap();
+        }
+
+        flp_validity_test(
+            &MeanVariance::new(0).unwrap(),
+            &[zero, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![zero, zero, one]),
+            },
+        )
+        .unwrap();
+
+        // Test FLP on invalid input.
+        //
+        // The second-to-last element is not the square of `x`.
+        flp_validity_test(
+            &mean_variance,
+            &[one, zero, one, zero, TestField::from(24), one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // A bit of `x` is not a 0 or 1.
+        flp_validity_test(
+            &mean_variance,
+            &[two, zero, zero, zero, TestField::from(4), one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // The count is not equal to 1.
+        flp_validity_test(
+            &mean_variance,
+            &[one, zero, one, zero, TestField::from(25), zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // Try encoding invalid measurements.
+        mean_variance.encode(&16).unwrap_err();
+
+        // The square of a 32-bit measurement does not fit in a 64-bit field.
+        MeanVariance::<TestField>::new(31).unwrap();
+        MeanVariance::<TestField>::new(32).unwrap_err();
+    }
+
+    #[test]
+    fn test_weighted_sum() {
+        let zero = TestField::zero();
+        let one = TestField::one();
+
+        let weighted_sum = WeightedSum::new(3, 2).unwrap();
+        assert_eq!(weighted_sum.input_len(), 6);
+
+        // The bits of `x` and `w`, followed by `w * x`.
+        assert_eq!(
+            weighted_sum.encode(&(5, 3)).unwrap(),
+            &[one, zero, one, one, one, TestField::from(15)]
+        );
+
+        // The gadgets differ in arity, degree and number of calls.
+        let gadgets = weighted_sum.gadget();
+        assert_eq!(gadgets.len(), 2);
+        assert_eq!(
+            (gadgets[0].arity(), gadgets[0].degree(), gadgets[0].calls()),
+            (2, 3, 5)
+        );
+        assert_eq!(
+            (gadgets[1].arity(), gadgets[1].degree(), gadgets[1].calls()),
+            (2, 2, 1)
+        );
+
+        // Test FLP on valid input.
+        for (x, w) in [(0, 0), (5, 3), (7, 1), (0, 2), (7, 3)] {
+            flp_validity_test(
+                &weighted_sum,
+                &weighted_sum.encode(&(x, w)).unwrap(),
+                &ValidityTestCase {
+                    expect_valid: true,
+                    expected_output: Some(vec![TestField::from(w * x), TestField::from(w)]),
+                },
+            )
+            .unwrap();
+        }
+
+        // Test FLP on invalid input.
+
+        // The product is wrong.
+        flp_validity_test(
+            &weighted_sum,
+            &[one, zero, one, one, one, TestField::from(14)],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // A bit of the weight is not a 0 or 1, but the product is consistent with it.
+        flp_validity_test(
+            &weighted_sum,
+            &[
+                one,
+                zero,
+                zero,
+                TestField::from(2),
+                zero,
+                TestField::from(2),
+            ],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        // Tampering with the proof segment of either gadget is detected.
+        let input = weighted_sum.encode(&(5, 3)).unwrap();
+        let joint_rand = random_vector(weighted_sum.joint_rand_len()).unwrap();
+        let prove_rand = random_vector(weighted_sum.prove_rand_len()).unwrap();
+        let query_rand = random_vector(weighted_sum.query_rand_len()).unwrap();
+        let proof = weighted_sum
+            .prove(&input, &prove_rand, &joint_rand)
+            .unwrap();
+        let range_proof_len = 2 + 3 * ((1 + 5_usize).next_power_of_two() - 1) + 1;
+        for i in [0, range_proof_len - 1, range_proof_len, proof.len() - 1] {
+            let mut tampered = proof.clone();
+            tampered[i] += one;
+            let verifier = weighted_sum
+                .query(&input, &tampered, &query_rand, &joint_rand, 1)
+                .unwrap();
+            assert!(
+                !weighted_sum.decide(&verifier).unwrap(),
+                "tampered at {}",
+                i
+            );
+        }
+
+        // Try encoding invalid measurements.
+        weighted_sum.encode(&(8, 0)).unwrap_err();
+        weighted_sum.encode(&(0, 4)).unwrap_err();
+
+        // Invalid bit lengths.
+        WeightedSum::<TestField>::new(0, 2).unwrap_err();
+        WeightedSum::<TestField>::new(2, 0).unwrap_err();
+        WeightedSum::<TestField>::new(32, 32).unwrap_err();
+        WeightedSum::<TestField>::new(32, 31).unwrap();
+    }
+
+    #[test]
+    fn test_histogram() {
+        let hist = Histogram::new(vec![10, 20]).unwrap();
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let nine = TestField::from(9);
+
+        assert_eq!(&hist.encode(&7).unwrap(), &[one, zero, zero]);
+        assert_eq!(&hist.encode(&10).unwrap(), &[one, zero, zero]);
+        assert_eq!(&hist.encode(&17).unwrap(), &[zero, one, zero]);
+        assert_eq!(&hist.encode(&20).unwrap(), &[zero, one, zero]);
+        assert_eq!(&hist.encode(&27).unwrap(), &[zero, zero, one]);
+
+        // Invalid bucket boundaries.
+        Histogram::<TestField>::new(vec![10, 0]).unwrap_err();
+        Histogram::<TestField>::new(vec![10, 10]).unwrap_err();
+
+        // Test valid inputs.
+        flp_validity_test(
+            &hist,
+            &hist.encode(&0).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![one, zero, zero]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &hist,
+            &hist.encode(&17).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![zero, one, zero]),
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &hist,
+            &hist.encode(&1337).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![zero, zero, one]),
+            },
+        )
+        .unwrap();
+
+        // Test invalid inputs.
+        flp_validity_test(
+            &hist,
+            &[zero, zero, nine],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &hist,
+            &[zero, one, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &hist,
+            &[one, one, one],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+
+        flp_validity_test(
+            &hist,
+            &[zero, zero, zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_categorical_histogram() {
+        let labels = vec![
+            "chrome".to_string(),
+            "firefox".to_string(),
+            "safari".to_string(),
+        ];
+        let hist = CategoricalHistogram::new(labels.clone()).unwrap();
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let nine = TestField::from(9);
+
+        assert_eq!(hist.labels(), &labels[..]);
+        assert_eq!(hist.bucket_index("firefox"), Some(1));
+        assert_eq!(hist.bucket_index("edge"), None);
+
+        assert_eq!(&hist.encode(&0).unwrap(), &[one, zero, zero]);
+        assert_eq!(&hist.encode(&2).unwrap(), &[zero, zero, one]);
+        hist.encode(&3).unwrap_err();
+
+        // Invalid labels.
+        CategoricalHistogram::<TestField>::new(vec![]).unwrap_err();
+        CategoricalHistogram::<TestField>::new(vec!["a".to_string(), "a".to_string()]).unwrap_err();
+
+        // Test valid inputs.
+        for (bucket, expected_output) in [
+            vec![one, zero, zero],
+            vec![zero, one, zero],
+            vec![zero, zero, one],
+        ]
+        .iter()
+        .enumerate()
+        {
+            flp_validity_test(
+                &hist,
+                &hist.encode(&bucket).unwrap(),
+                &ValidityTestCase::<TestField> {
+                    expect_valid: true,
+                    expected_output: Some(expected_output.clone()),
+                },
+            )
+            .unwrap();
+        }
+
+        let hist = CategoricalHistogram::new(vec!["only".to_string()]).unwrap();
+        flp_validity_test(
+            &hist,
+            &hist.encode(&0).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![one]),
+            },
+        )
+        .unwrap();
+
+        // Test invalid inputs.
+        let hist = CategoricalHistogram::new(labels).unwrap();
+        for input in [
+            [zero, zero, nine],
+            [zero, one, one],
+            [one, one, one],
+            [zero, zero, zero],
+        ] {
+            flp_validity_test(
+                &hist,
+                &input,
+                &ValidityTestCase::<TestField> {
+                    expect_valid: false,
+                    expected_output: None,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    fn test_count_vec<F, S>(f: F)
+    where
+        F: Fn(usize) -> CountVec<TestField, S>,
+        S: 'static + ParallelSumGadget<TestField, BlindPolyEval<TestField>> + Eq,
+    {
+        let one = TestField::one();
+        let nine = TestField::from(9);
+
+        // Test on valid inputs.
+        for len in 0..10 {
+            let count_vec = f(len);
+            flp_validity_test(
+                &count_vec,
+                &count_vec.encode(&vec![1; len]).unwrap(),
+                &ValidityTestCase::<TestField> {
+                    expect_valid: true,
+                    expected_output: Some(vec![one; len]),
+                },
+            )
+            .unwrap();
+        }
+
+        let len = 100;
+        let count_vec = f(len);
+        flp_validity_test(
+            &count_vec,
+            &count_vec.encode(&vec![1; len]).unwrap(),
+            &ValidityTestCase::<TestField> {
+                expect_valid: true,
+                expected_output: Some(vec![one; len]),
+            },
+        )
+        .unwrap();
+
+        // Test on invalid inputs.
+        for len in 1..10 {
+            let count_vec = f(len);
+            flp_validity_test(
+                &count_vec,
+                &vec![nine; len],
+                &ValidityTestCase::<TestField> {
+                    expect_valid: false,
+                    expected_output: None,
+                },
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_count_vec_serial() {
+        test_count_vec(CountVec::<TestField, ParallelSum<TestField, BlindPolyEval<TestField>>>::new)
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_count_vec_parallel() {
+        test_count_vec(CountVec::<TestField, ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>>::new)
+    }
+
+    fn test_count_vec_with_weight<S>()
+    where
+        S: 'static + ParallelSumGadget<TestField, BlindPolyEval<TestField>> + Eq,
+    {
+        let zero = TestField::zero();
+        let one = TestField::one();
+
+        // Test on valid inputs.
+        for len in 0..10 {
+            for weight in 0..=len {
+                let count_vec = CountVecWithWeight::<TestField, S>::new(len, weight).unwrap();
+                let mut measurement = vec![0; len];
+                for value in measurement.iter_mut().take(weight / 2) {
+                    *value = 1;
+                }
+                flp_validity_test(
+                    &count_vec,
+                    &count_vec.encode(&measurement).unwrap(),
+                    &ValidityTestCase::<TestField> {
+                        expect_valid: true,
+                        expected_output: Some(measurement.iter().map(|v| (*v).into()).collect()),
+                    },
+                )
+                .unwrap();
+
+                let count_vec = CountVecWithWeight::<TestField, S>::new_exact(len, weight).unwrap();
+                let mut measurement = vec![0; len];
+                for value in measurement.iter_mut().skip(len - weight) {
+                    *value = 1;
+                }
+                flp_validity_test(
+                    &count_vec,
+                    &count_vec.encode(&measurement).unwrap(),
+                    &ValidityTestCase::<TestField> {
+                        expect_valid: true,
+                        expected_output: Some(measurement.iter().map(|v| (*v).into()).collect()),
+                    },
+                )
+                .unwrap();
+            }
+        }
+
+        // Check the encoding: the entries are followed by the 3-bit slack `5 - 2 = 3`.
+        let count_vec = CountVecWithWeight::<TestField, S>::new(7, 5).unwrap();
+        assert_eq!(
+            count_vec.encode(&vec![0, 1, 0, 0, 1, 0, 0]).unwrap(),
+            &[zero, one, zero, zero, one, zero, zero, one, one, zero]
+        );
+
+        // Test on invalid inputs.
+        let count_vec = CountVecWithWeight::<TestField, S>::new(7, 2).unwrap();
+        let mut input = count_vec.encode(&vec![0, 1, 0, 0, 1, 0, 0]).unwrap();
+        input[0] = TestField::from(9);
+        flp_validity_test(
+            &count_vec,
+            &input,
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
+            },
         )
         .unwrap();
 
-        // Test FLP on invalid input.
+        // Three entries are set, so the slack would have to be negative.
         flp_validity_test(
-            &count,
-            &[TestField::from(1337)],
+            &count_vec,
+            &[one, one, one, zero, zero, zero, zero, zero, zero],
             &ValidityTestCase::<TestField> {
                 expect_valid: false,
                 expected_output: None,
@@ -583,137 +2738,260 @@ mod tests {
         )
         .unwrap();
 
-        // Try running the validity circuit on an input that's too short.
-        count.valid(&mut count.gadget(), &[], &[], 1).unwrap_err();
-        count
-            .valid(&mut count.gadget(), &[1.into(), 2.into()], &[], 1)
-            .unwrap_err();
-    }
-
-    #[test]
-    fn test_sum() {
-        let zero = TestField::zero();
-        let one = TestField::one();
-        let nine = TestField::from(9);
-
-        // TODO(cjpatton) Try encoding invalid measurements.
-
-        // Test FLP on valid input.
-        let sum = Sum::new(11).unwrap();
+        // Two entries are set, but the slack claims the weight is exactly 1.
         flp_validity_test(
-            &sum,
-            &sum.encode(&1337).unwrap(),
-            &ValidityTestCase {
-                expect_valid: true,
-                expected_output: Some(vec![TestField::from(1337)]),
+            &count_vec,
+            &[one, one, zero, zero, zero, zero, zero, one, zero],
+            &ValidityTestCase::<TestField> {
+                expect_valid: false,
+                expected_output: None,
             },
         )
         .unwrap();
 
+        let count_vec = CountVecWithWeight::<TestField, S>::new_exact(4, 2).unwrap();
         flp_validity_test(
-            &Sum::new(0).unwrap(),
-            &[],
+            &count_vec,
+            &[one, zero, zero, zero],
             &ValidityTestCase::<TestField> {
-                expect_valid: true,
-                expected_output: Some(vec![zero]),
+                expect_valid: false,
+                expected_output: None,
             },
         )
         .unwrap();
 
+        // Try encoding invalid measurements.
+        count_vec.encode(&vec![1, 0, 0, 0]).unwrap_err();
+        count_vec.encode(&vec![1, 1, 1, 0]).unwrap_err();
+        count_vec.encode(&vec![2, 0, 0, 0]).unwrap_err();
+        count_vec.encode(&vec![1, 1, 0]).unwrap_err();
+        let count_vec = CountVecWithWeight::<TestField, S>::new(4, 2).unwrap();
+        count_vec.encode(&vec![1, 1, 1, 0]).unwrap_err();
+
+        // Try constructing types with invalid parameters.
+        CountVecWithWeight::<TestField, S>::new(3, 4).unwrap_err();
+        CountVecWithWeight::<TestField, S>::new_exact(3, 4).unwrap_err();
+        CountVecWithWeight::<TestField, S>::new(usize::MAX, usize::MAX / 2 + 1).unwrap_err();
+    }
+
+    #[test]
+    fn test_count_vec_with_weight_serial() {
+        test_count_vec_with_weight::<ParallelSum<TestField, BlindPolyEval<TestField>>>()
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_count_vec_with_weight_parallel() {
+        test_count_vec_with_weight::<ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>>(
+        )
+    }
+
+    fn test_sum_vec<F, S>(f: F)
+    where
+        F: Fn(usize, usize) -> Result<SumVec<TestField, S>, FlpError>,
+        S: 'static + ParallelSumGadget<TestField, BlindPolyEval<TestField>> + Eq,
+    {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let nine = TestField::from(9);
+
+        // Test on valid inputs.
+        for len in 0..10 {
+            let sum_vec = f(1, len).unwrap();
+            flp_validity_test(
+                &sum_vec,
+                &sum_vec.encode(&vec![1; len]).unwrap(),
+                &ValidityTestCase::<TestField> {
+                    expect_valid: true,
+                    expected_output: Some(vec![one; len]),
+                },
+            )
+            .unwrap();
+        }
+
+        let len = 100;
+        let sum_vec = f(1, len).unwrap();
         flp_validity_test(
-            &Sum::new(2).unwrap(),
-            &[one, zero],
-            &ValidityTestCase {
+            &sum_vec,
+            &sum_vec.encode(&vec![1; len]).unwrap(),
+            &ValidityTestCase::<TestField> {
                 expect_valid: true,
-                expected_output: Some(vec![one]),
+                expected_output: Some(vec![one; len]),
             },
         )
         .unwrap();
 
+        let len = 23;
+        let sum_vec = f(4, len).unwrap();
         flp_validity_test(
-            &Sum::new(9).unwrap(),
-            &[one, zero, one, one, zero, one, one, one, zero],
+            &sum_vec,
+            &sum_vec.encode(&vec![9; len]).unwrap(),
             &ValidityTestCase::<TestField> {
                 expect_valid: true,
-                expected_output: Some(vec![TestField::from(237)]),
+                expected_output: Some(vec![nine; len]),
             },
         )
         .unwrap();
 
-        // Test FLP on invalid input.
+        let sum_vec = f(0, 3).unwrap();
         flp_validity_test(
-            &Sum::new(3).unwrap(),
-            &[one, nine, zero],
+            &sum_vec,
+            &[],
             &ValidityTestCase::<TestField> {
-                expect_valid: false,
-                expected_output: None,
+                expect_valid: true,
+                expected_output: Some(vec![zero; 3]),
             },
         )
         .unwrap();
 
+        let sum_vec = f(3, 3).unwrap();
+        assert_eq!(
+            sum_vec.encode(&vec![1, 6, 7]).unwrap(),
+            &[one, zero, zero, zero, one, one, one, one, one]
+        );
+
+        // Test on invalid inputs.
+        for len in 1..10 {
+            let sum_vec = f(2, len).unwrap();
+            flp_validity_test(
+                &sum_vec,
+                &vec![nine; 2 * len],
+                &ValidityTestCase::<TestField> {
+                    expect_valid: false,
+                    expected_output: None,
+                },
+            )
+            .unwrap();
+        }
+
+        // Try encoding invalid measurements.
+        let sum_vec = f(3, 2).unwrap();
+        sum_vec.encode(&vec![8, 0]).unwrap_err();
+        sum_vec.encode(&vec![0]).unwrap_err();
+        sum_vec.encode(&vec![0, 0, 0]).unwrap_err();
+    }
+
+    #[test]
+    fn test_sum_vec_serial() {
+        test_sum_vec(SumVec::<TestField, ParallelSum<TestField, BlindPolyEval<TestField>>>::new)
+    }
+
+    #[test]
+    fn test_sum_vec_large_bits() {
+        // Entries wider than 32 bits are decoded without overflowing the bit weights.
+        let sum_vec =
+            SumVec::<Field128, ParallelSum<Field128, BlindPolyEval<Field128>>>::new(64, 3).unwrap();
+        let measurement = vec![u64::MAX as u128, 1 << 40, 0];
         flp_validity_test(
-            &Sum::new(5).unwrap(),
-            &[zero, zero, zero, zero, nine],
-            &ValidityTestCase::<TestField> {
-                expect_valid: false,
-                expected_output: None,
+            &sum_vec,
+            &sum_vec.encode(&measurement).unwrap(),
+            &ValidityTestCase::<Field128> {
+                expect_valid: true,
+                expected_output: Some(measurement.into_iter().map(Field128::from).collect()),
             },
         )
         .unwrap();
     }
 
     #[test]
-    fn test_histogram() {
-        let hist = Histogram::new(vec![10, 20]).unwrap();
-        let zero = TestField::zero();
-        let one = TestField::one();
-        let nine = TestField::from(9);
+    fn test_sum_vec_field_bits() {
+        // A bit length equal to the width of the field is rejected rather than shifting the
+        // modulus out of range.
+        SumVec::<TestField, ParallelSum<TestField, BlindPolyEval<TestField>>>::new(64, 1)
+            .unwrap_err();
+        SumVec::<Field128, ParallelSum<Field128, BlindPolyEval<Field128>>>::new(128, 1)
+            .unwrap_err();
+        SumVec::<TestField, ParallelSum<TestField, BlindPolyEval<TestField>>>::new(63, 1).unwrap();
+        SumVec::<Field128, ParallelSum<Field128, BlindPolyEval<Field128>>>::new(127, 1).unwrap();
+    }
 
-        assert_eq!(&hist.encode(&7).unwrap(), &[one, zero, zero]);
-        assert_eq!(&hist.encode(&10).unwrap(), &[one, zero, zero]);
-        assert_eq!(&hist.encode(&17).unwrap(), &[zero, one, zero]);
-        assert_eq!(&hist.encode(&20).unwrap(), &[zero, one, zero]);
-        assert_eq!(&hist.encode(&27).unwrap(), &[zero, zero, one]);
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_sum_vec_parallel() {
+        test_sum_vec(
+            SumVec::<TestField, ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>>::new,
+        )
+    }
 
-        // Invalid bucket boundaries.
-        Histogram::<TestField>::new(vec![10, 0]).unwrap_err();
-        Histogram::<TestField>::new(vec![10, 10]).unwrap_err();
+    fn test_fixed_point_bounded_l2_vec_sum<F, SPoly, SMul>(f: F)
+    where
+        F: Fn(usize, usize) -> Result<FixedPointBoundedL2VecSum<TestField, SPoly, SMul>, FlpError>,
+        SPoly: 'static + ParallelSumGadget<TestField, BlindPolyEval<TestField>> + Eq,
+        SMul: 'static + ParallelSumGadget<TestField, Mul<TestField>> + Eq,
+    {
+        let zero = TestField::zero();
+        let one = TestField::one();
+        let four = TestField::from(4);
 
-        // Test valid inputs.
+        // Test on valid inputs.
+        let vec = f(4, 2).unwrap();
         flp_validity_test(
-            &hist,
-            &hist.encode(&0).unwrap(),
+            &vec,
+            &vec.encode(&vec![0.5, -0.5]).unwrap(),
             &ValidityTestCase::<TestField> {
                 expect_valid: true,
-                expected_output: Some(vec![one, zero, zero]),
+                expected_output: Some(vec![four, -four]),
             },
         )
         .unwrap();
 
+        // A vector whose squared norm is exactly 1.
         flp_validity_test(
-            &hist,
-            &hist.encode(&17).unwrap(),
+            &vec,
+            &vec.encode(&vec![-1.0, 0.0]).unwrap(),
             &ValidityTestCase::<TestField> {
                 expect_valid: true,
-                expected_output: Some(vec![zero, one, zero]),
+                expected_output: Some(vec![-TestField::from(8), zero]),
             },
         )
         .unwrap();
 
+        for len in 0..10 {
+            let vec = f(8, len).unwrap();
+            flp_validity_test(
+                &vec,
+                &vec.encode(&vec![0.0; len]).unwrap(),
+                &ValidityTestCase::<TestField> {
+                    expect_valid: true,
+                    expected_output: Some(vec![zero; len]),
+                },
+            )
+            .unwrap();
+        }
+
+        let len = 100;
+        let vec = f(8, len).unwrap();
+        let mut measurement = vec![0.0; len];
+        measurement[0] = 0.5;
+        measurement[len - 1] = -0.5;
+        let mut expected_output = vec![zero; len];
+        expected_output[0] = TestField::from(64);
+        expected_output[len - 1] = -TestField::from(64);
         flp_validity_test(
-            &hist,
-            &hist.encode(&1337).unwrap(),
+            &vec,
+            &vec.encode(&measurement).unwrap(),
             &ValidityTestCase::<TestField> {
                 expect_valid: true,
-                expected_output: Some(vec![zero, zero, one]),
+                expected_output: Some(expected_output),
             },
         )
         .unwrap();
 
-        // Test invalid inputs.
+        // Check the encoding: two 3-bit entries followed by the 5-bit slack `16 - 2^2 - 1^2 = 11`
+        // and the count.
+        let vec = f(3, 2).unwrap();
+        assert_eq!(
+            vec.encode(&vec![0.5, -0.25]).unwrap(),
+            &[zero, one, one, one, one, zero, one, one, zero, one, zero, one]
+        );
+
+        // Test on invalid inputs.
+        let vec = f(4, 2).unwrap();
+        let mut input = vec.encode(&vec![0.5, -0.5]).unwrap();
+        input[0] = TestField::from(9);
         flp_validity_test(
-            &hist,
-            &[zero, zero, nine],
+            &vec,
+            &input,
             &ValidityTestCase::<TestField> {
                 expect_valid: false,
                 expected_output: None,
@@ -721,9 +2999,12 @@ mod tests {
         )
         .unwrap();
 
+        // The count must be equal to 1.
+        let mut input = vec.encode(&vec![0.5, -0.5]).unwrap();
+        input[vec.input_len() - 1] = zero;
         flp_validity_test(
-            &hist,
-            &[zero, one, one],
+            &vec,
+            &input,
             &ValidityTestCase::<TestField> {
                 expect_valid: false,
                 expected_output: None,
@@ -731,9 +3012,15 @@ mod tests {
         )
         .unwrap();
 
+        // Both entries encode `0.75`, so the squared norm exceeds 1 and no slack makes up for it.
+        let mut input = Vec::new();
+        push_bits(&mut input, 14, 4);
+        push_bits(&mut input, 14, 4);
+        push_bits(&mut input, 0, 7);
+        input.push(one);
         flp_validity_test(
-            &hist,
-            &[one, one, one],
+            &vec,
+            &input,
             &ValidityTestCase::<TestField> {
                 expect_valid: false,
                 expected_output: None,
@@ -741,75 +3028,121 @@ mod tests {
         )
         .unwrap();
 
-        flp_validity_test(
-            &hist,
-            &[zero, zero, zero],
-            &ValidityTestCase::<TestField> {
-                expect_valid: false,
-                expected_output: None,
-            },
+        // Try encoding invalid measurements.
+        vec.encode(&vec![0.75, 0.75]).unwrap_err();
+        vec.encode(&vec![1.0, 0.0]).unwrap_err();
+        vec.encode(&vec![f64::NAN, 0.0]).unwrap_err();
+        vec.encode(&vec![0.0]).unwrap_err();
+        vec.encode(&vec![0.0, 0.0, 0.0]).unwrap_err();
+
+        // Try constructing types whose squared norm would wrap around the field modulus.
+        f(0, 2).unwrap_err();
+        f(32, 2).unwrap_err();
+
+        // Lengths whose encoding overflows are rejected rather than panicking.
+        f(4, usize::MAX).unwrap_err();
+        f(4, usize::MAX - 1).unwrap_err();
+    }
+
+    #[test]
+    fn test_fixed_point_bounded_l2_vec_sum_serial() {
+        test_fixed_point_bounded_l2_vec_sum(
+            FixedPointBoundedL2VecSum::<
+                TestField,
+                ParallelSum<TestField, BlindPolyEval<TestField>>,
+                ParallelSum<TestField, Mul<TestField>>,
+            >::new,
         )
-        .unwrap();
     }
 
-    fn test_count_vec<F, S>(f: F)
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_fixed_point_bounded_l2_vec_sum_parallel() {
+        test_fixed_point_bounded_l2_vec_sum(
+            FixedPointBoundedL2VecSum::<
+                TestField,
+                ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>,
+                ParallelSumMultithreaded<TestField, Mul<TestField>>,
+            >::new,
+        )
+    }
+
+    /// A regression vector for the FLP. The randomness is fixed so that the input, proof and
+    /// verifier message are deterministic. The vectors were generated by this crate rather than by
+    /// an independent implementation, so they detect unintended changes to the encoding, proof or
+    /// verifier of a type but are not known-answer tests for its correctness.
+    #[derive(Debug, Deserialize)]
+    struct FlpRegressionVector<M> {
+        measurement: M,
+        prove_rand: Vec<u64>,
+        joint_rand: Vec<u64>,
+        query_rand: Vec<u64>,
+        input: Vec<u64>,
+        proof: Vec<u64>,
+        verifier: Vec<u64>,
+        output: Vec<u64>,
+    }
+
+    fn field_vec(values: &[u64]) -> Vec<TestField> {
+        values.iter().map(|v| TestField::from(*v)).collect()
+    }
+
+    fn check_regression_vector<T>(typ: &T, t: &FlpRegressionVector<T::Measurement>)
     where
-        F: Fn(usize) -> CountVec<TestField, S>,
-        S: 'static + ParallelSumGadget<TestField, BlindPolyEval<TestField>> + Eq,
+        T: Type<Field = TestField>,
     {
-        let one = TestField::one();
-        let nine = TestField::from(9);
+        let prove_rand = field_vec(&t.prove_rand);
+        let joint_rand = field_vec(&t.joint_rand);
+        let query_rand = field_vec(&t.query_rand);
 
-        // Test on valid inputs.
-        for len in 0..10 {
-            let count_vec = f(len);
-            flp_validity_test(
-                &count_vec,
-                &count_vec.encode(&vec![1; len]).unwrap(),
-                &ValidityTestCase::<TestField> {
-                    expect_valid: true,
-                    expected_output: Some(vec![one; len]),
-                },
-            )
-            .unwrap();
-        }
+        let input = typ.encode(&t.measurement).unwrap();
+        assert_eq!(input, field_vec(&t.input));
 
-        let len = 100;
-        let count_vec = f(len);
-        flp_validity_test(
-            &count_vec,
-            &count_vec.encode(&vec![1; len]).unwrap(),
-            &ValidityTestCase::<TestField> {
-                expect_valid: true,
-                expected_output: Some(vec![one; len]),
-            },
-        )
-        .unwrap();
+        let proof = typ.prove(&input, &prove_rand, &joint_rand).unwrap();
+        assert_eq!(proof, field_vec(&t.proof));
 
-        // Test on invalid inputs.
-        for len in 1..10 {
-            let count_vec = f(len);
-            flp_validity_test(
-                &count_vec,
-                &vec![nine; len],
-                &ValidityTestCase::<TestField> {
-                    expect_valid: false,
-                    expected_output: None,
-                },
-            )
+        let verifier = typ
+            .query(&input, &proof, &query_rand, &joint_rand, 1)
             .unwrap();
-        }
+        assert_eq!(verifier, field_vec(&t.verifier));
+        assert!(typ.decide(&verifier).unwrap());
+
+        assert_eq!(typ.truncate(input).unwrap(), field_vec(&t.output));
     }
 
     #[test]
-    fn test_count_vec_serial() {
-        test_count_vec(CountVec::<TestField, ParallelSum<TestField, BlindPolyEval<TestField>>>::new)
+    fn test_weighted_sum_regression_vector() {
+        let t: FlpRegressionVector<(u64, u64)> =
+            serde_json::from_str(include_str!("testdata/flp_weighted_sum.json")).unwrap();
+        check_regression_vector(&WeightedSum::<TestField>::new(3, 2).unwrap(), &t);
     }
 
     #[test]
-    #[cfg(feature = "multithreaded")]
-    fn test_count_vec_parallel() {
-        test_count_vec(CountVec::<TestField, ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>>::new)
+    fn test_fixed_point_bounded_l2_vec_sum_regression_vector() {
+        let t: FlpRegressionVector<Vec<f64>> = serde_json::from_str(include_str!(
+            "testdata/flp_fixed_point_bounded_l2_vec_sum.json"
+        ))
+        .unwrap();
+        check_regression_vector(
+            &FixedPointBoundedL2VecSum::<
+                TestField,
+                ParallelSum<TestField, BlindPolyEval<TestField>>,
+                ParallelSum<TestField, Mul<TestField>>,
+            >::new(4, 3)
+            .unwrap(),
+            &t,
+        );
+
+        #[cfg(feature = "multithreaded")]
+        check_regression_vector(
+            &FixedPointBoundedL2VecSum::<
+                TestField,
+                ParallelSumMultithreaded<TestField, BlindPolyEval<TestField>>,
+                ParallelSumMultithreaded<TestField, Mul<TestField>>,
+            >::new(4, 3)
+            .unwrap(),
+            &t,
+        );
     }
 
     fn flp_validity_test<T: Type>(
@@ -10,7 +10,7 @@ use crate::codec::{CodecError, Decode, Encode, ParameterizedDecode};
 use crate::field::{FieldElement, FieldError};
 use crate::flp::FlpError;
 use crate::prng::PrngError;
-use crate::vdaf::prg::Seed;
+use crate::vdaf::prg::{RandSource, Seed};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt::Debug;
@@ -42,6 +42,88 @@ pub enum VdafError {
     /// failure when calling getrandom().
     #[error("getrandom: {0}")]
     GetRandom(#[from] getrandom::Error),
+
+    /// A message could not be encoded or decoded.
+    #[error("codec error: {0}")]
+    Codec(#[from] CodecError),
+
+    /// The VDAF was instantiated with invalid parameters.
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    /// The number of Aggregators is not supported by the VDAF.
+    #[error("invalid number of aggregators: {0}")]
+    NumAggregators(String),
+
+    /// The measurement cannot be sharded.
+    #[error("invalid measurement: {0}")]
+    InvalidMeasurement(String),
+
+    /// The Prepare process failed. The report should be rejected.
+    #[error("prepare error: {0}")]
+    Prepare(#[from] PrepareError),
+
+    /// Output shares or aggregate shares could not be aggregated or unsharded.
+    #[error("aggregate error: {0}")]
+    Aggregate(String),
+}
+
+/// The reason for which the Prepare process failed.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum PrepareError {
+    /// The input share is invalid: the proof of its validity was rejected.
+    #[error("verification failed")]
+    VerifyFailed,
+
+    /// The Aggregators did not derive the same joint randomness, or the parameters needed to
+    /// derive it were missing or unexpected.
+    #[error("joint randomness mismatch")]
+    JointRandMismatch,
+
+    /// A share or message has the wrong length.
+    #[error("unexpected {what} length: got {got}; want {want}")]
+    LengthMismatch {
+        /// The kind of share or message.
+        what: &'static str,
+
+        /// The length that was received.
+        got: usize,
+
+        /// The expected length.
+        want: usize,
+    },
+
+    /// The aggregation parameter is invalid.
+    #[error("invalid aggregation parameter: {0}")]
+    BadAggParam(String),
+
+    /// The number of prepare messages combined in a round is not the number of Aggregators.
+    #[error("unexpected message count: got {got}; want {want}")]
+    MessageCount {
+        /// The number of messages that were received.
+        got: usize,
+
+        /// The number of Aggregators.
+        want: usize,
+    },
+
+    /// A prepare message is inconsistent with the other messages or with the state of the
+    /// Aggregator.
+    #[error("invalid prepare message: {0}")]
+    InvalidMessage(String),
+
+    /// The state of the Aggregator cannot be advanced with the given input.
+    #[error("invalid state transition")]
+    InvalidStateTransition,
+
+    /// A cached report does not match the input share being prepared, e.g., because a nonce was
+    /// reused for a different report.
+    #[error("cached report does not match the input share")]
+    CacheMismatch,
+
+    /// The Aggregators did not finish the Prepare process in the same round.
+    #[error("aggregators finished the prepare process in different rounds")]
+    RoundMismatch,
 }
 
 /// An additive share of a vector of field elements.
@@ -136,7 +218,15 @@ where
     type AggregateShare: Aggregatable<OutputShare = Self::OutputShare> + for<'a> TryFrom<&'a [u8]>;
 
     /// Generates the long-lived parameters used by the Clients and Aggregators.
-    fn setup(&self) -> Result<(Self::PublicParam, Vec<Self::VerifyParam>), VdafError>;
+    fn setup(&self) -> Result<(Self::PublicParam, Vec<Self::VerifyParam>), VdafError> {
+        self.setup_with_rng(&mut getrandom::getrandom)
+    }
+
+    /// Like [`Vdaf::setup`], except that the randomness is drawn from `rng`.
+    fn setup_with_rng(
+        &self,
+        rng: &mut dyn RandSource,
+    ) -> Result<(Self::PublicParam, Vec<Self::VerifyParam>), VdafError>;
 
     /// The number of Aggregators. The Client generates as many input shares as there are
     /// Aggregators.
@@ -153,6 +243,16 @@ where
         &self,
         public_param: &Self::PublicParam,
         measurement: &Self::Measurement,
+    ) -> Result<Vec<Self::InputShare>, VdafError> {
+        self.shard_with_rng(public_param, measurement, &mut getrandom::getrandom)
+    }
+
+    /// Like [`Client::shard`], except that the randomness is drawn from `rng`.
+    fn shard_with_rng(
+        &self,
+        public_param: &Self::PublicParam,
+        measurement: &Self::Measurement,
+        rng: &mut dyn RandSource,
     ) -> Result<Vec<Self::InputShare>, VdafError>;
 }
 
@@ -161,8 +261,12 @@ pub trait Aggregator: Vdaf
 where
     for<'a> &'a Self::AggregateShare: Into<Vec<u8>>,
 {
-    /// State of the Aggregator during the Prepare process.
-    type PrepareStep: Clone + Debug;
+    /// State of the Aggregator during the Prepare process. The state can be encoded between rounds
+    /// (e.g., to persist it) and decoded given the VDAF and the Aggregator's verification parameter.
+    type PrepareStep: Clone
+        + Debug
+        + Encode
+        + for<'a> ParameterizedDecode<(&'a Self, &'a Self::VerifyParam)>;
 
     /// The type of messages exchanged among the Aggregators during the Prepare process.
     type PrepareMessage: Clone + Debug + ParameterizedDecode<Self::PrepareStep> + Encode;
@@ -197,6 +301,45 @@ where
         input: Option<Self::PrepareMessage>,
     ) -> PrepareTransition<Self::PrepareStep, Self::PrepareMessage, Self::OutputShare>;
 
+    /// Begins the Prepare process for a batch of reports, each given by its nonce and input share.
+    /// For each report, this is equivalent to calling [`Aggregator::prepare_init`] followed by
+    /// [`Aggregator::prepare_step`] with no input, except that an error from `prepare_init` is
+    /// returned as [`PrepareTransition::Fail`]. Implementations may override this method to
+    /// amortize the cost of preparation across the batch.
+    #[allow(clippy::type_complexity)]
+    fn prepare_init_batch<N: AsRef<[u8]>>(
+        &self,
+        verify_param: &Self::VerifyParam,
+        agg_param: &Self::AggregationParam,
+        reports: &[(N, Self::InputShare)],
+    ) -> Vec<PrepareTransition<Self::PrepareStep, Self::PrepareMessage, Self::OutputShare>> {
+        reports
+            .iter()
+            .map(|(nonce, input_share)| {
+                match self.prepare_init(verify_param, agg_param, nonce.as_ref(), input_share) {
+                    Ok(step) => self.prepare_step(step, None),
+                    Err(err) => PrepareTransition::Fail(err),
+                }
+            })
+            .collect()
+    }
+
+    /// Computes the next state transition of each report in a batch from its current state and
+    /// the previous round of input messages, as in [`Aggregator::prepare_step`].
+    #[allow(clippy::type_complexity)]
+    fn prepare_step_batch<M>(
+        &self,
+        inputs: M,
+    ) -> Vec<PrepareTransition<Self::PrepareStep, Self::PrepareMessage, Self::OutputShare>>
+    where
+        M: IntoIterator<Item = (Self::PrepareStep, Option<Self::PrepareMessage>)>,
+    {
+        inputs
+            .into_iter()
+            .map(|(state, input)| self.prepare_step(state, input))
+            .collect()
+    }
+
     /// Aggregates a sequence of output shares into an aggregate share.
     fn aggregate<M: IntoIterator<Item = Self::OutputShare>>(
         &self,
@@ -315,7 +458,7 @@ impl<F: FieldElement> Aggregatable for AggregateShare<F> {
 impl<F: FieldElement> AggregateShare<F> {
     fn sum(&mut self, other: &[F]) -> Result<(), VdafError> {
         if self.0.len() != other.len() {
-            return Err(VdafError::Uncategorized(format!(
+            return Err(VdafError::Aggregate(format!(
                 "cannot sum shares of different lengths (left = {}, right = {}",
                 self.0.len(),
                 other.len()
@@ -360,6 +503,61 @@ fn fieldvec_to_vec<F: FieldElement, T: AsRef<[F]>>(val: T) -> Vec<u8> {
     F::slice_into_byte_vec(val.as_ref())
 }
 
+/// Runs the Prepare process for a single report with each Aggregator in the same process, starting
+/// from the state returned by [`Aggregator::prepare_init`] (or a variant of it) for each
+/// Aggregator. Returns the output share of each Aggregator, or an error if an Aggregator fails or
+/// the Aggregators do not finish in the same round.
+///
+/// Each prepare message is passed to `on_message`, together with the round (starting from 0), the
+/// index of the Aggregator that sent it and the Aggregator's new state, before the messages are
+/// combined by [`Aggregator::prepare_preprocess`]. The message returned by `on_message` is used in
+/// its place. This can be used to record the messages or to tamper with them.
+pub fn run_prepare<V, F>(
+    vdaf: &V,
+    mut states: Vec<V::PrepareStep>,
+    mut on_message: F,
+) -> Result<Vec<V::OutputShare>, VdafError>
+where
+    V: Aggregator,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    F: FnMut(
+        usize,
+        usize,
+        &V::PrepareStep,
+        V::PrepareMessage,
+    ) -> Result<V::PrepareMessage, VdafError>,
+{
+    let mut round = 0;
+    let mut inbound = None;
+    loop {
+        let mut next_states = Vec::with_capacity(states.len());
+        let mut outbound = Vec::with_capacity(states.len());
+        let mut out_shares = Vec::with_capacity(states.len());
+        for (aggregator, state) in states.into_iter().enumerate() {
+            match vdaf.prepare_step(state, inbound.clone()) {
+                PrepareTransition::Continue(new_state, msg) => {
+                    outbound.push(on_message(round, aggregator, &new_state, msg)?);
+                    next_states.push(new_state);
+                }
+                PrepareTransition::Finish(out_share) => out_shares.push(out_share),
+                PrepareTransition::Fail(err) => return Err(err),
+            }
+        }
+
+        if next_states.is_empty() {
+            // Each Aggregator recovered an output share.
+            return Ok(out_shares);
+        } else if !out_shares.is_empty() {
+            return Err(PrepareError::RoundMismatch.into());
+        }
+
+        // Another round is required before output shares are computed.
+        inbound = Some(vdaf.prepare_preprocess(outbound)?);
+        states = next_states;
+        round += 1;
+    }
+}
+
 #[cfg(test)]
 pub(crate) fn run_vdaf<V, M>(
     vdaf: &V,
@@ -426,40 +624,12 @@ where
         states.push(state);
     }
 
-    let mut inbound = None;
-    let mut out_shares = Vec::new();
-    loop {
-        let mut outbound = Vec::new();
-        for state in states.iter_mut() {
-            match vdaf.prepare_step(state.clone(), inbound.clone()) {
-                PrepareTransition::Continue(new_state, msg) => {
-                    outbound.push(msg.get_encoded());
-                    *state = new_state
-                }
-                PrepareTransition::Finish(out_share) => {
-                    out_shares.push(out_share);
-                }
-                PrepareTransition::Fail(err) => {
-                    return Err(err);
-                }
-            }
-        }
-
-        if outbound.len() == vdaf.num_aggregators() {
-            // Another round is required before output shares are computed.
-            inbound = Some(vdaf.prepare_preprocess(outbound.iter().map(|encoded| {
-                V::PrepareMessage::get_decoded_with_param(&states[0], encoded)
-                    .expect("failed to decode papare message")
-            }))?);
-        } else if outbound.is_empty() {
-            // Each Aggregator recovered an output share.
-            break;
-        } else {
-            panic!("Aggregators did not finish the prepare phase at the same time");
-        }
-    }
-
-    Ok(out_shares)
+    run_prepare(vdaf, states, |_round, _aggregator, state, msg| {
+        Ok(
+            V::PrepareMessage::get_decoded_with_param(state, &msg.get_encoded())
+                .expect("failed to decode prepare message"),
+        )
+    })
 }
 
 #[cfg(test)]
@@ -506,3 +676,8 @@ pub mod prg;
 pub mod prio3;
 #[cfg(test)]
 mod prio3_test;
+pub mod simulation;
+#[cfg(any(feature = "test-vector", test))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-vector")))]
+pub mod test_vector;
+pub mod topology;
@@ -6,20 +6,23 @@
 //! development. Thus this code should be regarded as experimental and not compliant with any
 //! existing speciication.
 //!
-//! TODO Make the input shares stateful so that applications can efficiently evaluate the IDPF over
-//! multiple rounds. Question: Will this require API changes to [`crate::vdaf::Vdaf`]?
+//! Aggregators that evaluate the IDPF over multiple rounds can use
+//! [`Poplar1::prepare_init_with_cache`] to carry the evaluation state of each report from one round
+//! to the next. [`HeavyHitters`] uses this to compute the heavy hitters level by level.
 //!
-//! TODO Update trait [`Idpf`] so that the IDPF can have a different field type at the leaves than
-//! at the inner nodes.
+//! The IDPF may use a different field for the leaves of the prefix tree than for the inner nodes.
+//! For example, [`TreeIdpf`] can use [`Field64`](crate::field::Field64) to count prefixes and
+//! [`Field128`](crate::field::Field128) to count full inputs.
 //!
-//! TODO Implement the efficient IDPF of [[BBCG+21]]. [`ToyIdpf`] is not space efficient and is
-//! merely intended as a proof-of-concept.
+//! The tree-based IDPF of [[BBCG+21]] is implemented by [`TreeIdpf`]. [`ToyIdpf`] is not space
+//! efficient and is merely intended as a proof-of-concept.
 //!
 //! [BBCG+21]: https://eprint.iacr.org/2021/017
 //! [draft-patton-cfrg-vdaf-01]: https://datatracker.ietf.org/doc/html/draft-patton-cfrg-vdaf-01
 
+use std::any::Any;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
 use std::io::Cursor;
@@ -30,67 +33,83 @@ use crate::codec::{
     decode_u16_items, decode_u24_items, encode_u16_items, encode_u24_items, CodecError, Decode,
     Encode, ParameterizedDecode,
 };
-use crate::field::{split_vector, FieldElement};
+use crate::field::FieldElement;
 use crate::fp::log2;
 use crate::prng::Prng;
-use crate::vdaf::prg::{Prg, Seed};
+use crate::vdaf::prg::{Prg, PrgAes128, PrgShake256, RandSource, Seed, SeedStream};
+#[cfg(any(feature = "test-vector", test))]
+use crate::vdaf::test_vector::{test_vec_field_vec, TestVectorVdaf};
 use crate::vdaf::{
-    Aggregatable, AggregateShare, Aggregator, Client, Collector, OutputShare, PrepareTransition,
-    Share, ShareDecodingParameter, Vdaf, VdafError,
+    run_prepare, Aggregatable, AggregateShare, Aggregator, Client, Collector, OutputShare,
+    PrepareError, PrepareTransition, Share, ShareDecodingParameter, Vdaf, VdafError,
 };
 
 /// An input for an IDPF ([`Idpf`]).
 ///
 /// TODO Make this an associated type of `Idpf`.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct IdpfInput {
-    index: usize,
-    level: usize,
+    /// The bits of the input, in the order in which they are consumed by the IDPF. The length of
+    /// the input is the number of bits.
+    bits: Vec<bool>,
 }
 
 impl IdpfInput {
     /// Constructs an IDPF input using the first `level` bits of `data`.
     pub fn new(data: &[u8], level: usize) -> Result<Self, VdafError> {
         if level > data.len() << 3 {
-            return Err(VdafError::Uncategorized(format!(
+            return Err(VdafError::InvalidMeasurement(format!(
                 "desired bit length ({} bits) exceeds data length ({} bytes)",
                 level,
                 data.len()
             )));
         }
 
-        let mut index = 0;
-        let mut i = 0;
-        for byte in data {
-            for j in 0..8 {
-                let bit = (byte >> j) & 1;
-                if i < level {
-                    index |= (bit as usize) << i;
-                }
-                i += 1;
-            }
-        }
+        let bits = (0..level)
+            .map(|i| (data[i >> 3] >> (i & 7)) & 1 == 1)
+            .collect();
+
+        Ok(Self { bits })
+    }
+
+    /// Returns the length of the input in bits.
+    pub fn level(&self) -> usize {
+        self.bits.len()
+    }
 
-        Ok(Self { index, level })
+    /// Construct a new input by appending `bit` to `self`.
+    fn extend(&self, bit: bool) -> Self {
+        let mut bits = self.bits.clone();
+        bits.push(bit);
+        Self { bits }
     }
 
     /// Construct a new input that is a prefix of `self`. Bounds checking is performed by the
     /// caller.
     fn prefix(&self, level: usize) -> Self {
-        let index = self.index & ((1 << level) - 1);
-        Self { index, level }
+        Self {
+            bits: self.bits[..level].to_vec(),
+        }
     }
 
-    /// Return the position of `self` in the look-up table of `ToyIdpf`.
+    /// Return the position of `self` in the look-up table of `ToyIdpf`. The caller must ensure
+    /// the input is shorter than the bit length of `usize`.
     fn data_index(&self) -> usize {
-        self.index | (1 << self.level)
+        self.bits
+            .iter()
+            .enumerate()
+            .fold(1 << self.level(), |index, (i, bit)| {
+                index | ((*bit as usize) << i)
+            })
     }
 }
 
 impl Ord for IdpfInput {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.level.cmp(&other.level) {
-            Ordering::Equal => self.index.cmp(&other.index),
+        // Inputs of the same length are ordered as integers, where the first bit is the least
+        // significant.
+        match self.level().cmp(&other.level()) {
+            Ordering::Equal => self.bits.iter().rev().cmp(other.bits.iter().rev()),
             ord => ord,
         }
     }
@@ -102,22 +121,47 @@ impl PartialOrd for IdpfInput {
     }
 }
 
+/// An [`IdpfInput`] is encoded as its length in bits as a `u64`, followed by its bits packed into
+/// bytes, least significant bit first, with a `u16` length prefix.
+///
+/// This encoding replaced the previous one, a `u64` index followed by a `u64` level, when inputs
+/// longer than 64 bits were introduced. Inputs encoded by previous releases do not decode.
 impl Encode for IdpfInput {
     fn encode(&self, bytes: &mut Vec<u8>) {
-        (self.index as u64).encode(bytes);
-        (self.level as u64).encode(bytes);
+        let mut data = vec![0_u8; (self.level() + 7) >> 3];
+        for (i, bit) in self.bits.iter().enumerate() {
+            data[i >> 3] |= (*bit as u8) << (i & 7);
+        }
+
+        (self.level() as u64).encode(bytes);
+        encode_u16_items(bytes, &(), &data);
     }
 }
 
 impl Decode for IdpfInput {
     fn decode(bytes: &mut Cursor<&[u8]>) -> Result<Self, CodecError> {
-        let index = u64::decode(bytes)? as usize;
-        let level = u64::decode(bytes)? as usize;
+        let level =
+            usize::try_from(u64::decode(bytes)?).map_err(|_| CodecError::UnexpectedValue)?;
+        let data: Vec<u8> = decode_u16_items(&(), bytes)?;
+        if data.len() != level.checked_add(7).ok_or(CodecError::UnexpectedValue)? >> 3 {
+            return Err(CodecError::UnexpectedValue);
+        }
 
-        Ok(Self { index, level })
+        Self::new(&data, level).map_err(|_| CodecError::UnexpectedValue)
     }
 }
 
+/// The output of an IDPF share on a prefix. The output is an element of [`Idpf::InnerField`] if
+/// the prefix is shorter than the input and an element of [`Idpf::LeafField`] otherwise.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IdpfValue<FI, FL, const OUT_LEN: usize> {
+    /// The output on an inner node of the prefix tree.
+    Inner([FI; OUT_LEN]),
+
+    /// The output on a leaf of the prefix tree.
+    Leaf([FL; OUT_LEN]),
+}
+
 /// An Incremental Distributed Point Function (IDPF), as defined by [[BBCG+21]].
 ///
 /// [BBCG+21]: https://eprint.iacr.org/2021/017
@@ -126,27 +170,89 @@ impl Decode for IdpfInput {
 pub trait Idpf<const KEY_LEN: usize, const OUT_LEN: usize>:
     Sized + Clone + Debug + Encode + Decode
 {
-    /// The finite field over which the IDPF is defined.
-    //
-    // NOTE(cjpatton) The IDPF of [BBCG+21] might use different fields for different levels of the
-    // prefix tree.
-    type Field: FieldElement;
-
-    /// Generate and return a sequence of IDPF shares for `input`. Parameter `output` is an
-    /// iterator that is invoked to get the output value for each successive level of the prefix
-    /// tree.
-    fn gen<M: IntoIterator<Item = [Self::Field; OUT_LEN]>>(
+    /// The finite field used for the output on the inner nodes of the prefix tree.
+    type InnerField: FieldElement;
+
+    /// The finite field used for the output on the leaves of the prefix tree.
+    type LeafField: FieldElement;
+
+    /// Generate and return a sequence of IDPF shares for `input`. Parameter `inner_values` is an
+    /// iterator that is invoked to get the output value for each successive inner level of the
+    /// prefix tree, i.e., for each proper prefix of `input`. Parameter `leaf_value` is the output
+    /// value for `input` itself.
+    fn gen<M: IntoIterator<Item = [Self::InnerField; OUT_LEN]>>(
         input: &IdpfInput,
-        values: M,
+        inner_values: M,
+        leaf_value: [Self::LeafField; OUT_LEN],
+    ) -> Result<[Self; KEY_LEN], VdafError> {
+        Self::gen_with_rng(input, inner_values, leaf_value, &mut getrandom::getrandom)
+    }
+
+    /// Like [`Idpf::gen`], except that the randomness used to generate the shares is read from
+    /// `rng`.
+    fn gen_with_rng<M: IntoIterator<Item = [Self::InnerField; OUT_LEN]>>(
+        input: &IdpfInput,
+        inner_values: M,
+        leaf_value: [Self::LeafField; OUT_LEN],
+        rng: &mut dyn RandSource,
     ) -> Result<[Self; KEY_LEN], VdafError>;
 
+    /// Returns the length in bits of the input for which this IDPF share was generated.
+    fn level(&self) -> usize;
+
     /// Evaluate an IDPF share on `prefix`.
-    fn eval(&self, prefix: &IdpfInput) -> Result<[Self::Field; OUT_LEN], VdafError>;
+    fn eval(
+        &self,
+        prefix: &IdpfInput,
+    ) -> Result<IdpfValue<Self::InnerField, Self::LeafField, OUT_LEN>, VdafError>;
+
+    /// Evaluate an IDPF share on `prefix`, reusing the intermediate state stored in `cache` by
+    /// previous evaluations on shorter prefixes. This is intended for evaluating the share one
+    /// level of the prefix tree at a time.
+    ///
+    /// The default implementation ignores the cache and calls [`Idpf::eval`].
+    fn eval_with_cache(
+        &self,
+        prefix: &IdpfInput,
+        _cache: &mut IdpfCache,
+    ) -> Result<IdpfValue<Self::InnerField, Self::LeafField, OUT_LEN>, VdafError> {
+        self.eval(prefix)
+    }
+}
+
+/// State carried across calls to [`Idpf::eval_with_cache`] for the same IDPF share. Each
+/// implementation of [`Idpf`] decides what, if anything, to store in it.
+#[derive(Default)]
+pub struct IdpfCache(Option<Box<dyn Any + Send + Sync>>);
+
+impl IdpfCache {
+    /// Construct an empty cache.
+    pub fn new() -> Self {
+        Self(None)
+    }
+
+    /// Returns the state stored in the cache. If the cache is empty or holds state of a different
+    /// type, then it is first reset to `T::default()`.
+    pub fn get_or_default<T: Any + Send + Sync + Default>(&mut self) -> &mut T {
+        if !matches!(&self.0, Some(state) if (**state).is::<T>()) {
+            self.0 = Some(Box::new(T::default()));
+        }
+        self.0.as_mut().unwrap().downcast_mut().unwrap()
+    }
+}
+
+impl Debug for IdpfCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("IdpfCache")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 /// A "toy" IDPF used for demonstration purposes. The space consumed by each share is `O(2^n)`,
 /// where `n` is the length of the input. The size of each share is restricted to 1MB, so this IDPF
-/// is only suitable for very short inputs.
+/// is only suitable for very short inputs. The same field is used for the inner nodes and the
+/// leaves.
 //
 // NOTE(cjpatton) It would be straight-forward to generalize this construction to any `KEY_LEN` and
 // `OUT_LEN`.
@@ -158,60 +264,93 @@ pub struct ToyIdpf<F> {
 }
 
 impl<F: FieldElement> Idpf<2, 2> for ToyIdpf<F> {
-    type Field = F;
+    type InnerField = F;
+    type LeafField = F;
 
-    fn gen<M: IntoIterator<Item = [Self::Field; 2]>>(
+    fn gen_with_rng<M: IntoIterator<Item = [F; 2]>>(
         input: &IdpfInput,
-        values: M,
+        inner_values: M,
+        leaf_value: [F; 2],
+        rng: &mut dyn RandSource,
     ) -> Result<[Self; 2], VdafError> {
         const MAX_DATA_BYTES: usize = 1024 * 1024; // 1MB
 
         let max_input_len =
             usize::try_from(log2((MAX_DATA_BYTES / F::ENCODED_SIZE) as u128)).unwrap();
-        if input.level > max_input_len {
-            return Err(VdafError::Uncategorized(format!(
+        if input.level() > max_input_len {
+            return Err(VdafError::InvalidMeasurement(format!(
                 "input length ({}) exceeds maximum of ({})",
-                input.level, max_input_len
+                input.level(),
+                max_input_len
             )));
         }
 
-        let data_len = 1 << (input.level + 1);
+        let data_len = 1 << (input.level() + 1);
         let mut data0 = vec![F::zero(); data_len];
         let mut data1 = vec![F::zero(); data_len];
-        let mut values = values.into_iter();
-        for level in 0..input.level + 1 {
-            let value = values.next().unwrap();
+        let mut inner_values = inner_values.into_iter();
+        for level in 0..input.level() + 1 {
+            let value = if level < input.level() {
+                inner_values.next().ok_or_else(|| {
+                    VdafError::InvalidMeasurement(format!("missing IDPF value for level {}", level))
+                })?
+            } else {
+                leaf_value
+            };
             let index = input.prefix(level).data_index();
             data0[index] = value[0];
             data1[index] = value[1];
         }
 
-        let mut data0 = split_vector(&data0, 2)?.into_iter();
-        let mut data1 = split_vector(&data1, 2)?.into_iter();
+        // Split each look-up table into two shares.
+        let mut prng: Prng<F, _> = Prng::from_seed_stream(PrgAes128::seed_stream(
+            &Seed::from_rand_source(rng)?,
+            b"toy idpf",
+        ));
+        let data0_share: Vec<F> = prng.by_ref().take(data_len).collect();
+        let data1_share: Vec<F> = prng.take(data_len).collect();
+        for (x, y) in data0.iter_mut().zip(data0_share.iter()) {
+            *x -= *y;
+        }
+        for (x, y) in data1.iter_mut().zip(data1_share.iter()) {
+            *x -= *y;
+        }
+
         Ok([
             ToyIdpf {
-                data0: data0.next().unwrap(),
-                data1: data1.next().unwrap(),
-                level: input.level,
+                data0,
+                data1,
+                level: input.level(),
             },
             ToyIdpf {
-                data0: data0.next().unwrap(),
-                data1: data1.next().unwrap(),
-                level: input.level,
+                data0: data0_share,
+                data1: data1_share,
+                level: input.level(),
             },
         ])
     }
 
-    fn eval(&self, prefix: &IdpfInput) -> Result<[F; 2], VdafError> {
-        if prefix.level > self.level {
-            return Err(VdafError::Uncategorized(format!(
+    fn level(&self) -> usize {
+        self.level
+    }
+
+    fn eval(&self, prefix: &IdpfInput) -> Result<IdpfValue<F, F, 2>, VdafError> {
+        if prefix.level() > self.level {
+            return Err(PrepareError::BadAggParam(format!(
                 "prefix length ({}) exceeds input length ({})",
-                prefix.level, self.level
-            )));
+                prefix.level(),
+                self.level
+            ))
+            .into());
         }
 
         let index = prefix.data_index();
-        Ok([self.data0[index], self.data1[index]])
+        let value = [self.data0[index], self.data1[index]];
+        if prefix.level() == self.level {
+            Ok(IdpfValue::Leaf(value))
+        } else {
+            Ok(IdpfValue::Inner(value))
+        }
     }
 }
 
@@ -237,6 +376,325 @@ impl<F: FieldElement> Decode for ToyIdpf<F> {
     }
 }
 
+/// The tree-based IDPF of [[BBCG+21]]. Each key consists of a seed for the root of a binary tree
+/// and a sequence of correction words, one for each level of the tree. Evaluating a key on a
+/// prefix walks the path from the root to the prefix, expanding the seed at each node into the
+/// seeds of its children using the PRG `P`. The size of each key is linear in the length of the
+/// input.
+///
+/// The output on the inner nodes of the tree is in field `FI` and the output on the leaves is in
+/// field `FL`.
+///
+/// [BBCG+21]: https://eprint.iacr.org/2021/017
+#[derive(Debug, Clone)]
+pub struct TreeIdpf<FI, FL, P, const L: usize> {
+    /// The seed at the root of the tree.
+    seed: Seed<L>,
+
+    /// The control bit at the root of the tree. This is `false` for the first key and `true` for
+    /// the second.
+    control_bit: bool,
+
+    /// For each level of the tree except the last, the correction words for the seed and the
+    /// control bits of the children of each node.
+    node_cws: Vec<(Seed<L>, [bool; 2])>,
+
+    /// For each level of the tree except the last, the correction word for the output.
+    value_cws: Vec<[FI; 2]>,
+
+    /// The correction word for the output on the last level of the tree.
+    leaf_cw: [FL; 2],
+
+    phantom: PhantomData<P>,
+}
+
+impl<FI, FL, P, const L: usize> TreeIdpf<FI, FL, P, L>
+where
+    FI: FieldElement,
+    FL: FieldElement,
+    P: Prg<L>,
+{
+    /// Expand the seed of a node into the seeds and control bits of its left and right children.
+    fn extend(seed: &Seed<L>) -> ([Seed<L>; 2], [bool; 2]) {
+        let mut seed_stream = P::seed_stream(seed, b"idpf extend");
+        let mut seeds = [Seed::uninitialized(), Seed::uninitialized()];
+        seed_stream.fill(&mut seeds[0].0);
+        seed_stream.fill(&mut seeds[1].0);
+        let mut control_bits = [0];
+        seed_stream.fill(&mut control_bits);
+        (seeds, [control_bits[0] & 1 == 1, control_bits[0] & 2 == 2])
+    }
+
+    /// Convert the seed of a node into the (uncorrected) share of its output.
+    fn convert<F: FieldElement>(seed: &Seed<L>) -> [F; 2] {
+        let mut prng: Prng<F, _> = Prng::from_seed_stream(P::seed_stream(seed, b"idpf convert"));
+        [prng.get(), prng.get()]
+    }
+
+    /// Compute the correction word for the output on a node on the path to the input, given the
+    /// seeds and control bits of the node for each key.
+    fn value_cw<F: FieldElement>(
+        value: [F; 2],
+        seeds: &[Seed<L>; 2],
+        control_bits: [bool; 2],
+    ) -> [F; 2] {
+        // The control bits of the nodes on the path to the input differ, so exactly one
+        // aggregator applies the correction. Choose it so that the output shares sum to `value`.
+        let y0 = Self::convert::<F>(&seeds[0]);
+        let y1 = Self::convert::<F>(&seeds[1]);
+        let mut value_cw = [F::zero(); 2];
+        for i in 0..2 {
+            value_cw[i] = value[i] - y0[i] + y1[i];
+            if control_bits[1] {
+                value_cw[i] = -value_cw[i];
+            }
+        }
+        value_cw
+    }
+
+    /// Compute this key's share of the output on a node, given the node's seed and control bit
+    /// and the correction word for its level.
+    fn output<F: FieldElement>(
+        &self,
+        seed: &Seed<L>,
+        control_bit: bool,
+        value_cw: [F; 2],
+    ) -> [F; 2] {
+        let mut output = Self::convert::<F>(seed);
+        for (y, value_cw) in output.iter_mut().zip(value_cw) {
+            if control_bit {
+                *y += value_cw;
+            }
+
+            // The second aggregator negates its output share.
+            if self.control_bit {
+                *y = -*y;
+            }
+        }
+        output
+    }
+}
+
+/// The state stored by [`TreeIdpf::eval_with_cache`]. It holds the seed and control bit of each node
+/// of the prefix tree that was evaluated on the most recent two levels.
+#[derive(Clone, Debug, Default)]
+struct TreeIdpfCache<const L: usize> {
+    nodes: BTreeMap<IdpfInput, (Seed<L>, bool)>,
+}
+
+impl<FI, FL, P, const L: usize> Idpf<2, 2> for TreeIdpf<FI, FL, P, L>
+where
+    FI: FieldElement,
+    FL: FieldElement,
+    P: Prg<L>,
+{
+    type InnerField = FI;
+    type LeafField = FL;
+
+    fn gen_with_rng<M: IntoIterator<Item = [FI; 2]>>(
+        input: &IdpfInput,
+        inner_values: M,
+        leaf_value: [FL; 2],
+        rng: &mut dyn RandSource,
+    ) -> Result<[Self; 2], VdafError> {
+        let root_seeds = [Seed::from_rand_source(rng)?, Seed::from_rand_source(rng)?];
+
+        let mut inner_values = inner_values.into_iter();
+        let mut seeds = root_seeds.clone();
+        let mut control_bits = [false, true];
+        let mut node_cws = Vec::with_capacity(input.level());
+        let mut value_cws = Vec::with_capacity(input.level());
+        for level in 0..input.level() {
+            let value = inner_values.next().ok_or_else(|| {
+                VdafError::InvalidMeasurement(format!("missing IDPF value for level {}", level))
+            })?;
+            value_cws.push(Self::value_cw(value, &seeds, control_bits));
+
+            // Compute the correction words for the next level. After correction, the seeds and
+            // control bits of the children that are off the path are equal.
+            let keep = input.bits[level] as usize;
+            let lose = 1 - keep;
+            let (seeds0, control_bits0) = Self::extend(&seeds[0]);
+            let (seeds1, control_bits1) = Self::extend(&seeds[1]);
+
+            let mut seed_cw = Seed::uninitialized();
+            seed_cw.xor(&seeds0[lose], &seeds1[lose]);
+            let control_bit_cws = [
+                control_bits0[0] ^ control_bits1[0] ^ (keep == 0),
+                control_bits0[1] ^ control_bits1[1] ^ (keep == 1),
+            ];
+
+            for (j, (children, child_control_bits)) in
+                [(seeds0, control_bits0), (seeds1, control_bits1)]
+                    .iter()
+                    .enumerate()
+            {
+                seeds[j] = children[keep].clone();
+                if control_bits[j] {
+                    seeds[j].xor_accumulate(&seed_cw);
+                }
+                control_bits[j] =
+                    child_control_bits[keep] ^ (control_bits[j] & control_bit_cws[keep]);
+            }
+
+            node_cws.push((seed_cw, control_bit_cws));
+        }
+        let leaf_cw = Self::value_cw(leaf_value, &seeds, control_bits);
+
+        let [seed0, seed1] = root_seeds;
+        Ok([
+            TreeIdpf {
+                seed: seed0,
+                control_bit: false,
+                node_cws: node_cws.clone(),
+                value_cws: value_cws.clone(),
+                leaf_cw,
+                phantom: PhantomData,
+            },
+            TreeIdpf {
+                seed: seed1,
+                control_bit: true,
+                node_cws,
+                value_cws,
+                leaf_cw,
+                phantom: PhantomData,
+            },
+        ])
+    }
+
+    fn level(&self) -> usize {
+        self.node_cws.len()
+    }
+
+    fn eval(&self, prefix: &IdpfInput) -> Result<IdpfValue<FI, FL, 2>, VdafError> {
+        self.eval_with_cache(prefix, &mut IdpfCache::new())
+    }
+
+    fn eval_with_cache(
+        &self,
+        prefix: &IdpfInput,
+        cache: &mut IdpfCache,
+    ) -> Result<IdpfValue<FI, FL, 2>, VdafError> {
+        if prefix.level() > self.level() {
+            return Err(PrepareError::BadAggParam(format!(
+                "prefix length ({}) exceeds input length ({})",
+                prefix.level(),
+                self.level()
+            ))
+            .into());
+        }
+
+        // Resume from the parent of the prefix if it was evaluated previously. Otherwise start
+        // from the root.
+        let cache = cache.get_or_default::<TreeIdpfCache<L>>();
+        let parent = prefix.level().checked_sub(1).and_then(|level| {
+            cache
+                .nodes
+                .get(&prefix.prefix(level))
+                .map(|node| (level, node))
+        });
+        let (start, mut seed, mut control_bit) = match parent {
+            Some((level, (seed, control_bit))) => (level, seed.clone(), *control_bit),
+            None => (0, self.seed.clone(), self.control_bit),
+        };
+
+        for (bit, (seed_cw, control_bit_cws)) in prefix.bits[start..]
+            .iter()
+            .zip(self.node_cws[start..].iter())
+        {
+            let (children, child_control_bits) = Self::extend(&seed);
+            let bit = *bit as usize;
+            seed = children[bit].clone();
+            if control_bit {
+                seed.xor_accumulate(seed_cw);
+            }
+            control_bit = child_control_bits[bit] ^ (control_bit & control_bit_cws[bit]);
+        }
+
+        // Evict nodes that are not the parent of a prefix on this level or the next.
+        while let Some(level) = cache.nodes.keys().next().map(IdpfInput::level) {
+            if level + 1 >= prefix.level() {
+                break;
+            }
+            let first = cache.nodes.keys().next().unwrap().clone();
+            cache.nodes.remove(&first);
+        }
+
+        let output = if prefix.level() == self.level() {
+            IdpfValue::Leaf(self.output(&seed, control_bit, self.leaf_cw))
+        } else {
+            IdpfValue::Inner(self.output(&seed, control_bit, self.value_cws[prefix.level()]))
+        };
+        cache.nodes.insert(prefix.clone(), (seed, control_bit));
+        Ok(output)
+    }
+}
+
+impl<FI, FL, P, const L: usize> Encode for TreeIdpf<FI, FL, P, L>
+where
+    FI: FieldElement,
+    FL: FieldElement,
+    P: Prg<L>,
+{
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.seed.encode(bytes);
+        (self.control_bit as u8).encode(bytes);
+        (self.level() as u64).encode(bytes);
+        for (seed_cw, control_bit_cws) in self.node_cws.iter() {
+            seed_cw.encode(bytes);
+            (control_bit_cws[0] as u8 | (control_bit_cws[1] as u8) << 1).encode(bytes);
+        }
+        for value_cw in self.value_cws.iter() {
+            value_cw[0].encode(bytes);
+            value_cw[1].encode(bytes);
+        }
+        self.leaf_cw[0].encode(bytes);
+        self.leaf_cw[1].encode(bytes);
+    }
+}
+
+impl<FI, FL, P, const L: usize> Decode for TreeIdpf<FI, FL, P, L>
+where
+    FI: FieldElement,
+    FL: FieldElement,
+    P: Prg<L>,
+{
+    fn decode(bytes: &mut Cursor<&[u8]>) -> Result<Self, CodecError> {
+        let seed = Seed::decode(bytes)?;
+        let control_bit = match u8::decode(bytes)? {
+            0 => false,
+            1 => true,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+        let level = u64::decode(bytes)?;
+
+        let mut node_cws = Vec::new();
+        for _ in 0..level {
+            let seed_cw = Seed::decode(bytes)?;
+            let control_bit_cws = match u8::decode(bytes)? {
+                bits if bits < 4 => [bits & 1 == 1, bits & 2 == 2],
+                _ => return Err(CodecError::UnexpectedValue),
+            };
+            node_cws.push((seed_cw, control_bit_cws));
+        }
+
+        let mut value_cws = Vec::new();
+        for _ in 0..level {
+            value_cws.push([FI::decode(bytes)?, FI::decode(bytes)?]);
+        }
+        let leaf_cw = [FL::decode(bytes)?, FL::decode(bytes)?];
+
+        Ok(Self {
+            seed,
+            control_bit,
+            node_cws,
+            value_cws,
+            leaf_cw,
+            phantom: PhantomData,
+        })
+    }
+}
+
 impl Encode for BTreeSet<IdpfInput> {
     fn encode(&self, bytes: &mut Vec<u8>) {
         // Encodes the aggregation parameter as a variable length vector of
@@ -264,8 +722,13 @@ pub struct Poplar1InputShare<I: Idpf<2, 2>, const L: usize> {
     /// of the sketching protocol.
     sketch_start_seed: Seed<L>,
 
-    /// Aggregator's share of the randomness used in the second part of the sketching protocol.
-    sketch_next: Share<I::Field, L>,
+    /// Aggregator's share of the randomness used in the second part of the sketching protocol on
+    /// the inner levels of the prefix tree.
+    sketch_next: Share<I::InnerField, L>,
+
+    /// Aggregator's share of the randomness used in the second part of the sketching protocol on
+    /// the leaves of the prefix tree.
+    sketch_next_leaf: Share<I::LeafField, L>,
 }
 
 impl<I: Idpf<2, 2>, const L: usize> Encode for Poplar1InputShare<I, L> {
@@ -273,6 +736,7 @@ impl<I: Idpf<2, 2>, const L: usize> Encode for Poplar1InputShare<I, L> {
         self.idpf.encode(bytes);
         self.sketch_start_seed.encode(bytes);
         self.sketch_next.encode(bytes);
+        self.sketch_next_leaf.encode(bytes);
     }
 }
 
@@ -284,23 +748,36 @@ impl<I: Idpf<2, 2>, const L: usize> ParameterizedDecode<Poplar1VerifyParam<L>>
         bytes: &mut Cursor<&[u8]>,
     ) -> Result<Self, CodecError> {
         let idpf = I::decode(bytes)?;
+        if idpf.level() != decoding_parameter.input_length {
+            return Err(CodecError::UnexpectedValue);
+        }
         let sketch_start_seed = Seed::decode(bytes)?;
 
-        let share_decoding_parameter = if decoding_parameter.is_leader {
-            // The sketch is two field elements for every bit of input, plus two more, corresponding
-            // to construction of shares in `Poplar1::shard`.
-            ShareDecodingParameter::Leader((decoding_parameter.input_length + 1) * 2)
-        } else {
-            ShareDecodingParameter::Helper
-        };
+        // The sketch is two field elements for every bit of input, plus two more for the leaves,
+        // corresponding to construction of shares in `Poplar1::shard`.
+        let (share_decoding_parameter, leaf_share_decoding_parameter) =
+            if decoding_parameter.is_leader {
+                (
+                    ShareDecodingParameter::Leader(decoding_parameter.input_length * 2),
+                    ShareDecodingParameter::Leader(2),
+                )
+            } else {
+                (
+                    ShareDecodingParameter::Helper,
+                    ShareDecodingParameter::Helper,
+                )
+            };
 
         let sketch_next =
-            <Share<I::Field, L>>::decode_with_param(&share_decoding_parameter, bytes)?;
+            <Share<I::InnerField, L>>::decode_with_param(&share_decoding_parameter, bytes)?;
+        let sketch_next_leaf =
+            <Share<I::LeafField, L>>::decode_with_param(&leaf_share_decoding_parameter, bytes)?;
 
         Ok(Self {
             idpf,
             sketch_start_seed,
             sketch_next,
+            sketch_next_leaf,
         })
     }
 }
@@ -331,6 +808,10 @@ impl<I, P, const L: usize> Clone for Poplar1<I, P, L> {
         Self::new(self.input_length)
     }
 }
+
+/// The poplar1 VDAF instantiated with [`PrgShake256`] and 32-byte seeds for a 256-bit security
+/// level.
+pub type Poplar1Shake256<I> = Poplar1<I, PrgShake256, 32>;
 impl<I, P, const L: usize> Vdaf for Poplar1<I, P, L>
 where
     I: Idpf<2, 2>,
@@ -342,11 +823,14 @@ where
     type PublicParam = ();
     type VerifyParam = Poplar1VerifyParam<L>;
     type InputShare = Poplar1InputShare<I, L>;
-    type OutputShare = OutputShare<I::Field>;
-    type AggregateShare = AggregateShare<I::Field>;
+    type OutputShare = Poplar1OutputShare<I::InnerField, I::LeafField>;
+    type AggregateShare = Poplar1AggregateShare<I::InnerField, I::LeafField>;
 
-    fn setup(&self) -> Result<((), Vec<Poplar1VerifyParam<L>>), VdafError> {
-        let verify_rand_init = Seed::generate()?;
+    fn setup_with_rng(
+        &self,
+        rng: &mut dyn RandSource,
+    ) -> Result<((), Vec<Poplar1VerifyParam<L>>), VdafError> {
+        let verify_rand_init = Seed::from_rand_source(rng)?;
         Ok((
             (),
             vec![
@@ -366,65 +850,104 @@ where
     I: Idpf<2, 2>,
     P: Prg<L>,
 {
-    #[allow(clippy::many_single_char_names)]
-    fn shard(
+    fn shard_with_rng(
         &self,
         _public_param: &(),
         input: &IdpfInput,
+        rng: &mut dyn RandSource,
     ) -> Result<Vec<Poplar1InputShare<I, L>>, VdafError> {
-        let idpf_values: Vec<[I::Field; 2]> = Prng::new()?
-            .take(input.level + 1)
-            .map(|k| [I::Field::one(), k])
-            .collect();
+        if input.level() != self.input_length {
+            return Err(VdafError::InvalidMeasurement(format!(
+                "unexpected input length: got {}; want {}",
+                input.level(),
+                self.input_length
+            )));
+        }
+
+        // Generate the authenticator for each level of the prefix tree.
+        let idpf_rand_seed = Seed::from_rand_source(rng)?;
+        let idpf_values: Vec<[I::InnerField; 2]> =
+            Prng::from_seed_stream(P::seed_stream(&idpf_rand_seed, b""))
+                .take(input.level())
+                .map(|k| [I::InnerField::one(), k])
+                .collect();
+        let idpf_leaf_value = [
+            I::LeafField::one(),
+            Prng::from_seed_stream(P::seed_stream(&idpf_rand_seed, b"leaf")).get(),
+        ];
 
         // For each level of the prefix tree, generate correlated randomness that the aggregators use
         // to validate the output. See [BBCG+21, Appendix C.4].
-        let leader_sketch_start_seed = Seed::generate()?;
-        let helper_sketch_start_seed = Seed::generate()?;
-        let helper_sketch_next_seed = Seed::generate()?;
-        let mut leader_sketch_start_prng: Prng<I::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&leader_sketch_start_seed, b""));
-        let mut helper_sketch_start_prng: Prng<I::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&helper_sketch_start_seed, b""));
-        let mut helper_sketch_next_prng: Prng<I::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&helper_sketch_next_seed, b""));
-        let mut leader_sketch_next: Vec<I::Field> = Vec::with_capacity(2 * idpf_values.len());
-        for value in idpf_values.iter() {
-            let k = value[1];
-
-            // [BBCG+21, Appendix C.4]
-            //
-            // $(a, b, c)$
-            let a = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
-            let b = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
-            let c = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
-
-            // $A = -2a + k$
-            // $B = a^2 + b + -ak + c$
-            let d = k - (a + a);
-            let e = (a * a) + b - (a * k) + c;
-            leader_sketch_next.push(d - helper_sketch_next_prng.get());
-            leader_sketch_next.push(e - helper_sketch_next_prng.get());
-        }
+        let leader_sketch_start_seed = Seed::from_rand_source(rng)?;
+        let helper_sketch_start_seed = Seed::from_rand_source(rng)?;
+        let helper_sketch_next_seed = Seed::from_rand_source(rng)?;
+        let helper_sketch_next_leaf_seed = Seed::from_rand_source(rng)?;
+        let leader_sketch_next = sketch_next(
+            idpf_values.iter().map(|value| value[1]),
+            P::seed_stream(&leader_sketch_start_seed, b""),
+            P::seed_stream(&helper_sketch_start_seed, b""),
+            P::seed_stream(&helper_sketch_next_seed, b""),
+        );
+        let leader_sketch_next_leaf = sketch_next(
+            std::iter::once(idpf_leaf_value[1]),
+            P::seed_stream(&leader_sketch_start_seed, b"leaf"),
+            P::seed_stream(&helper_sketch_start_seed, b"leaf"),
+            P::seed_stream(&helper_sketch_next_leaf_seed, b""),
+        );
 
         // Generate IDPF shares of the data and authentication vectors.
-        let idpf_shares = I::gen(input, idpf_values)?;
+        let idpf_shares = I::gen_with_rng(input, idpf_values, idpf_leaf_value, rng)?;
 
         Ok(vec![
             Poplar1InputShare {
                 idpf: idpf_shares[0].clone(),
                 sketch_start_seed: leader_sketch_start_seed,
                 sketch_next: Share::Leader(leader_sketch_next),
+                sketch_next_leaf: Share::Leader(leader_sketch_next_leaf),
             },
             Poplar1InputShare {
                 idpf: idpf_shares[1].clone(),
                 sketch_start_seed: helper_sketch_start_seed,
                 sketch_next: Share::Helper(helper_sketch_next_seed),
+                sketch_next_leaf: Share::Helper(helper_sketch_next_leaf_seed),
             },
         ])
     }
 }
 
+/// Compute the leader's share of the correlated randomness used in the second part of the sketching
+/// protocol, given the authenticator `k` of the output on each level. The seed streams are used to
+/// derive the aggregators' shares of the randomness used in the first part of the protocol and the
+/// helper's share of the randomness used in the second part.
+#[allow(clippy::many_single_char_names)]
+fn sketch_next<F: FieldElement, S: SeedStream>(
+    ks: impl Iterator<Item = F>,
+    leader_sketch_start: S,
+    helper_sketch_start: S,
+    helper_sketch_next: S,
+) -> Vec<F> {
+    let mut leader_sketch_start_prng: Prng<F, _> = Prng::from_seed_stream(leader_sketch_start);
+    let mut helper_sketch_start_prng: Prng<F, _> = Prng::from_seed_stream(helper_sketch_start);
+    let mut helper_sketch_next_prng: Prng<F, _> = Prng::from_seed_stream(helper_sketch_next);
+    let mut leader_sketch_next = Vec::new();
+    for k in ks {
+        // [BBCG+21, Appendix C.4]
+        //
+        // $(a, b, c)$
+        let a = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
+        let b = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
+        let c = leader_sketch_start_prng.get() + helper_sketch_start_prng.get();
+
+        // $A = -2a + k$
+        // $B = a^2 + b + -ak + c$
+        let d = k - (a + a);
+        let e = (a * a) + b - (a * k) + c;
+        leader_sketch_next.push(d - helper_sketch_next_prng.get());
+        leader_sketch_next.push(e - helper_sketch_next_prng.get());
+    }
+    leader_sketch_next
+}
+
 /// The verification parameter used by the aggregators to evaluate the VDAF on a distributed input.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Poplar1VerifyParam<const L: usize> {
@@ -490,205 +1013,260 @@ fn get_level(agg_param: &BTreeSet<IdpfInput>) -> Result<usize, VdafError> {
     let mut level = None;
     for prefix in agg_param {
         if let Some(l) = level {
-            if prefix.level != l {
-                return Err(VdafError::Uncategorized(
+            if prefix.level() != l {
+                return Err(PrepareError::BadAggParam(
                     "prefixes must all have the same length".to_string(),
-                ));
+                )
+                .into());
             }
         } else {
-            level = Some(prefix.level);
+            level = Some(prefix.level());
         }
     }
 
     match level {
         Some(level) => Ok(level),
-        None => Err(VdafError::Uncategorized("prefix set is empty".to_string())),
+        None => Err(PrepareError::BadAggParam("prefix set is empty".to_string()).into()),
     }
 }
 
-impl<I, P, const L: usize> Aggregator for Poplar1<I, P, L>
+impl<I, P, const L: usize> Poplar1<I, P, L>
 where
     I: Idpf<2, 2>,
     P: Prg<L>,
 {
-    type PrepareStep = Poplar1PrepareStep<I::Field>;
-    type PrepareMessage = Poplar1PrepareMessage<I::Field>;
+    /// Like [`Aggregator::prepare_init`], except that the state of the IDPF evaluation and of the
+    /// sketch is stored in `cache` and reused the next time this is called for the same report
+    /// (i.e., the same nonce) on a longer prefix. When the prefix tree is traversed one level at a
+    /// time, this makes the total cost of preparation linear rather than quadratic in the length
+    /// of the input.
+    ///
+    /// Reports are cached per nonce and aggregator, together with the sketch seed of the input
+    /// share, which is chosen at random by the client. An error is returned if the same nonce is
+    /// used for an input share with a different seed. This guards against accidental reuse of a
+    /// nonce without re-reading the input share at every level; it does not authenticate the
+    /// input share. The caller is responsible for removing reports from the cache once they are no
+    /// longer needed.
+    pub fn prepare_init_with_cache(
+        &self,
+        cache: &mut Poplar1Cache<I, P, L>,
+        verify_param: &Poplar1VerifyParam<L>,
+        agg_param: &BTreeSet<IdpfInput>,
+        nonce: &[u8],
+        input_share: &Poplar1InputShare<I, L>,
+    ) -> Result<Poplar1PrepareStep<I::InnerField, I::LeafField>, VdafError> {
+        let report_cache = cache
+            .reports
+            .entry((nonce.to_vec(), verify_param.is_leader))
+            .or_insert_with(|| ReportCache::new(input_share.sketch_start_seed.clone()));
+        if report_cache.sketch_start_seed != input_share.sketch_start_seed {
+            return Err(PrepareError::CacheMismatch.into());
+        }
+        self.prepare_init_with_report_cache(
+            verify_param,
+            agg_param,
+            nonce,
+            input_share,
+            report_cache,
+        )
+    }
 
-    fn prepare_init(
+    fn prepare_init_with_report_cache(
         &self,
         verify_param: &Poplar1VerifyParam<L>,
         agg_param: &BTreeSet<IdpfInput>,
         nonce: &[u8],
-        input_share: &Self::InputShare,
-    ) -> Result<Poplar1PrepareStep<I::Field>, VdafError> {
+        input_share: &Poplar1InputShare<I, L>,
+        cache: &mut ReportCache<I, P, L>,
+    ) -> Result<Poplar1PrepareStep<I::InnerField, I::LeafField>, VdafError> {
         let level = get_level(agg_param)?;
 
-        // Derive the verification randomness.
-        let mut verify_rand_prng: Prng<I::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&verify_param.rand_init, nonce));
-
-        // Evaluate the IDPF shares and compute the polynomial coefficients.
-        let mut z = [I::Field::zero(); 3];
-        let mut output_share = Vec::with_capacity(agg_param.len());
+        // Evaluate the IDPF shares. The prefixes all have the same length, so the outputs are
+        // either all on inner nodes or all on leaves.
+        let mut inner_values = Vec::new();
+        let mut leaf_values = Vec::new();
         for prefix in agg_param.iter() {
-            let value = input_share.idpf.eval(prefix)?;
-            let (v, k) = (value[0], value[1]);
-            let r = verify_rand_prng.get();
+            match input_share.idpf.eval_with_cache(prefix, &mut cache.idpf)? {
+                IdpfValue::Inner(value) => inner_values.push(value),
+                IdpfValue::Leaf(value) => leaf_values.push(value),
+            }
+        }
 
+        let verify_rand_seed_stream = P::seed_stream(&verify_param.rand_init, nonce);
+        if leaf_values.is_empty() {
             // [BBCG+21, Appendix C.4]
             //
-            // $(z_\sigma, z^*_\sigma, z^{**}_\sigma)$
-            let tmp = r * v;
-            z[0] += tmp;
-            z[1] += r * tmp;
-            z[2] += r * k;
-            output_share.push(v);
+            // The PRNG state is kept in the cache so that it need not be fast-forwarded from the
+            // start at each level.
+            let sketch_start = SketchPrng::resume(&mut cache.sketch_start, level, || {
+                P::seed_stream(&input_share.sketch_start_seed, b"")
+            })
+            .get(level);
+
+            let sketch_next = match &input_share.sketch_next {
+                Share::Leader(data) => match data.get(2 * level..2 * level + 2) {
+                    Some(data) => [data[0], data[1]],
+                    None => {
+                        return Err(PrepareError::LengthMismatch {
+                            what: "sketch share",
+                            got: data.len(),
+                            want: 2 * level + 2,
+                        }
+                        .into())
+                    }
+                },
+                Share::Helper(seed) => {
+                    SketchPrng::resume(&mut cache.sketch_next, level, || P::seed_stream(seed, b""))
+                        .get(level)
+                }
+            };
+
+            Ok(Poplar1PrepareStep(PrepareStepField::Inner(
+                SketchStep::new(
+                    &inner_values,
+                    verify_rand_seed_stream,
+                    sketch_start,
+                    sketch_next,
+                    verify_param.is_leader,
+                ),
+            )))
+        } else if inner_values.is_empty() {
+            let mut sketch_start_prng: Prng<I::LeafField, _> =
+                Prng::from_seed_stream(P::seed_stream(&input_share.sketch_start_seed, b"leaf"));
+            let sketch_start = [(); 3].map(|_| sketch_start_prng.get());
+
+            let sketch_next = match &input_share.sketch_next_leaf {
+                Share::Leader(data) => [data[0], data[1]],
+                Share::Helper(seed) => {
+                    let mut prng: Prng<I::LeafField, _> =
+                        Prng::from_seed_stream(P::seed_stream(seed, b""));
+                    [prng.get(), prng.get()]
+                }
+            };
+
+            Ok(Poplar1PrepareStep(PrepareStepField::Leaf(SketchStep::new(
+                &leaf_values,
+                verify_rand_seed_stream,
+                sketch_start,
+                sketch_next,
+                verify_param.is_leader,
+            ))))
+        } else {
+            Err(PrepareError::BadAggParam(
+                "IDPF output is on both inner nodes and leaves".to_string(),
+            )
+            .into())
         }
+    }
+}
 
-        // [BBCG+21, Appendix C.4]
-        //
-        // Add blind shares $(a_\sigma b_\sigma, c_\sigma)$
-        //
-        // NOTE(cjpatton) We can make this faster by a factor of 3 by using three seed shares instead
-        // of one. On the other hand, if the input shares are made stateful, then we could store
-        // the PRNG state theire and avoid fast-forwarding.
-        let mut prng = Prng::<I::Field, _>::from_seed_stream(P::seed_stream(
-            &input_share.sketch_start_seed,
-            b"",
-        ))
-        .skip(3 * level);
-        z[0] += prng.next().unwrap();
-        z[1] += prng.next().unwrap();
-        z[2] += prng.next().unwrap();
-
-        let (d, e) = match &input_share.sketch_next {
-            Share::Leader(data) => (data[2 * level], data[2 * level + 1]),
-            Share::Helper(seed) => {
-                let mut prng = Prng::<I::Field, _>::from_seed_stream(P::seed_stream(seed, b""))
-                    .skip(2 * level);
-                (prng.next().unwrap(), prng.next().unwrap())
-            }
-        };
-
-        let x = if verify_param.is_leader {
-            I::Field::one()
-        } else {
-            I::Field::zero()
-        };
+impl<I, P, const L: usize> Aggregator for Poplar1<I, P, L>
+where
+    I: Idpf<2, 2>,
+    P: Prg<L>,
+{
+    type PrepareStep = Poplar1PrepareStep<I::InnerField, I::LeafField>;
+    type PrepareMessage = Poplar1PrepareMessage<I::InnerField, I::LeafField>;
 
-        Ok(Poplar1PrepareStep {
-            sketch: SketchState::Ready,
-            output_share: OutputShare(output_share),
-            z,
-            d,
-            e,
-            x,
-        })
+    fn prepare_init(
+        &self,
+        verify_param: &Poplar1VerifyParam<L>,
+        agg_param: &BTreeSet<IdpfInput>,
+        nonce: &[u8],
+        input_share: &Self::InputShare,
+    ) -> Result<Self::PrepareStep, VdafError> {
+        self.prepare_init_with_report_cache(
+            verify_param,
+            agg_param,
+            nonce,
+            input_share,
+            &mut ReportCache::new(input_share.sketch_start_seed.clone()),
+        )
     }
 
-    fn prepare_preprocess<M: IntoIterator<Item = Poplar1PrepareMessage<I::Field>>>(
+    fn prepare_preprocess<M: IntoIterator<Item = Self::PrepareMessage>>(
         &self,
         inputs: M,
-    ) -> Result<Poplar1PrepareMessage<I::Field>, VdafError> {
-        let mut output: Option<Vec<I::Field>> = None;
-        let mut count = 0;
+    ) -> Result<Self::PrepareMessage, VdafError> {
+        let mut inner_shares = Vec::new();
+        let mut leaf_shares = Vec::new();
         for data_share in inputs.into_iter() {
-            count += 1;
-            if let Some(ref mut data) = output {
-                if data_share.0.len() != data.len() {
-                    return Err(VdafError::Uncategorized(format!(
-                        "unexpected message length: got {}; want {}",
-                        data_share.0.len(),
-                        data.len(),
-                    )));
-                }
-
-                for (x, y) in data.iter_mut().zip(data_share.0.iter()) {
-                    *x += *y;
-                }
-            } else {
-                output = Some(data_share.0);
+            match data_share.0 {
+                FieldVec::Inner(data) => inner_shares.push(data),
+                FieldVec::Leaf(data) => leaf_shares.push(data),
             }
         }
 
-        if count != 2 {
-            return Err(VdafError::Uncategorized(format!(
-                "unexpected message count: got {}; want 2",
-                count,
-            )));
+        if leaf_shares.is_empty() {
+            Ok(Poplar1PrepareMessage(FieldVec::Inner(sum_prepare_shares(
+                inner_shares,
+            )?)))
+        } else if inner_shares.is_empty() {
+            Ok(Poplar1PrepareMessage(FieldVec::Leaf(sum_prepare_shares(
+                leaf_shares,
+            )?)))
+        } else {
+            Err(PrepareError::InvalidMessage(
+                "prepare messages are for different levels".to_string(),
+            )
+            .into())
         }
-
-        Ok(Poplar1PrepareMessage(output.unwrap()))
     }
 
     // TODO Fix this clippy warning instead of bypassing it.
     #[allow(clippy::type_complexity)]
     fn prepare_step(
         &self,
-        mut state: Poplar1PrepareStep<I::Field>,
-        input: Option<Poplar1PrepareMessage<I::Field>>,
-    ) -> PrepareTransition<
-        Poplar1PrepareStep<I::Field>,
-        Poplar1PrepareMessage<I::Field>,
-        OutputShare<I::Field>,
-    > {
-        match (&state.sketch, input) {
-            (SketchState::Ready, None) => {
-                let z_share = state.z.to_vec();
-                state.sketch = SketchState::RoundOne;
-                PrepareTransition::Continue(state, Poplar1PrepareMessage(z_share))
-            }
-
-            (SketchState::RoundOne, Some(msg)) => {
-                if msg.0.len() != 3 {
-                    return PrepareTransition::Fail(VdafError::Uncategorized(format!(
-                        "unexpected message length ({:?}): got {}; want 3",
-                        state.sketch,
-                        msg.0.len(),
-                    )));
-                }
-
-                // Compute polynomial coefficients.
-                let z: [I::Field; 3] = msg.0.try_into().unwrap();
-                let y_share =
-                    vec![(state.d * z[0]) + state.e + state.x * ((z[0] * z[0]) - z[1] - z[2])];
-
-                state.sketch = SketchState::RoundTwo;
-                PrepareTransition::Continue(state, Poplar1PrepareMessage(y_share))
-            }
-
-            (SketchState::RoundTwo, Some(msg)) => {
-                if msg.0.len() != 1 {
-                    return PrepareTransition::Fail(VdafError::Uncategorized(format!(
-                        "unexpected message length ({:?}): got {}; want 1",
-                        state.sketch,
-                        msg.0.len(),
-                    )));
-                }
-
-                let y = msg.0[0];
-                if y != I::Field::zero() {
-                    return PrepareTransition::Fail(VdafError::Uncategorized(format!(
-                        "output is invalid: polynomial evaluated to {}; want {}",
-                        y,
-                        I::Field::zero(),
-                    )));
-                }
-
-                PrepareTransition::Finish(state.output_share)
-            }
-            _ => PrepareTransition::Fail(VdafError::Uncategorized(
-                "invalid state transition".to_string(),
-            )),
+        state: Self::PrepareStep,
+        input: Option<Self::PrepareMessage>,
+    ) -> PrepareTransition<Self::PrepareStep, Self::PrepareMessage, Self::OutputShare> {
+        match (state.0, input.map(|msg| msg.0)) {
+            (PrepareStepField::Inner(step), None) => map_transition(
+                step.next(None),
+                PrepareStepField::Inner,
+                FieldVec::Inner,
+                Poplar1OutputShare::Inner,
+            ),
+            (PrepareStepField::Inner(step), Some(FieldVec::Inner(msg))) => map_transition(
+                step.next(Some(msg)),
+                PrepareStepField::Inner,
+                FieldVec::Inner,
+                Poplar1OutputShare::Inner,
+            ),
+            (PrepareStepField::Leaf(step), None) => map_transition(
+                step.next(None),
+                PrepareStepField::Leaf,
+                FieldVec::Leaf,
+                Poplar1OutputShare::Leaf,
+            ),
+            (PrepareStepField::Leaf(step), Some(FieldVec::Leaf(msg))) => map_transition(
+                step.next(Some(msg)),
+                PrepareStepField::Leaf,
+                FieldVec::Leaf,
+                Poplar1OutputShare::Leaf,
+            ),
+            _ => PrepareTransition::Fail(
+                PrepareError::InvalidMessage(
+                    "prepare message does not match the level of the prefix tree".to_string(),
+                )
+                .into(),
+            ),
         }
     }
 
-    fn aggregate<M: IntoIterator<Item = OutputShare<I::Field>>>(
+    fn aggregate<M: IntoIterator<Item = Self::OutputShare>>(
         &self,
         agg_param: &BTreeSet<IdpfInput>,
         output_shares: M,
-    ) -> Result<AggregateShare<I::Field>, VdafError> {
-        let mut agg_share = AggregateShare(vec![I::Field::zero(); agg_param.len()]);
+    ) -> Result<Self::AggregateShare, VdafError> {
+        let mut agg_share = if get_level(agg_param)? == self.input_length {
+            Poplar1AggregateShare::Leaf(AggregateShare(vec![I::LeafField::zero(); agg_param.len()]))
+        } else {
+            Poplar1AggregateShare::Inner(AggregateShare(vec![
+                I::InnerField::zero();
+                agg_param.len()
+            ]))
+        };
         for output_share in output_shares.into_iter() {
             agg_share.accumulate(&output_share)?;
         }
@@ -697,42 +1275,257 @@ where
     }
 }
 
-/// A prepare message sent exchanged between Poplar1 aggregators
-#[derive(Clone, Debug)]
-pub struct Poplar1PrepareMessage<F>(Vec<F>);
+/// Sum the aggregators' shares of a prepare message.
+fn sum_prepare_shares<F: FieldElement>(shares: Vec<Vec<F>>) -> Result<Vec<F>, VdafError> {
+    if shares.len() != 2 {
+        return Err(PrepareError::MessageCount {
+            got: shares.len(),
+            want: 2,
+        }
+        .into());
+    }
+
+    let mut shares = shares.into_iter();
+    let mut output = shares.next().unwrap();
+    for data_share in shares {
+        if data_share.len() != output.len() {
+            return Err(PrepareError::LengthMismatch {
+                what: "prepare message",
+                got: data_share.len(),
+                want: output.len(),
+            }
+            .into());
+        }
+
+        for (x, y) in output.iter_mut().zip(data_share.iter()) {
+            *x += *y;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Wrap the prepare state, prepare message or output share of `transition`, which is on a level
+/// of the prefix tree with field `F`, using `state`, `message` or `output` respectively.
+#[allow(clippy::type_complexity)]
+fn map_transition<F, FI, FL>(
+    transition: PrepareTransition<SketchStep<F>, Vec<F>, OutputShare<F>>,
+    state: fn(SketchStep<F>) -> PrepareStepField<FI, FL>,
+    message: fn(Vec<F>) -> FieldVec<FI, FL>,
+    output: fn(OutputShare<F>) -> Poplar1OutputShare<FI, FL>,
+) -> PrepareTransition<
+    Poplar1PrepareStep<FI, FL>,
+    Poplar1PrepareMessage<FI, FL>,
+    Poplar1OutputShare<FI, FL>,
+> {
+    match transition {
+        PrepareTransition::Continue(step, msg) => PrepareTransition::Continue(
+            Poplar1PrepareStep(state(step)),
+            Poplar1PrepareMessage(message(msg)),
+        ),
+        PrepareTransition::Finish(output_share) => PrepareTransition::Finish(output(output_share)),
+        PrepareTransition::Fail(err) => PrepareTransition::Fail(err),
+    }
+}
+
+/// Aggregator-side cache of the evaluation state of each report, keyed by nonce and aggregator.
+/// See [`Poplar1::prepare_init_with_cache`].
+pub struct Poplar1Cache<I: Idpf<2, 2>, P: Prg<L>, const L: usize> {
+    reports: HashMap<(Vec<u8>, bool), ReportCache<I, P, L>>,
+}
+
+impl<I: Idpf<2, 2>, P: Prg<L>, const L: usize> Poplar1Cache<I, P, L> {
+    /// Construct an empty cache.
+    pub fn new() -> Self {
+        Self {
+            reports: HashMap::new(),
+        }
+    }
+
+    /// Remove the state of the report with the given nonce for each aggregator.
+    pub fn remove(&mut self, nonce: &[u8]) {
+        for is_leader in [true, false] {
+            self.reports.remove(&(nonce.to_vec(), is_leader));
+        }
+    }
+
+    /// Returns the number of (report, aggregator) pairs in the cache.
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    /// Returns true if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+}
+
+impl<I: Idpf<2, 2>, P: Prg<L>, const L: usize> Default for Poplar1Cache<I, P, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-impl<F> AsRef<[F]> for Poplar1PrepareMessage<F> {
-    fn as_ref(&self) -> &[F] {
-        &self.0
+impl<I: Idpf<2, 2>, P: Prg<L>, const L: usize> Debug for Poplar1Cache<I, P, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Poplar1Cache")
+            .field("reports", &self.reports.len())
+            .finish()
     }
 }
 
-impl<F: FieldElement> Encode for Poplar1PrepareMessage<F> {
+/// The evaluation state of a single report.
+struct ReportCache<I: Idpf<2, 2>, P: Prg<L>, const L: usize> {
+    sketch_start_seed: Seed<L>,
+    idpf: IdpfCache,
+    sketch_start: Option<SketchPrng<I::InnerField, P::SeedStream>>,
+    sketch_next: Option<SketchPrng<I::InnerField, P::SeedStream>>,
+}
+
+impl<I: Idpf<2, 2>, P: Prg<L>, const L: usize> ReportCache<I, P, L> {
+    fn new(sketch_start_seed: Seed<L>) -> Self {
+        Self {
+            sketch_start_seed,
+            idpf: IdpfCache::new(),
+            sketch_start: None,
+            sketch_next: None,
+        }
+    }
+}
+
+/// A PRNG whose output is consumed `N` elements per level of the prefix tree, together with the
+/// next level it is positioned at.
+struct SketchPrng<F, S> {
+    prng: Prng<F, S>,
+    level: usize,
+}
+
+impl<F: FieldElement, S: SeedStream> SketchPrng<F, S> {
+    /// Returns the PRNG in `cache` if it can be advanced to `level`. Otherwise a new PRNG is
+    /// stored in `cache` with the seed stream returned by `seed_stream`.
+    fn resume(
+        cache: &mut Option<Self>,
+        level: usize,
+        seed_stream: impl FnOnce() -> S,
+    ) -> &mut Self {
+        if !matches!(cache, Some(prng) if prng.level <= level) {
+            *cache = Some(Self {
+                prng: Prng::from_seed_stream(seed_stream()),
+                level: 0,
+            });
+        }
+        cache.as_mut().unwrap()
+    }
+
+    /// Returns the output of the PRNG for `level`.
+    fn get<const N: usize>(&mut self, level: usize) -> [F; N] {
+        for _ in 0..(level - self.level) * N {
+            self.prng.get();
+        }
+        self.level = level + 1;
+        [(); N].map(|_| self.prng.get())
+    }
+}
+
+/// A vector of elements of the field for the inner nodes of the prefix tree (`FI`) or of the field
+/// for the leaves (`FL`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FieldVec<FI, FL> {
+    Inner(Vec<FI>),
+    Leaf(Vec<FL>),
+}
+
+/// A prepare message sent exchanged between Poplar1 aggregators
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poplar1PrepareMessage<FI, FL>(FieldVec<FI, FL>);
+
+impl<FI: FieldElement, FL: FieldElement> Encode for Poplar1PrepareMessage<FI, FL> {
     fn encode(&self, bytes: &mut Vec<u8>) {
         // TODO: This is encoded as a variable length vector of F, but we may
         // be able to make this a fixed-length vector for specific Poplar1
         // instantations
-        encode_u16_items(bytes, &(), &self.0);
+        match &self.0 {
+            FieldVec::Inner(data) => encode_u16_items(bytes, &(), data),
+            FieldVec::Leaf(data) => encode_u16_items(bytes, &(), data),
+        }
     }
 }
 
-impl<F: FieldElement> ParameterizedDecode<Poplar1PrepareStep<F>> for Poplar1PrepareMessage<F> {
+impl<FI: FieldElement, FL: FieldElement> ParameterizedDecode<Poplar1PrepareStep<FI, FL>>
+    for Poplar1PrepareMessage<FI, FL>
+{
     fn decode_with_param(
-        _decoding_parameter: &Poplar1PrepareStep<F>,
+        decoding_parameter: &Poplar1PrepareStep<FI, FL>,
         bytes: &mut Cursor<&[u8]>,
     ) -> Result<Self, CodecError> {
         // TODO: This is decoded as a variable length vector of F, but we may be
         // able to make this a fixed-length vector for specific Poplar1
         // instantiations.
-        let items = decode_u16_items(&(), bytes)?;
-
-        Ok(Self(items))
+        //
+        // The field is determined by the level of the prefix tree the aggregator is on.
+        match decoding_parameter.0 {
+            PrepareStepField::Inner(_) => Ok(Self(FieldVec::Inner(decode_u16_items(&(), bytes)?))),
+            PrepareStepField::Leaf(_) => Ok(Self(FieldVec::Leaf(decode_u16_items(&(), bytes)?))),
+        }
     }
 }
 
 /// The state of each Aggregator during the Prepare process.
-#[derive(Clone, Debug)]
-pub struct Poplar1PrepareStep<F> {
+///
+/// Serialization traits [`Encode`] and [`ParameterizedDecode`] are implemented for this type, so
+/// that the state can be persisted between rounds of the Prepare process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poplar1PrepareStep<FI, FL>(PrepareStepField<FI, FL>);
+
+impl<FI: FieldElement, FL: FieldElement> Encode for Poplar1PrepareStep<FI, FL> {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match &self.0 {
+            PrepareStepField::Inner(step) => {
+                0u8.encode(bytes);
+                step.encode(bytes);
+            }
+            PrepareStepField::Leaf(step) => {
+                1u8.encode(bytes);
+                step.encode(bytes);
+            }
+        }
+    }
+}
+
+impl<'a, I, P, const L: usize>
+    ParameterizedDecode<(&'a Poplar1<I, P, L>, &'a Poplar1VerifyParam<L>)>
+    for Poplar1PrepareStep<I::InnerField, I::LeafField>
+where
+    I: Idpf<2, 2>,
+    P: Prg<L>,
+{
+    fn decode_with_param(
+        (_, verify_param): &(&'a Poplar1<I, P, L>, &'a Poplar1VerifyParam<L>),
+        bytes: &mut Cursor<&[u8]>,
+    ) -> Result<Self, CodecError> {
+        match u8::decode(bytes)? {
+            0 => Ok(Self(PrepareStepField::Inner(
+                SketchStep::decode_with_param(&verify_param.is_leader, bytes)?,
+            ))),
+            1 => Ok(Self(PrepareStepField::Leaf(SketchStep::decode_with_param(
+                &verify_param.is_leader,
+                bytes,
+            )?))),
+            _ => Err(CodecError::UnexpectedValue),
+        }
+    }
+}
+
+/// The state of the Prepare process on an inner level of the prefix tree or on the leaves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PrepareStepField<FI, FL> {
+    Inner(SketchStep<FI>),
+    Leaf(SketchStep<FL>),
+}
+
+/// The state of the Prepare process on a level of the prefix tree whose output is in field `F`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SketchStep<F> {
     /// State of the secure sketching protocol.
     sketch: SketchState,
 
@@ -752,37 +1545,510 @@ pub struct Poplar1PrepareStep<F> {
     x: F,
 }
 
-#[derive(Clone, Debug)]
+impl<F: FieldElement> SketchStep<F> {
+    /// Compute the initial state from the aggregator's share of the IDPF output on each prefix
+    /// and its shares of the randomness used in the sketching protocol.
+    fn new<S: SeedStream>(
+        values: &[[F; 2]],
+        verify_rand_seed_stream: S,
+        [a, b, c]: [F; 3],
+        [d, e]: [F; 2],
+        is_leader: bool,
+    ) -> Self {
+        // Derive the verification randomness.
+        let mut verify_rand_prng: Prng<F, _> = Prng::from_seed_stream(verify_rand_seed_stream);
+
+        // Compute the polynomial coefficients.
+        let mut z = [F::zero(); 3];
+        let mut output_share = Vec::with_capacity(values.len());
+        for [v, k] in values.iter() {
+            let r = verify_rand_prng.get();
+
+            // [BBCG+21, Appendix C.4]
+            //
+            // $(z_\sigma, z^*_\sigma, z^{**}_\sigma)$
+            let tmp = r * *v;
+            z[0] += tmp;
+            z[1] += r * tmp;
+            z[2] += r * *k;
+            output_share.push(*v);
+        }
+
+        // [BBCG+21, Appendix C.4]
+        //
+        // Add blind shares $(a_\sigma b_\sigma, c_\sigma)$
+        z[0] += a;
+        z[1] += b;
+        z[2] += c;
+
+        let x = if is_leader { F::one() } else { F::zero() };
+
+        Self {
+            sketch: SketchState::Ready,
+            output_share: OutputShare(output_share),
+            z,
+            d,
+            e,
+            x,
+        }
+    }
+
+    /// Advance the sketching protocol with the combined prepare message from the previous round.
+    fn next(mut self, input: Option<Vec<F>>) -> PrepareTransition<Self, Vec<F>, OutputShare<F>> {
+        match (&self.sketch, input) {
+            (SketchState::Ready, None) => {
+                let z_share = self.z.to_vec();
+                self.sketch = SketchState::RoundOne;
+                PrepareTransition::Continue(self, z_share)
+            }
+
+            (SketchState::RoundOne, Some(msg)) => {
+                if msg.len() != 3 {
+                    return PrepareTransition::Fail(
+                        PrepareError::LengthMismatch {
+                            what: "sketch message",
+                            got: msg.len(),
+                            want: 3,
+                        }
+                        .into(),
+                    );
+                }
+
+                // Compute polynomial coefficients.
+                let z: [F; 3] = msg.try_into().unwrap();
+                let y_share =
+                    vec![(self.d * z[0]) + self.e + self.x * ((z[0] * z[0]) - z[1] - z[2])];
+
+                self.sketch = SketchState::RoundTwo;
+                PrepareTransition::Continue(self, y_share)
+            }
+
+            (SketchState::RoundTwo, Some(msg)) => {
+                if msg.len() != 1 {
+                    return PrepareTransition::Fail(
+                        PrepareError::LengthMismatch {
+                            what: "sketch message",
+                            got: msg.len(),
+                            want: 1,
+                        }
+                        .into(),
+                    );
+                }
+
+                let y = msg[0];
+                if y != F::zero() {
+                    return PrepareTransition::Fail(PrepareError::VerifyFailed.into());
+                }
+
+                PrepareTransition::Finish(self.output_share)
+            }
+            _ => PrepareTransition::Fail(PrepareError::InvalidStateTransition.into()),
+        }
+    }
+}
+
+impl<F: FieldElement> Encode for SketchStep<F> {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match self.sketch {
+            SketchState::Ready => 0u8.encode(bytes),
+            SketchState::RoundOne => 1u8.encode(bytes),
+            SketchState::RoundTwo => 2u8.encode(bytes),
+        }
+        encode_u24_items(bytes, &(), &self.output_share.0);
+        for x in self.z.iter() {
+            x.encode(bytes);
+        }
+        self.d.encode(bytes);
+        self.e.encode(bytes);
+    }
+}
+
+/// The decoding parameter indicates whether the Aggregator is the leader.
+impl<F: FieldElement> ParameterizedDecode<bool> for SketchStep<F> {
+    fn decode_with_param(is_leader: &bool, bytes: &mut Cursor<&[u8]>) -> Result<Self, CodecError> {
+        let sketch = match u8::decode(bytes)? {
+            0 => SketchState::Ready,
+            1 => SketchState::RoundOne,
+            2 => SketchState::RoundTwo,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+        let output_share = OutputShare(decode_u24_items(&(), bytes)?);
+        let z = [F::decode(bytes)?, F::decode(bytes)?, F::decode(bytes)?];
+        let d = F::decode(bytes)?;
+        let e = F::decode(bytes)?;
+        let x = if *is_leader { F::one() } else { F::zero() };
+
+        Ok(Self {
+            sketch,
+            output_share,
+            z,
+            d,
+            e,
+            x,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum SketchState {
     Ready,
     RoundOne,
     RoundTwo,
 }
 
+/// An output share for the `poplar1` VDAF. The output share is a vector of elements of the field
+/// for the inner nodes of the prefix tree (`FI`), unless the prefixes are full inputs, in which
+/// case it is a vector of elements of the field for the leaves (`FL`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Poplar1OutputShare<FI, FL> {
+    /// An output share on an inner level of the prefix tree.
+    Inner(OutputShare<FI>),
+
+    /// An output share on the leaves of the prefix tree.
+    Leaf(OutputShare<FL>),
+}
+
+/// An aggregate share for the `poplar1` VDAF. See [`Poplar1OutputShare`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Poplar1AggregateShare<FI, FL> {
+    /// An aggregate share on an inner level of the prefix tree.
+    Inner(AggregateShare<FI>),
+
+    /// An aggregate share on the leaves of the prefix tree.
+    Leaf(AggregateShare<FL>),
+}
+
+impl<FI, FL> From<Poplar1OutputShare<FI, FL>> for Poplar1AggregateShare<FI, FL> {
+    fn from(other: Poplar1OutputShare<FI, FL>) -> Self {
+        match other {
+            Poplar1OutputShare::Inner(output_share) => Self::Inner(output_share.into()),
+            Poplar1OutputShare::Leaf(output_share) => Self::Leaf(output_share.into()),
+        }
+    }
+}
+
+impl<FI: FieldElement, FL: FieldElement> Aggregatable for Poplar1AggregateShare<FI, FL> {
+    type OutputShare = Poplar1OutputShare<FI, FL>;
+
+    fn merge(&mut self, agg_share: &Self) -> Result<(), VdafError> {
+        match (self, agg_share) {
+            (Self::Inner(data), Self::Inner(other)) => data.merge(other),
+            (Self::Leaf(data), Self::Leaf(other)) => data.merge(other),
+            _ => Err(VdafError::Aggregate(
+                "aggregate shares are for different levels".to_string(),
+            )),
+        }
+    }
+
+    fn accumulate(&mut self, output_share: &Self::OutputShare) -> Result<(), VdafError> {
+        match (self, output_share) {
+            (Self::Inner(data), Poplar1OutputShare::Inner(other)) => data.accumulate(other),
+            (Self::Leaf(data), Poplar1OutputShare::Leaf(other)) => data.accumulate(other),
+            _ => Err(VdafError::Aggregate(
+                "output share and aggregate share are for different levels".to_string(),
+            )),
+        }
+    }
+}
+
+impl<FI: FieldElement, FL: FieldElement> TryFrom<&[u8]> for Poplar1AggregateShare<FI, FL> {
+    type Error = CodecError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        // The first byte indicates whether the share is for the inner nodes or the leaves.
+        match bytes.split_first() {
+            Some((0, data)) => AggregateShare::try_from(data)
+                .map(Self::Inner)
+                .map_err(|e| CodecError::Other(Box::new(e))),
+            Some((1, data)) => AggregateShare::try_from(data)
+                .map(Self::Leaf)
+                .map_err(|e| CodecError::Other(Box::new(e))),
+            _ => Err(CodecError::UnexpectedValue),
+        }
+    }
+}
+
+impl<FI: FieldElement, FL: FieldElement> From<&Poplar1AggregateShare<FI, FL>> for Vec<u8> {
+    fn from(aggregate_share: &Poplar1AggregateShare<FI, FL>) -> Self {
+        let (tag, mut data): (u8, Vec<u8>) = match aggregate_share {
+            Poplar1AggregateShare::Inner(data) => (0, data.into()),
+            Poplar1AggregateShare::Leaf(data) => (1, data.into()),
+        };
+        data.insert(0, tag);
+        data
+    }
+}
+
 impl<I, P, const L: usize> Collector for Poplar1<I, P, L>
 where
     I: Idpf<2, 2>,
     P: Prg<L>,
 {
-    fn unshard<M: IntoIterator<Item = AggregateShare<I::Field>>>(
+    fn unshard<M: IntoIterator<Item = Self::AggregateShare>>(
         &self,
         agg_param: &BTreeSet<IdpfInput>,
         agg_shares: M,
     ) -> Result<BTreeMap<IdpfInput, u64>, VdafError> {
-        let mut agg_data = AggregateShare(vec![I::Field::zero(); agg_param.len()]);
-        for agg_share in agg_shares.into_iter() {
+        let mut agg_shares = agg_shares.into_iter();
+        let mut agg_data = agg_shares
+            .next()
+            .ok_or_else(|| VdafError::Aggregate("no aggregate shares to unshard".to_string()))?;
+        for agg_share in agg_shares {
             agg_data.merge(&agg_share)?;
         }
 
-        let mut agg = BTreeMap::new();
-        for (prefix, count) in agg_param.iter().zip(agg_data.as_ref()) {
-            let count = <I::Field as FieldElement>::Integer::from(*count);
-            let count: u64 = count
-                .try_into()
-                .map_err(|_| VdafError::Uncategorized("aggregate overflow".to_string()))?;
-            agg.insert(*prefix, count);
+        match agg_data {
+            Poplar1AggregateShare::Inner(data) => counts(agg_param, data.as_ref()),
+            Poplar1AggregateShare::Leaf(data) => counts(agg_param, data.as_ref()),
         }
-        Ok(agg)
+    }
+}
+
+/// Map each prefix in `agg_param` to its count in `agg_data`.
+fn counts<F: FieldElement>(
+    agg_param: &BTreeSet<IdpfInput>,
+    agg_data: &[F],
+) -> Result<BTreeMap<IdpfInput, u64>, VdafError> {
+    if agg_data.len() != agg_param.len() {
+        return Err(VdafError::Aggregate(format!(
+            "unexpected aggregate share length: got {}; want {}",
+            agg_data.len(),
+            agg_param.len()
+        )));
+    }
+
+    let mut agg = BTreeMap::new();
+    for (prefix, count) in agg_param.iter().zip(agg_data) {
+        let count = F::Integer::from(*count);
+        let count: u64 = count
+            .try_into()
+            .map_err(|_| VdafError::Aggregate("aggregate overflow".to_string()))?;
+        agg.insert(prefix.clone(), count);
+    }
+    Ok(agg)
+}
+
+// In test vectors, as in [draft-patton-cfrg-vdaf-01], each measurement is represented as an
+// integer whose most significant bit is the first bit of the input, and the aggregation parameter
+// is represented as a level of the prefix tree (i.e., the length of the prefixes minus one) and a
+// sequence of prefixes, each represented as an integer. The aggregate result is represented as the
+// count of each prefix.
+#[cfg(any(feature = "test-vector", test))]
+impl<I, P, const L: usize> TestVectorVdaf for Poplar1<I, P, L>
+where
+    I: Idpf<2, 2>,
+    P: Prg<L>,
+{
+    type TestVecMeasurement = u128;
+    type TestVecAggParam = (usize, Vec<u128>);
+    type TestVecAggResult = Vec<u64>;
+
+    fn test_vec_verify_param(&self, verify_param: &Poplar1VerifyParam<L>) -> (u8, Vec<u8>) {
+        (
+            if verify_param.is_leader { 0 } else { 1 },
+            verify_param.rand_init.0.to_vec(),
+        )
+    }
+
+    fn test_vec_measurement(&self, measurement: &u128) -> Result<IdpfInput, VdafError> {
+        IdpfInput::from_test_vec_int(*measurement, self.input_length)
+    }
+
+    fn test_vec_agg_param(
+        &self,
+        (level, prefixes): &(usize, Vec<u128>),
+    ) -> Result<BTreeSet<IdpfInput>, VdafError> {
+        prefixes
+            .iter()
+            .map(|prefix| IdpfInput::from_test_vec_int(*prefix, level + 1))
+            .collect()
+    }
+
+    fn test_vec_output_share(
+        &self,
+        output_share: &Poplar1OutputShare<I::InnerField, I::LeafField>,
+    ) -> Vec<u128> {
+        match output_share {
+            Poplar1OutputShare::Inner(data) => test_vec_field_vec(data.as_ref()),
+            Poplar1OutputShare::Leaf(data) => test_vec_field_vec(data.as_ref()),
+        }
+    }
+
+    fn test_vec_agg_share(
+        &self,
+        agg_share: &Poplar1AggregateShare<I::InnerField, I::LeafField>,
+    ) -> Vec<u128> {
+        match agg_share {
+            Poplar1AggregateShare::Inner(data) => test_vec_field_vec(data.as_ref()),
+            Poplar1AggregateShare::Leaf(data) => test_vec_field_vec(data.as_ref()),
+        }
+    }
+
+    fn test_vec_agg_result(
+        &self,
+        agg_param: &BTreeSet<IdpfInput>,
+        agg_shares: Vec<Poplar1AggregateShare<I::InnerField, I::LeafField>>,
+    ) -> Result<Vec<u64>, VdafError> {
+        Ok(self.unshard(agg_param, agg_shares)?.into_values().collect())
+    }
+}
+
+#[cfg(any(feature = "test-vector", test))]
+impl IdpfInput {
+    /// Constructs an IDPF input of length `level` from the bits of an integer, where the most
+    /// significant bit is the first bit of the input.
+    fn from_test_vec_int(value: u128, level: usize) -> Result<Self, VdafError> {
+        if level > 128 || (level < 128 && value >> level != 0) {
+            return Err(VdafError::InvalidMeasurement(format!(
+                "test vector value does not fit in {} bits",
+                level
+            )));
+        }
+
+        let bits = (0..level)
+            .map(|i| (value >> (level - 1 - i)) & 1 == 1)
+            .collect();
+        Ok(Self { bits })
+    }
+}
+
+/// A driver for the Collector that computes the heavy hitters among the measurements, i.e., each
+/// input that occurs at least `threshold` times, and the number of times it occurs.
+///
+/// The driver walks down the prefix tree one level at a time. At each level, the aggregation
+/// parameter is the set of candidate prefixes. The aggregators prepare and aggregate their input
+/// shares for this parameter, and the driver unshards the aggregate shares. Prefixes whose count
+/// is below the threshold are pruned, and each remaining prefix is extended by one bit to form the
+/// candidates for the next level.
+#[derive(Clone, Debug)]
+pub struct HeavyHitters<'a, I, P, const L: usize> {
+    vdaf: &'a Poplar1<I, P, L>,
+    threshold: u64,
+}
+
+impl<'a, I, P, const L: usize> HeavyHitters<'a, I, P, L>
+where
+    I: Idpf<2, 2>,
+    P: Prg<L>,
+{
+    /// Construct a driver for `vdaf` that reports each input that occurs at least `threshold`
+    /// times. The threshold must be at least `1`: otherwise no prefix is pruned, and the number of
+    /// candidate prefixes doubles at each level of the prefix tree.
+    pub fn new(vdaf: &'a Poplar1<I, P, L>, threshold: u64) -> Result<Self, VdafError> {
+        if threshold == 0 {
+            return Err(VdafError::InvalidParameter(
+                "heavy hitters threshold must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(Self { vdaf, threshold })
+    }
+
+    /// Run the protocol and return the heavy hitters and their counts.
+    ///
+    /// For each level of the prefix tree, `aggregate` is called with the candidate prefixes. It is
+    /// expected to have the aggregators prepare their input shares with this aggregation
+    /// parameter, aggregate the output shares, and return the aggregate share of each
+    /// aggregator.
+    pub fn run<A>(&self, mut aggregate: A) -> Result<BTreeMap<IdpfInput, u64>, VdafError>
+    where
+        A: FnMut(
+            &BTreeSet<IdpfInput>,
+        )
+            -> Result<Vec<Poplar1AggregateShare<I::InnerField, I::LeafField>>, VdafError>,
+    {
+        let root = IdpfInput { bits: Vec::new() };
+        let mut candidates = BTreeSet::new();
+        candidates.insert(root.extend(false));
+        candidates.insert(root.extend(true));
+        for level in 1..self.vdaf.input_length + 1 {
+            let agg_shares = aggregate(&candidates)?;
+            let counts = self.vdaf.unshard(&candidates, agg_shares)?;
+            let survivors = counts
+                .into_iter()
+                .filter(|(_prefix, count)| *count >= self.threshold);
+
+            if level == self.vdaf.input_length {
+                return Ok(survivors.collect());
+            }
+
+            candidates = survivors
+                .flat_map(|(prefix, _count)| [prefix.extend(false), prefix.extend(true)])
+                .collect();
+            if candidates.is_empty() {
+                break;
+            }
+        }
+
+        Ok(BTreeMap::new())
+    }
+
+    /// Run the protocol with each aggregator in the same process. Each report is a nonce and the
+    /// sequence of input shares, one for each aggregator. Reports that fail preparation are
+    /// dropped.
+    ///
+    /// Each aggregator caches the evaluation state of each report from one level to the next (see
+    /// [`Poplar1::prepare_init_with_cache`]).
+    pub fn run_in_process(
+        &self,
+        verify_params: &[Poplar1VerifyParam<L>],
+        reports: &[(Vec<u8>, Vec<Poplar1InputShare<I, L>>)],
+    ) -> Result<BTreeMap<IdpfInput, u64>, VdafError> {
+        let mut caches: Vec<Poplar1Cache<I, P, L>> =
+            verify_params.iter().map(|_| Poplar1Cache::new()).collect();
+        self.run(|agg_param| {
+            let mut out_shares = vec![Vec::with_capacity(reports.len()); verify_params.len()];
+            for (nonce, input_shares) in reports.iter() {
+                match self.prepare(&mut caches, verify_params, agg_param, nonce, input_shares) {
+                    Ok(report_out_shares) => {
+                        for (out_share, out) in
+                            report_out_shares.into_iter().zip(out_shares.iter_mut())
+                        {
+                            out.push(out_share);
+                        }
+                    }
+                    Err(_) => {
+                        for cache in caches.iter_mut() {
+                            cache.remove(nonce);
+                        }
+                    }
+                }
+            }
+
+            out_shares
+                .into_iter()
+                .map(|out| self.vdaf.aggregate(agg_param, out))
+                .collect()
+        })
+    }
+
+    // Run the prepare process for a single report.
+    fn prepare(
+        &self,
+        caches: &mut [Poplar1Cache<I, P, L>],
+        verify_params: &[Poplar1VerifyParam<L>],
+        agg_param: &BTreeSet<IdpfInput>,
+        nonce: &[u8],
+        input_shares: &[Poplar1InputShare<I, L>],
+    ) -> Result<Vec<<Poplar1<I, P, L> as Vdaf>::OutputShare>, VdafError> {
+        let mut states = Vec::with_capacity(input_shares.len());
+        for ((cache, verify_param), input_share) in caches
+            .iter_mut()
+            .zip(verify_params.iter())
+            .zip(input_shares.iter())
+        {
+            states.push(self.vdaf.prepare_init_with_cache(
+                cache,
+                verify_param,
+                agg_param,
+                nonce,
+                input_share,
+            )?);
+        }
+
+        run_prepare(self.vdaf, states, |_round, _aggregator, _state, msg| {
+            Ok(msg)
+        })
     }
 }
 
@@ -790,9 +2056,36 @@ where
 mod tests {
     use super::*;
 
-    use crate::field::Field128;
-    use crate::vdaf::prg::PrgAes128;
+    use crate::field::{Field128, Field64};
+    use crate::vdaf::prg::{PrgAes128, RngSource};
+    use crate::vdaf::test_vector::{check_test_vector, generate_test_vector, TestVector};
     use crate::vdaf::{run_vdaf, run_vdaf_prepare};
+    use assert_matches::assert_matches;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_idpf_input_codec() {
+        for input in [
+            IdpfInput::new(b"", 0).unwrap(),
+            IdpfInput::new(b"hi", 9).unwrap(),
+            IdpfInput::new(&[0xff; 20], 160).unwrap(),
+        ] {
+            let encoded = input.get_encoded();
+            assert_eq!(IdpfInput::get_decoded(&encoded).unwrap(), input);
+        }
+
+        // A level that does not match the length of the data is rejected.
+        let mut encoded = Vec::new();
+        8_u64.encode(&mut encoded);
+        encode_u16_items(&mut encoded, &(), &[0_u8; 2]);
+        IdpfInput::get_decoded(&encoded).unwrap_err();
+
+        // A level whose byte length overflows is rejected without panicking.
+        let mut encoded = Vec::new();
+        u64::MAX.encode(&mut encoded);
+        encode_u16_items(&mut encoded, &(), &[0_u8; 1]);
+        IdpfInput::get_decoded(&encoded).unwrap_err();
+    }
 
     #[test]
     fn test_idpf() {
@@ -830,52 +2123,193 @@ mod tests {
         let keys = ToyIdpf::<Field128>::gen(
             &input,
             std::iter::repeat([Field128::one(), Field128::one()]),
+            [Field128::one(), Field128::one()],
         )
         .unwrap();
 
         // Try evaluating the IDPF keys on all prefixes.
-        for prefix_len in 0..input.level + 1 {
+        for prefix_len in 0..input.level() {
             let res = eval_idpf(
                 &keys,
                 &input.prefix(prefix_len),
-                &[Field128::one(), Field128::one()],
+                &IdpfValue::Inner([Field128::one(), Field128::one()]),
             );
             assert!(res.is_ok(), "prefix_len={} error: {:?}", prefix_len, res);
         }
+        eval_idpf(
+            &keys,
+            &input,
+            &IdpfValue::Leaf([Field128::one(), Field128::one()]),
+        )
+        .unwrap();
 
         // Try evaluating the IDPF keys on incorrect prefixes.
         eval_idpf(
             &keys,
             &IdpfInput::new(&[2], 2).unwrap(),
-            &[Field128::zero(), Field128::zero()],
+            &IdpfValue::Inner([Field128::zero(), Field128::zero()]),
         )
         .unwrap();
 
         eval_idpf(
             &keys,
             &IdpfInput::new(&[23, 1], 12).unwrap(),
-            &[Field128::zero(), Field128::zero()],
+            &IdpfValue::Inner([Field128::zero(), Field128::zero()]),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_tree_idpf() {
+        const INPUT_LEN: usize = 256;
+
+        let input = IdpfInput::new(&[0xa5; INPUT_LEN / 8], INPUT_LEN).unwrap();
+        let values: Vec<[Field64; 2]> = Prng::new()
+            .unwrap()
+            .take(INPUT_LEN)
+            .map(|k| [Field64::one(), k])
+            .collect();
+        let leaf_value = [Field128::one(), Prng::new().unwrap().get()];
+        let keys = TreeIdpf::<Field64, Field128, PrgAes128, 16>::gen(
+            &input,
+            values.iter().cloned(),
+            leaf_value,
+        )
+        .unwrap();
+
+        // Try evaluating the IDPF keys on all prefixes.
+        for (prefix_len, value) in values.iter().enumerate() {
+            let res = eval_idpf(&keys, &input.prefix(prefix_len), &IdpfValue::Inner(*value));
+            assert!(res.is_ok(), "prefix_len={} error: {:?}", prefix_len, res);
+        }
+        eval_idpf(&keys, &input, &IdpfValue::Leaf(leaf_value)).unwrap();
+
+        // Try evaluating the IDPF keys on incorrect prefixes.
+        for prefix_len in [1, 2, 17, 128] {
+            let mut prefix = input.prefix(prefix_len);
+            prefix.bits[prefix_len - 1] ^= true;
+            eval_idpf(
+                &keys,
+                &prefix,
+                &IdpfValue::Inner([Field64::zero(), Field64::zero()]),
+            )
+            .unwrap();
+        }
+        let mut prefix = input.clone();
+        prefix.bits[INPUT_LEN - 1] ^= true;
+        eval_idpf(
+            &keys,
+            &prefix,
+            &IdpfValue::Leaf([Field128::zero(), Field128::zero()]),
+        )
+        .unwrap();
+        eval_idpf(
+            &keys,
+            &IdpfInput::new(&[0; INPUT_LEN / 8], INPUT_LEN).unwrap(),
+            &IdpfValue::Leaf([Field128::zero(), Field128::zero()]),
         )
         .unwrap();
+
+        // Prefixes longer than the input are rejected.
+        keys[0]
+            .eval(&IdpfInput::new(&[0; INPUT_LEN / 8 + 1], INPUT_LEN + 1).unwrap())
+            .unwrap_err();
+
+        // The size of each key is linear in the length of the input.
+        for key in keys.iter() {
+            let encoded = key.get_encoded();
+            assert_eq!(
+                encoded.len(),
+                16 + 1
+                    + 8
+                    + INPUT_LEN * (16 + 1)
+                    + INPUT_LEN * 2 * Field64::ENCODED_SIZE
+                    + 2 * Field128::ENCODED_SIZE
+            );
+            let got = TreeIdpf::<Field64, Field128, PrgAes128, 16>::get_decoded(&encoded).unwrap();
+            assert_eq!(got.get_encoded(), encoded);
+            assert_eq!(got.eval(&input).unwrap(), key.eval(&input).unwrap(),);
+        }
+    }
+
+    #[test]
+    fn test_tree_idpf_cache() {
+        const INPUT_LEN: usize = 64;
+
+        let input = IdpfInput::new(b"cache me", INPUT_LEN).unwrap();
+        let values = Prng::new()
+            .unwrap()
+            .take(INPUT_LEN)
+            .map(|k| [Field128::one(), k]);
+        let keys = TreeIdpf::<Field128, Field128, PrgAes128, 16>::gen(
+            &input,
+            values,
+            [Field128::one(), Field128::one()],
+        )
+        .unwrap();
+
+        for key in keys.iter() {
+            let mut cache = IdpfCache::new();
+            for level in 0..INPUT_LEN + 1 {
+                // Evaluate on the prefix of the input and its sibling.
+                let prefix = input.prefix(level);
+                assert_eq!(
+                    key.eval_with_cache(&prefix, &mut cache).unwrap(),
+                    key.eval(&prefix).unwrap()
+                );
+                if level > 0 {
+                    let mut sibling = prefix.clone();
+                    sibling.bits[level - 1] ^= true;
+                    assert_eq!(
+                        key.eval_with_cache(&sibling, &mut cache).unwrap(),
+                        key.eval(&sibling).unwrap()
+                    );
+                }
+
+                // Only the nodes on the previous and current levels are retained.
+                assert!(cache.get_or_default::<TreeIdpfCache<16>>().nodes.len() <= 4);
+            }
+
+            // Evaluating on a prefix whose parent is not cached starts from the root.
+            let prefix = input.prefix(INPUT_LEN / 2);
+            assert_eq!(
+                key.eval_with_cache(&prefix, &mut cache).unwrap(),
+                key.eval(&prefix).unwrap()
+            );
+        }
     }
 
     fn eval_idpf<I, const KEY_LEN: usize, const OUT_LEN: usize>(
         keys: &[I; KEY_LEN],
         input: &IdpfInput,
-        expected_output: &[I::Field; OUT_LEN],
+        expected_output: &IdpfValue<I::InnerField, I::LeafField, OUT_LEN>,
     ) -> Result<(), VdafError>
     where
         I: Idpf<KEY_LEN, OUT_LEN>,
     {
-        let mut output = [I::Field::zero(); OUT_LEN];
-        for key in keys {
-            let output_share = key.eval(input)?;
-            for (x, y) in output.iter_mut().zip(output_share) {
+        fn add<F: FieldElement, const N: usize>(mut x: [F; N], y: [F; N]) -> [F; N] {
+            for (x, y) in x.iter_mut().zip(y) {
                 *x += y;
             }
+            x
+        }
+
+        let mut output = None;
+        for key in keys {
+            let output_share = key.eval(input)?;
+            output = Some(match (output, output_share) {
+                (None, y) => y,
+                (Some(IdpfValue::Inner(x)), IdpfValue::Inner(y)) => IdpfValue::Inner(add(x, y)),
+                (Some(IdpfValue::Leaf(x)), IdpfValue::Leaf(y)) => IdpfValue::Leaf(add(x, y)),
+                _ => {
+                    return Err(VdafError::Uncategorized(
+                        "eval_idpf(): output shares are for different levels".to_string(),
+                    ))
+                }
+            });
         }
 
-        if expected_output != &output {
+        if Some(expected_output) != output.as_ref() {
             return Err(VdafError::Uncategorized(format!(
                 "eval_idpf(): unexpected output: got {:?}; want {:?}",
                 output, expected_output
@@ -896,11 +2330,11 @@ mod tests {
         let input = vec![IdpfInput::new(&[0b0110_1000], INPUT_LEN).unwrap()];
 
         let mut agg_param = BTreeSet::new();
-        agg_param.insert(input[0]);
+        agg_param.insert(input[0].clone());
         check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), &[1]);
 
         // Try evaluating the VDAF on each prefix of the input.
-        for prefix_len in 0..input[0].level + 1 {
+        for prefix_len in 0..input[0].level() + 1 {
             let mut agg_param = BTreeSet::new();
             agg_param.insert(input[0].prefix(prefix_len));
             check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), &[1]);
@@ -935,20 +2369,26 @@ mod tests {
         agg_param.insert(IdpfInput::new(&[0b0000_0111], 6).unwrap());
         agg_param.insert(IdpfInput::new(&[0b0000_1000], 7).unwrap());
         let input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
-        run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares).unwrap_err();
+        assert_matches!(
+            run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares),
+            Err(VdafError::Prepare(PrepareError::BadAggParam(_)))
+        );
 
         // Try evaluating the VDAF with malformed inputs.
         //
         // This IDPF key pair evaluates to 1 everywhere, which is illegal.
         let mut input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
         for (i, x) in input_shares[0].idpf.data0.iter_mut().enumerate() {
-            if i != input[0].index {
+            if i != input[0].data_index() {
                 *x += Field128::one();
             }
         }
         let mut agg_param = BTreeSet::new();
         agg_param.insert(IdpfInput::new(&[0b0000_0111], 8).unwrap());
-        run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares).unwrap_err();
+        assert_matches!(
+            run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares),
+            Err(VdafError::Prepare(PrepareError::VerifyFailed))
+        );
 
         // This IDPF key pair has a garbled authentication vector.
         let mut input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
@@ -957,7 +2397,314 @@ mod tests {
         }
         let mut agg_param = BTreeSet::new();
         agg_param.insert(IdpfInput::new(&[0b0000_0111], 8).unwrap());
-        run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares).unwrap_err();
+        assert_matches!(
+            run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares),
+            Err(VdafError::Prepare(PrepareError::VerifyFailed))
+        );
+    }
+
+    #[test]
+    fn test_poplar1_tree_idpf() {
+        const INPUT_LEN: usize = 256;
+
+        let vdaf: Poplar1<TreeIdpf<Field128, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(INPUT_LEN);
+
+        let mut data = [0; INPUT_LEN / 8];
+        data[..29].copy_from_slice(b"https://example.com/some/path");
+        let mut other_data = data;
+        other_data[31] = 1;
+        let input = vec![
+            IdpfInput::new(&data, INPUT_LEN).unwrap(),
+            IdpfInput::new(&other_data, INPUT_LEN).unwrap(),
+            IdpfInput::new(&data, INPUT_LEN).unwrap(),
+        ];
+
+        // The inputs share a prefix of length 248.
+        for (prefix_len, counts) in [(0, &[3][..]), (8, &[3]), (248, &[3]), (256, &[2, 1])] {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input[0].prefix(prefix_len));
+            agg_param.insert(input[1].prefix(prefix_len));
+            check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), counts);
+        }
+
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let nonce = b"this is a nonce";
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input[0].clone());
+        agg_param.insert(input[1].clone());
+
+        // This IDPF key pair has a garbled correction word for the last level.
+        let mut input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
+        input_shares[0].idpf.leaf_cw[0] += Field128::one();
+        input_shares[1].idpf.leaf_cw[0] += Field128::one();
+        assert_matches!(
+            run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares),
+            Err(VdafError::Prepare(PrepareError::VerifyFailed))
+        );
+
+        // This IDPF key pair has a garbled authentication value.
+        let mut input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
+        input_shares[0].idpf.leaf_cw[1] += Field128::one();
+        input_shares[1].idpf.leaf_cw[1] += Field128::one();
+        assert_matches!(
+            run_vdaf_prepare(&vdaf, &verify_params, &agg_param, nonce, input_shares),
+            Err(VdafError::Prepare(PrepareError::VerifyFailed))
+        );
+    }
+
+    #[test]
+    fn test_poplar1_spliced_idpf_key() {
+        let vdaf: Poplar1<TreeIdpf<Field128, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(8);
+        let long_vdaf: Poplar1<TreeIdpf<Field128, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(16);
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input = IdpfInput::new(b"a", 8).unwrap();
+        let long_input = IdpfInput::new(b"ab", 16).unwrap();
+        let nonce = b"this is a nonce";
+
+        // Splice the IDPF key of a 16-bit input into the input share of an 8-bit input.
+        let mut input_share = vdaf.shard(&public_param, &input).unwrap().swap_remove(0);
+        input_share.idpf = long_vdaf
+            .shard(&public_param, &long_input)
+            .unwrap()
+            .swap_remove(0)
+            .idpf;
+
+        assert_matches!(
+            Poplar1InputShare::<TreeIdpf<Field128, Field128, PrgAes128, 16>, 16>::get_decoded_with_param(
+                &verify_params[0],
+                &input_share.get_encoded()
+            ),
+            Err(CodecError::UnexpectedValue)
+        );
+
+        // A share constructed in memory must not cause a panic when evaluated past the end of
+        // the sketch.
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(long_input.prefix(12));
+        assert_matches!(
+            vdaf.prepare_init(&verify_params[0], &agg_param, nonce, &input_share),
+            Err(VdafError::Prepare(PrepareError::LengthMismatch { .. }))
+        );
+    }
+
+    #[test]
+    fn test_poplar1_cache() {
+        const INPUT_LEN: usize = 32;
+
+        let vdaf: Poplar1<TreeIdpf<Field128, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(INPUT_LEN);
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input = IdpfInput::new(b"abcd", INPUT_LEN).unwrap();
+        let input_shares = vdaf.shard(&public_param, &input).unwrap();
+        let nonce = b"this is a nonce";
+
+        // Walk down the prefix tree, then revisit a lower level. The prepare messages computed
+        // with the cache must match those computed without.
+        let mut caches = [Poplar1Cache::new(), Poplar1Cache::new()];
+        let levels = (1..INPUT_LEN + 1).chain([4, 5]);
+        for level in levels {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input.prefix(level));
+            let mut sibling = input.prefix(level);
+            sibling.bits[level - 1] ^= true;
+            agg_param.insert(sibling);
+
+            for ((cache, verify_param), input_share) in caches
+                .iter_mut()
+                .zip(verify_params.iter())
+                .zip(input_shares.iter())
+            {
+                let got = vdaf
+                    .prepare_init_with_cache(cache, verify_param, &agg_param, nonce, input_share)
+                    .unwrap();
+                let want = vdaf
+                    .prepare_init(verify_param, &agg_param, nonce, input_share)
+                    .unwrap();
+                match (vdaf.prepare_step(got, None), vdaf.prepare_step(want, None)) {
+                    (PrepareTransition::Continue(_, got), PrepareTransition::Continue(_, want)) => {
+                        assert_eq!(got, want, "level {}", level)
+                    }
+                    _ => panic!("unexpected transition"),
+                }
+                assert_eq!(cache.len(), 1);
+            }
+        }
+
+        for cache in caches.iter_mut() {
+            cache.remove(nonce);
+            assert!(cache.is_empty());
+        }
+
+        // Both aggregators may share a cache, but a nonce may not be reused for a different input
+        // share.
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input.prefix(1));
+        let mut cache = Poplar1Cache::new();
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            vdaf.prepare_init_with_cache(&mut cache, verify_param, &agg_param, nonce, input_share)
+                .unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+
+        let other_input_shares = vdaf.shard(&public_param, &input).unwrap();
+        assert_matches!(
+            vdaf.prepare_init_with_cache(
+                &mut cache,
+                &verify_params[0],
+                &agg_param,
+                nonce,
+                &other_input_shares[0]
+            ),
+            Err(VdafError::Prepare(PrepareError::CacheMismatch))
+        );
+
+        cache.remove(nonce);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_heavy_hitters() {
+        const INPUT_LEN: usize = 16;
+
+        let vdaf: Poplar1<TreeIdpf<Field128, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(INPUT_LEN);
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+
+        let measurements = [(b"ab", 5), (b"ac", 3), (b"zz", 1), (b"ad", 2), (b"ba", 4)];
+        let mut reports = Vec::new();
+        for (data, count) in measurements.iter() {
+            let input = IdpfInput::new(*data, INPUT_LEN).unwrap();
+            for _ in 0..*count {
+                let nonce = reports.len().to_be_bytes().to_vec();
+                reports.push((nonce, vdaf.shard(&public_param, &input).unwrap()));
+            }
+        }
+
+        let heavy_hitters = HeavyHitters::new(&vdaf, 3)
+            .unwrap()
+            .run_in_process(&verify_params, &reports)
+            .unwrap();
+        let want: BTreeMap<IdpfInput, u64> = [(b"ab", 5), (b"ac", 3), (b"ba", 4)]
+            .iter()
+            .map(|(data, count)| (IdpfInput::new(*data, INPUT_LEN).unwrap(), *count))
+            .collect();
+        assert_eq!(heavy_hitters, want);
+
+        // Malformed reports are dropped.
+        let mut input_shares = vdaf
+            .shard(&public_param, &IdpfInput::new(b"ac", INPUT_LEN).unwrap())
+            .unwrap();
+        input_shares[0].idpf.leaf_cw[0] += Field128::one();
+        input_shares[1].idpf.leaf_cw[0] += Field128::one();
+        reports.push((b"malformed".to_vec(), input_shares));
+        let heavy_hitters = HeavyHitters::new(&vdaf, 3)
+            .unwrap()
+            .run_in_process(&verify_params, &reports)
+            .unwrap();
+        assert_eq!(heavy_hitters, want);
+
+        // No input occurs often enough.
+        let heavy_hitters = HeavyHitters::new(&vdaf, 6)
+            .unwrap()
+            .run_in_process(&verify_params, &reports)
+            .unwrap();
+        assert!(heavy_hitters.is_empty());
+
+        // A zero threshold would keep every candidate prefix.
+        assert_matches!(
+            HeavyHitters::new(&vdaf, 0),
+            Err(VdafError::InvalidParameter(_))
+        );
+    }
+
+    #[test]
+    fn test_poplar1_inner_leaf_fields() {
+        const INPUT_LEN: usize = 16;
+
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(INPUT_LEN);
+        let input = vec![
+            IdpfInput::new(b"ab", INPUT_LEN).unwrap(),
+            IdpfInput::new(b"ac", INPUT_LEN).unwrap(),
+            IdpfInput::new(b"ab", INPUT_LEN).unwrap(),
+        ];
+        // The inputs share a prefix of length 8.
+        for (prefix_len, counts) in [(1, &[3][..]), (8, &[3]), (9, &[2, 1]), (16, &[2, 1])] {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input[0].prefix(prefix_len));
+            agg_param.insert(input[1].prefix(prefix_len));
+            check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), counts);
+        }
+
+        // Prepare messages and aggregate shares are in the field for the level of the prefixes.
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input_shares = vdaf.shard(&public_param, &input[0]).unwrap();
+        let nonce = b"this is a nonce";
+        for (prefix_len, field_size) in [
+            (8, Field64::ENCODED_SIZE),
+            (INPUT_LEN, Field128::ENCODED_SIZE),
+        ] {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input[0].prefix(prefix_len));
+            for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+                let step = vdaf
+                    .prepare_init(verify_param, &agg_param, nonce, input_share)
+                    .unwrap();
+                let (step, msg) = match vdaf.prepare_step(step, None) {
+                    PrepareTransition::Continue(step, msg) => (step, msg),
+                    _ => panic!("unexpected transition"),
+                };
+                let encoded = msg.get_encoded();
+                assert_eq!(encoded.len(), 2 + 3 * field_size);
+                let got = Poplar1PrepareMessage::get_decoded_with_param(&step, &encoded).unwrap();
+                assert_eq!(got, msg);
+            }
+
+            let agg_share = vdaf.aggregate(&agg_param, []).unwrap();
+            let encoded: Vec<u8> = (&agg_share).into();
+            assert_eq!(encoded.len(), 1 + field_size);
+            assert_eq!(
+                Poplar1AggregateShare::try_from(encoded.as_slice()).unwrap(),
+                agg_share
+            );
+        }
+
+        // Output shares from different levels cannot be aggregated together.
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input[0].clone());
+        vdaf.aggregate(
+            &agg_param,
+            [Poplar1OutputShare::Inner(OutputShare(vec![Field64::one()]))],
+        )
+        .unwrap_err();
+
+        let heavy_hitters = HeavyHitters::new(&vdaf, 2)
+            .unwrap()
+            .run_in_process(
+                &verify_params,
+                &[
+                    (
+                        b"nonce 0".to_vec(),
+                        vdaf.shard(&public_param, &input[0]).unwrap(),
+                    ),
+                    (
+                        b"nonce 1".to_vec(),
+                        vdaf.shard(&public_param, &input[1]).unwrap(),
+                    ),
+                    (
+                        b"nonce 2".to_vec(),
+                        vdaf.shard(&public_param, &input[2]).unwrap(),
+                    ),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            heavy_hitters.into_iter().collect::<Vec<_>>(),
+            [(input[0].clone(), 2)]
+        );
     }
 
     #[test]
@@ -971,6 +2718,264 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_poplar1_shake256() {
+        const INPUT_LEN: usize = 8;
+
+        let input = vec![IdpfInput::new(&[0b0110_1000], INPUT_LEN).unwrap()];
+
+        let vdaf: Poplar1Shake256<ToyIdpf<Field128>> = Poplar1::new(INPUT_LEN);
+        for prefix_len in 0..input[0].level() + 1 {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input[0].prefix(prefix_len));
+            check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), &[1]);
+        }
+        for prefix_len in [4, INPUT_LEN] {
+            test_serialization(&vdaf, &input[0], prefix_len);
+        }
+
+        let vdaf: Poplar1Shake256<TreeIdpf<Field64, Field128, PrgShake256, 32>> =
+            Poplar1::new(INPUT_LEN);
+        for prefix_len in 0..input[0].level() + 1 {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input[0].prefix(prefix_len));
+            check_btree(&run_vdaf(&vdaf, &agg_param, input.clone()).unwrap(), &[1]);
+        }
+        for prefix_len in [4, INPUT_LEN] {
+            test_serialization(&vdaf, &input[0], prefix_len);
+        }
+    }
+
+    // Runs the VDAF on `input` with the prefix of length `prefix_len` as the aggregation
+    // parameter. Checks that the verification parameters, input shares, aggregation parameter,
+    // prepare steps and messages of each round, and aggregate shares round-trip through their
+    // encodings, and that the decoded messages yield the expected aggregate.
+    fn test_serialization<I, P, const L: usize>(
+        vdaf: &Poplar1<I, P, L>,
+        input: &IdpfInput,
+        prefix_len: usize,
+    ) where
+        I: Idpf<2, 2>,
+        P: Prg<L>,
+    {
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input_shares = vdaf.shard(&public_param, input).unwrap();
+        let nonce = b"this is a nonce";
+
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input.prefix(prefix_len));
+        let encoded = agg_param.get_encoded();
+        let agg_param = BTreeSet::<IdpfInput>::get_decoded(&encoded).unwrap();
+        assert_eq!(agg_param.get_encoded(), encoded);
+
+        let mut steps = Vec::new();
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            let encoded = verify_param.get_encoded();
+            assert_eq!(encoded.len(), 1 + L);
+            let got = Poplar1VerifyParam::get_decoded_with_param(vdaf, &encoded).unwrap();
+            assert_eq!(&got, verify_param);
+
+            let encoded = input_share.get_encoded();
+            let input_share =
+                Poplar1InputShare::<I, L>::get_decoded_with_param(verify_param, &encoded).unwrap();
+            assert_eq!(input_share.get_encoded(), encoded);
+
+            steps.push(
+                vdaf.prepare_init(verify_param, &agg_param, nonce, &input_share)
+                    .unwrap(),
+            );
+        }
+
+        let mut inbound = None;
+        let out_shares = loop {
+            let mut outbound = Vec::new();
+            let mut out_shares = Vec::new();
+            for (step, verify_param) in steps.iter_mut().zip(verify_params.iter()) {
+                let decoded = Poplar1PrepareStep::get_decoded_with_param(
+                    &(vdaf, verify_param),
+                    &step.get_encoded(),
+                )
+                .unwrap();
+                assert_eq!(&decoded, step);
+
+                match vdaf.prepare_step(decoded, inbound.clone()) {
+                    PrepareTransition::Continue(new_step, msg) => {
+                        let got = Poplar1PrepareMessage::get_decoded_with_param(
+                            &new_step,
+                            &msg.get_encoded(),
+                        )
+                        .unwrap();
+                        assert_eq!(got, msg);
+                        *step = new_step;
+                        outbound.push(msg);
+                    }
+                    PrepareTransition::Finish(out_share) => out_shares.push(out_share),
+                    PrepareTransition::Fail(err) => panic!("prepare failed: {}", err),
+                }
+            }
+
+            if !out_shares.is_empty() {
+                break out_shares;
+            }
+            inbound = Some(vdaf.prepare_preprocess(outbound).unwrap());
+        };
+
+        let agg_shares = out_shares
+            .into_iter()
+            .map(|out_share| {
+                let agg_share = Poplar1AggregateShare::from(out_share);
+                let encoded = Vec::from(&agg_share);
+                let got = Poplar1AggregateShare::try_from(encoded.as_slice()).unwrap();
+                assert_eq!(got, agg_share);
+                got
+            })
+            .collect::<Vec<_>>();
+        check_btree(&vdaf.unshard(&agg_param, agg_shares).unwrap(), &[1]);
+    }
+
+    #[test]
+    fn test_poplar1_prepare_step_serialization() {
+        const INPUT_LEN: usize = 16;
+
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(INPUT_LEN);
+        let input = IdpfInput::new(b"hi", INPUT_LEN).unwrap();
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input_shares = vdaf.shard(&public_param, &input).unwrap();
+        let nonce = b"this is a nonce";
+
+        // Check both an inner level and the leaves.
+        for prefix_len in [12, INPUT_LEN] {
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(input.prefix(prefix_len));
+            agg_param.insert(IdpfInput::new(b"ha", INPUT_LEN).unwrap().prefix(prefix_len));
+
+            let mut steps = verify_params
+                .iter()
+                .zip(input_shares.iter())
+                .map(|(verify_param, input_share)| {
+                    vdaf.prepare_init(verify_param, &agg_param, nonce, input_share)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            // Persist the state before each round and resume from the decoded state.
+            let mut inbound = None;
+            let out_shares = loop {
+                let mut outbound = Vec::new();
+                let mut out_shares = Vec::new();
+                for (step, verify_param) in steps.iter_mut().zip(verify_params.iter()) {
+                    let decoded = Poplar1PrepareStep::get_decoded_with_param(
+                        &(&vdaf, verify_param),
+                        &step.get_encoded(),
+                    )
+                    .unwrap();
+                    assert_eq!(&decoded, step);
+
+                    match vdaf.prepare_step(decoded, inbound.clone()) {
+                        PrepareTransition::Continue(new_step, msg) => {
+                            *step = new_step;
+                            outbound.push(msg);
+                        }
+                        PrepareTransition::Finish(out_share) => out_shares.push(out_share),
+                        PrepareTransition::Fail(err) => panic!("prepare failed: {}", err),
+                    }
+                }
+
+                if !out_shares.is_empty() {
+                    break out_shares;
+                }
+                inbound = Some(vdaf.prepare_preprocess(outbound).unwrap());
+            };
+
+            let agg_shares = out_shares
+                .into_iter()
+                .map(Poplar1AggregateShare::from)
+                .collect::<Vec<_>>();
+            check_btree(&vdaf.unshard(&agg_param, agg_shares).unwrap(), &[0, 1]);
+        }
+
+        // Unknown states are rejected.
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input.prefix(8));
+        let mut encoded = vdaf
+            .prepare_init(&verify_params[0], &agg_param, nonce, &input_shares[0])
+            .unwrap()
+            .get_encoded();
+        encoded[1] = 3;
+        assert!(
+            Poplar1PrepareStep::get_decoded_with_param(&(&vdaf, &verify_params[0]), &encoded)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_poplar1_with_rng() {
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(16);
+        let input = IdpfInput::new(b"hi", 16).unwrap();
+
+        // The same seeded RNG yields the same parameters and input shares.
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        let (public_param, verify_params) = vdaf.setup_with_rng(&mut rng).unwrap();
+        let input_shares = vdaf
+            .shard_with_rng(&public_param, &input, &mut rng)
+            .unwrap();
+
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        let (_, got_verify_params) = vdaf.setup_with_rng(&mut rng).unwrap();
+        let got_input_shares = vdaf
+            .shard_with_rng(&public_param, &input, &mut rng)
+            .unwrap();
+        assert_eq!(got_verify_params, verify_params);
+        for (got, want) in got_input_shares.iter().zip(input_shares.iter()) {
+            assert_eq!(got.get_encoded(), want.get_encoded());
+        }
+
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(input.prefix(8));
+        let out_shares = run_vdaf_prepare(
+            &vdaf,
+            &verify_params,
+            &agg_param,
+            b"this is a nonce",
+            input_shares,
+        )
+        .unwrap();
+        let agg_shares = out_shares
+            .into_iter()
+            .map(Poplar1AggregateShare::from)
+            .collect::<Vec<_>>();
+        check_btree(&vdaf.unshard(&agg_param, agg_shares).unwrap(), &[1]);
+    }
+
+    #[test]
+    fn test_vec_poplar1() {
+        let t: TestVector<u128, (usize, Vec<u128>), Vec<u64>> =
+            serde_json::from_str(include_str!("testdata/poplar1_aes128.json")).unwrap();
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(4);
+
+        check_test_vector(&vdaf, &t);
+    }
+
+    #[test]
+    fn test_vec_poplar1_round_trip() {
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(4);
+
+        // Check that a test vector for an inner level of the prefix tree is checked against its
+        // JSON representation.
+        let t = generate_test_vector(&vdaf, &(1, vec![0b00, 0b01, 0b11]), &[1, 1, 7, 12]).unwrap();
+        assert_eq!(t.agg_result, vec![2, 1, 1]);
+        let t = serde_json::from_str(&serde_json::to_string(&t).unwrap()).unwrap();
+        check_test_vector(&vdaf, &t);
+
+        // Measurements and prefixes must fit in the number of bits.
+        assert!(generate_test_vector(&vdaf, &(1, vec![0b100]), &[1]).is_err());
+        assert!(generate_test_vector(&vdaf, &(1, vec![0b00]), &[16]).is_err());
+    }
+
     fn check_btree(btree: &BTreeMap<IdpfInput, u64>, counts: &[u64]) {
         for (got, want) in btree.values().zip(counts.iter()) {
             assert_eq!(got, want, "got {:?} want {:?}", btree.values(), counts);
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
-//! This module implements PRGs as specified in draft-patton-cfrg-vdaf-01.
+//! This module implements PRGs as specified in draft-patton-cfrg-vdaf-01, as well as PRGs based
+//! on the cSHAKE128 and cSHAKE256 extendable-output functions (XOFs) along the lines of later VDAF
+//! drafts.
 
 use crate::vdaf::{CodecError, Decode, Encode};
 use aes::{
@@ -9,15 +11,52 @@ use aes::{
 };
 use cmac::{Cmac, Mac};
 use ctr::Ctr64BE;
+use rand_core::RngCore;
+use sha3::{
+    digest::{self, ExtendableOutput, XofReader},
+    CShake128, CShake128Core, CShake256, CShake256Core,
+};
 use std::{
     fmt::{Debug, Formatter},
     io::{Cursor, Read},
+    num::NonZeroU32,
 };
 
-/// Function pointer to fill a buffer with random bytes. Under normal operation,
-/// `getrandom::getrandom()` will be used, but other implementations can be used to control
-/// randomness when generating or verifying test vectors.
-pub(crate) type RandSource = fn(&mut [u8]) -> Result<(), getrandom::Error>;
+/// A source of random bytes. Under normal operation, `getrandom::getrandom()` is used, but other
+/// sources can be used to control randomness, e.g., for reproducible simulations, for generating or
+/// verifying test vectors, or for drawing entropy from a hardware device.
+///
+/// Any function or closure with the signature of `getrandom::getrandom()` is a `RandSource`, as is
+/// any [`RngCore`] wrapped in an [`RngSource`].
+pub trait RandSource {
+    /// Fills `buf` with random bytes.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), getrandom::Error>;
+}
+
+impl<F> RandSource for F
+where
+    F: FnMut(&mut [u8]) -> Result<(), getrandom::Error>,
+{
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), getrandom::Error> {
+        self(buf)
+    }
+}
+
+/// A [`RandSource`] that draws random bytes from an [`RngCore`].
+#[derive(Clone, Debug)]
+pub struct RngSource<R>(pub R);
+
+impl<R: RngCore> RandSource for RngSource<R> {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), getrandom::Error> {
+        self.0.try_fill_bytes(buf).map_err(|e| {
+            // Preserve the error code of the RNG, if there is one.
+            let code = e
+                .code()
+                .unwrap_or_else(|| NonZeroU32::new(getrandom::Error::CUSTOM_START).unwrap());
+            getrandom::Error::from(code)
+        })
+    }
+}
 
 /// Input of [`Prg`].
 #[derive(Clone, Debug, Eq)]
@@ -26,12 +65,13 @@ pub struct Seed<const L: usize>(pub(crate) [u8; L]);
 impl<const L: usize> Seed<L> {
     /// Generate a uniform random seed.
     pub fn generate() -> Result<Self, getrandom::Error> {
-        Self::from_rand_source(getrandom::getrandom)
+        Self::from_rand_source(&mut getrandom::getrandom)
     }
 
-    pub(crate) fn from_rand_source(rand_source: RandSource) -> Result<Self, getrandom::Error> {
+    /// Generate a uniform random seed from the given source of randomness.
+    pub fn from_rand_source(rand_source: &mut dyn RandSource) -> Result<Self, getrandom::Error> {
         let mut seed = [0; L];
-        rand_source(&mut seed)?;
+        rand_source.fill(&mut seed)?;
         Ok(Self(seed))
     }
 
@@ -164,10 +204,117 @@ impl Debug for SeedStreamAes128 {
     }
 }
 
+/// The customization string of the cSHAKE instances used by [`PrgCShake128`] and
+/// [`PrgShake256`]. It separates the output of the PRGs from other uses of cSHAKE with the same
+/// input.
+const PRG_CSHAKE_CUSTOM: &[u8] = b"libprio prg";
+
+/// A PRG based on cSHAKE128 [SP800-185]. The seed and each fragment of the info string are
+/// absorbed into cSHAKE128 with customization string `"libprio prg"`, and the seed stream is the
+/// XOF's output. Because the seed has a fixed length, this is usable with seeds of any length `L`,
+/// in particular 16 and 32 bytes.
+///
+/// This construction is specific to this crate. It is not the `PrgCShake128` of later VDAF drafts and
+/// does not interoperate with other implementations.
+///
+/// Unlike [`PrgAes128`], this PRG does not rely on AES and is therefore well-suited to platforms
+/// without hardware support for AES.
+///
+/// [SP800-185]: https://nvlpubs.nist.gov/nistpubs/SpecialPublications/NIST.SP.800-185.pdf
+#[derive(Clone)]
+pub struct PrgCShake128(CShake128);
+
+impl<const L: usize> Prg<L> for PrgCShake128 {
+    type SeedStream = SeedStreamCShake128;
+
+    fn init(seed: &Seed<L>) -> Self {
+        let mut cshake = CShake128::from_core(CShake128Core::new(PRG_CSHAKE_CUSTOM));
+        digest::Update::update(&mut cshake, &seed.0);
+        Self(cshake)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        digest::Update::update(&mut self.0, data);
+    }
+
+    fn into_seed_stream(self) -> SeedStreamCShake128 {
+        SeedStreamCShake128(self.0.finalize_xof())
+    }
+}
+
+impl Debug for PrgCShake128 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Avoid printing the XOF state, which depends on the seed.
+        f.debug_struct("PrgCShake128").finish_non_exhaustive()
+    }
+}
+
+/// The output stream of cSHAKE128.
+pub struct SeedStreamCShake128(<CShake128 as ExtendableOutput>::Reader);
+
+impl SeedStream for SeedStreamCShake128 {
+    fn fill(&mut self, buf: &mut [u8]) {
+        XofReader::read(&mut self.0, buf);
+    }
+}
+
+impl Debug for SeedStreamCShake128 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeedStreamCShake128")
+            .finish_non_exhaustive()
+    }
+}
+
+/// The PRG based on cSHAKE256. This is constructed the same way as [`PrgCShake128`], but provides a
+/// 256-bit security level when used with 32-byte seeds, i.e., as a [`Prg<32>`](Prg).
+#[derive(Clone)]
+pub struct PrgShake256(CShake256);
+
+impl<const L: usize> Prg<L> for PrgShake256 {
+    type SeedStream = SeedStreamShake256;
+
+    fn init(seed: &Seed<L>) -> Self {
+        let mut cshake = CShake256::from_core(CShake256Core::new(PRG_CSHAKE_CUSTOM));
+        digest::Update::update(&mut cshake, &seed.0);
+        Self(cshake)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        digest::Update::update(&mut self.0, data);
+    }
+
+    fn into_seed_stream(self) -> SeedStreamShake256 {
+        SeedStreamShake256(self.0.finalize_xof())
+    }
+}
+
+impl Debug for PrgShake256 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Avoid printing the XOF state, which depends on the seed.
+        f.debug_struct("PrgShake256").finish_non_exhaustive()
+    }
+}
+
+/// The output stream of cSHAKE256.
+pub struct SeedStreamShake256(<CShake256 as ExtendableOutput>::Reader);
+
+impl SeedStream for SeedStreamShake256 {
+    fn fill(&mut self, buf: &mut [u8]) {
+        XofReader::read(&mut self.0, buf);
+    }
+}
+
+impl Debug for SeedStreamShake256 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeedStreamShake256").finish_non_exhaustive()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{field::Field128, prng::Prng};
+    use rand::{rngs::StdRng, SeedableRng};
     use serde::{Deserialize, Serialize};
     use std::convert::TryInto;
 
@@ -180,6 +327,40 @@ mod tests {
   "derived_seed": "ccf3be704c982182ad2961e9795a88aa",
   "expanded_vec": "ccf3be704c982182ad2961e9795a88aa8df71c0b5ea5c13bcf3173c3f3626505e1bf4738874d5405805082cc38c55d1f04f85fbb88b8cf8592ffed8a4ac7f76991c58d850a15e8deb34fb289ab6fab584554ffef16c683228db2b76e792ca4f3c15760044d0703b438c2aefd7975c5dd4b9992ee6f87f20e570572dea18fa580ee17204903c1234f1332d47a442ea636580518ce7aa5943c415117460a049bc19cc81edbb0114d71890cbdbe4ea2664cd038e57b88fb7fd3557830ad363c20b9840d35fd6bee6c3c8424f026ee7fbca3daf3c396a4d6736d7bd3b65b2c228d22a40f4404e47c61b26ac3c88bebf2f268fa972f8831f18bee374a22af0f8bb94d9331a1584bdf8cf3e8a5318b546efee8acd28f6cba8b21b9d52acbae8e726500340da98d643d0a5f1270ecb94c574130cea61224b0bc6d438b2f4f74152e72b37e6a9541c9dc5515f8f98fd0d1bce8743f033ab3e8574180ffc3363f3a0490f6f9583bf73a87b9bb4b51bfd0ef260637a4288c37a491c6cbdc46b6a86cd26edf611793236e912e7227bfb85b560308b06238bbd978f72ed4a58583cf0c6e134066eb6b399ad2f26fa01d69a62d8a2d04b4b8acf82299b07a834d4c2f48fee23a24c20307f9cabcd34b6d69f1969588ebde777e46e9522e866e6dd1e14119a1cb4c0709fa9ea347d9f872e76a39313e7d49bfbf3e5ce807183f43271ba2b5c6aaeaef22da301327c1fd9fedde7c5a68d9b97fa6eb687ec8ca692cb0f631f46e6699a211a1254026c9a0a43eceb450dc97cfa923321baf1f4b6f233260d46182b844dccec153aaddd20f920e9e13ff11434bcd2aa632bf4f544f41b5ddced962939676476f70e0b8640c3471fc7af62d80053781295b070388f7b7f1fa66220cb3"
 }
+"#;
+
+    // Test vectors for `PrgCShake128` with 16- and 32-byte seeds. There are no published vectors
+    // for this PRG. These were generated independently of this crate, by a Keccak implementation
+    // checked against the cSHAKE128 sample values published with SP 800-185, by computing
+    // cSHAKE128(seed || info) with customization string "libprio prg" and decoding the output as
+    // big-endian `Field128` elements with rejection sampling.
+    const TEST_PRG_CSHAKE128_16_FIELD128: &str = r#"{
+  "seed": "01010101010101010101010101010101",
+  "info": "696e666f20737472696e67",
+  "length": 40,
+  "derived_seed": "d450e65a7fc527a07e28351e67dc7769",
+  "expanded_vec": "d450e65a7fc527a07e28351e67dc776928b485e7d116a22a3739c8acd909b985a5c0197ed08501fedeca5d3e5fe69bd23206321eae70ed41c9186f8df40c2f91b7a154369fc082a90132545bf07f891e9b1b85089621671a5099b410e4395d727d37abd870d9c352c0c51a0cd02050f32a988c64f023c44bf80cb6449a6bfc9480f55f3d2a36c90a2fa35b872ab3626a7f93cc976ae5907dd074e96a2e507db8f44f108f1beba1d06ff31fbc41e016b270e3defa04e582f21145c87af08f692eddf7635ffc4a9bec577387638d02a9ad455efd4e949c6a900acee60a427df11d76814e63a93e0da2f5e36e7b5a6af043c7a1fe2c80fcd4d4ec90973b92e9c990cf213a2f437d2516b03b3008c39a7de85ea5ee60be24341bd6092bc00e205fa077b0c51164a7a0e6286ae14aeae0cb89bdccf7083257547c777456d04827553fe81e6bb7199581897b2c0dd63745b55ed12a718376ca4deb4100aba142fb6aef66f5e4eac65adb52ca6e8061d80f2c08995a825b80d9839e117e69c05c5044f996c0743f721eab556ec5c1a6d7e35b102d6b10b6c6266703dc1652dff5e6da1d2457e72302bb9002d8fb162acdeb87b68aaa46a50f24f4ebf4c844e1cba5325f59376179bc1cf4dd7ec9f0a047b4bd30cf7f683405e189388c984aefc13185eb96c2b8da4c735d632c5052ebeec44764ecf5dbdeb82ce147e83d01cee793898eb4cb9ba1f2074847bf61054b2fb9d1c59a79f4e6abe3f74b4206890fab7d433d588323a07c6826e1d697065c8a22a9533ff4b72e949ae8aa4efa9aceedd7ac65d1116ac8c23a0f630796bec7f48d5c81313a6a37c7f8cd8b428e58f44e5c3ccd12c250d0ee52d25723791ff4ca2078736c1ec54989ace29a5474b242c4d590b3"
+}
+"#;
+
+    const TEST_PRG_CSHAKE128_32_FIELD128: &str = r#"{
+  "seed": "0101010101010101010101010101010101010101010101010101010101010101",
+  "info": "696e666f20737472696e67",
+  "length": 40,
+  "derived_seed": "9b3d85941f2ff04e1b7f9e7ae931741f630e4286b6db961b12417a553eb79959",
+  "expanded_vec": "9b3d85941f2ff04e1b7f9e7ae931741f630e4286b6db961b12417a553eb7995906afb62fbde60dd4865f2c83409fb28c3c3a122772c8c0e7280c0bb0b1671056428a1784188ac23de9fe0d33df971e856427a4d4afc5010b6503b703479cfb798c572ea711183f895692c0fe02ec0593079d38365a490d728a612d809d62dd386abca8efe3995b333cddf3ca7c54583365a42d8d5ae72fb99bb48394951b60c48b7544938d8d566c8c98bbc34c82d98ad211764b2fd40a2fd42c76f872cdd05066d492744a204193316d50c365d26f866113d27385fcaa9e749d5d17bcf9ccdd11c152c35c1263c1ee8be8901ec9ef6f22ad294f879bf697675154f21b8d2c9725fe3f54ca915dc2848e61e66793153fa1482be347a265a2dc4cb9315a7145f329df788f74fe228241b6b8cc911b207f67c0254d61cbfdbcebc9ff44b7bb0cb9097257025d7b8680912bce033bbab7bbff971fbaad5541252a620fc999d4a32b9ae9daaf9a8aff657cf5cf4ed6b403430ce143053132939fb29fb257fc752cbea58958f36856cb623793a2b4a3ad06368263a20852420034c83bd0908e0b23b37bd1285c21603457ca47d7f05e9e3193028cf5f70602c03cabfba7620caee4d377337963fbcc2cfb764f87203d553739086326463d03c8e84ec852ee0fca67d63e71de366681199e98a5b2efdee50f505f90aecf650b5b4d8f026e58ba3f5e96b4925413c372a0c25d39e671b751cdd2d67eb5ee74c5ec00e43a227a96e14d8668f409c1efbdd55bbf00b802e0a9d3415babd35070651796df583e8c0064feccd49b1f46a35e91e02267b966fdbfdfcfd2ca65fdbcd3ce395edf6d0c10fa96a1bc10da6b9e05302fb691c1010de2a76aa66b4dd8e851fe6e242b4257060885b6"
+}
+"#;
+
+    // Test vector for `PrgShake256` with a 32-byte seed, generated the same way as for `PrgCShake128`
+    // but with cSHAKE256.
+    const TEST_PRG_SHAKE256_32_FIELD128: &str = r#"{
+  "seed": "0101010101010101010101010101010101010101010101010101010101010101",
+  "info": "696e666f20737472696e67",
+  "length": 40,
+  "derived_seed": "ae6b1837bb3a0c821f1e9d30383d219fdf26f3357292257b5b6b273f98f7c063",
+  "expanded_vec": "ae6b1837bb3a0c821f1e9d30383d219fdf26f3357292257b5b6b273f98f7c06313c1452bd9771c65ec4c49607204ffa5770e75a179bee60b9237051ac32843ba1761c79cefe66b712dd2e1b25ce140cdda60e2fa510cca7981cfdfb457e3795470b9a62e160509ae74634cd5288bb378383bf90ef4a41f588e24e3de51e06b0f50ff63fbecf341e01e5a950fd5c5ba539225975cf8b11c48d2648ed08d7e00104c705336a8af34c40bd77c474d30c91ab43efeee7d6c1c476a783e8a2bcd3b872ab19967b50d1c70bd935e8bb9c9dac60bb582284e4d06877c48f735ba7c60d5941cdece1b97377327bb2bcec11b145674c9a7ffb2cd82e2be513535e7f8b4cce13a3e4f7fb32a8d7b8ba9a042d7380a57fca58b84d77ed001c599ec933bbcf6b56e975f81379d52700749cbf43f2808064ca0782bf034b0d6ff05b098a4eea2ac69d8fad9121a48aa4382f6abb45a0e7fc9ffbb39ceba4e26ad73056d1d0e735b1d523568d55eeb12fb671922fc3441142a36024c5cc81449f4ad56a37f9b3acfeb0f9756c3a1029b14aa001d96fad70e7629762fbae3c01f8b101ebfb5680a3c113b4f92880e1a1e9eda85be2ca2198461340654d263982dcafadfcf0281558a002d701ee408f9cd271d06fb08423d72250ac381040ab4a9afc95cc7942c949dd0d5ee8d58e82e3511bf8d1553f9d472cb26ddf9f6d47887a02fe48bd8ab9bac9cb5bd4e8056e77f6e79a411d21d497057d5ee2a362b9161144922a18af38088c3e66cabe7555cdcf7183999a19b0fff30a7245f4e07cd2b1ba36900f957c9177ebe6ac026a9e35d496af69e57a30c310d8c5d5013e0b50c6bb1728eb11398e3d3cf91bb38f65834c27a634896b6aebf6215dc8244979656d2d306ecb54cbc"
+}
 "#;
 
     #[derive(Deserialize, Serialize)]
@@ -218,10 +399,13 @@ mod tests {
         assert_eq!(got, want);
     }
 
-    #[test]
-    fn prg_aes128() {
-        let t: PrgTestVector = serde_json::from_str(TEST_PRG_AES128_FIELD128).unwrap();
-        let mut prg = PrgAes128::init(&Seed(t.seed.try_into().unwrap()));
+    // Check the PRG against a test vector.
+    fn check_test_vector<P, const L: usize>(test_vector: &str)
+    where
+        P: Prg<L>,
+    {
+        let t: PrgTestVector = serde_json::from_str(test_vector).unwrap();
+        let mut prg = P::init(&Seed(t.seed.try_into().unwrap()));
         prg.update(&t.info);
 
         assert_eq!(
@@ -238,7 +422,55 @@ mod tests {
             .take(t.length)
             .collect();
         assert_eq!(got, want);
+    }
 
+    #[test]
+    fn prg_aes128() {
+        check_test_vector::<PrgAes128, 16>(TEST_PRG_AES128_FIELD128);
         test_prg::<PrgAes128, 16>();
     }
+
+    #[test]
+    fn prg_cshake128() {
+        check_test_vector::<PrgCShake128, 16>(TEST_PRG_CSHAKE128_16_FIELD128);
+        check_test_vector::<PrgCShake128, 32>(TEST_PRG_CSHAKE128_32_FIELD128);
+        test_prg::<PrgCShake128, 16>();
+        test_prg::<PrgCShake128, 32>();
+    }
+
+    #[test]
+    fn prg_shake256() {
+        check_test_vector::<PrgShake256, 32>(TEST_PRG_SHAKE256_32_FIELD128);
+        test_prg::<PrgShake256, 32>();
+    }
+
+    #[test]
+    fn rand_source() {
+        // A closure is a source of randomness.
+        let mut counter = 0;
+        let mut rand_source = |buf: &mut [u8]| {
+            for x in buf.iter_mut() {
+                *x = counter;
+                counter += 1;
+            }
+            Ok(())
+        };
+        let seed = Seed::<4>::from_rand_source(&mut rand_source).unwrap();
+        assert_eq!(seed.0, [0, 1, 2, 3]);
+        let seed = Seed::<4>::from_rand_source(&mut rand_source).unwrap();
+        assert_eq!(seed.0, [4, 5, 6, 7]);
+
+        // Errors are propagated.
+        let mut rand_source = |_: &mut [u8]| Err(getrandom::Error::UNSUPPORTED);
+        assert_eq!(
+            Seed::<4>::from_rand_source(&mut rand_source).unwrap_err(),
+            getrandom::Error::UNSUPPORTED
+        );
+
+        // So is an RNG.
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        let seed = Seed::<16>::from_rand_source(&mut rng).unwrap();
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        assert_eq!(seed, Seed::<16>::from_rand_source(&mut rng).unwrap());
+    }
 }
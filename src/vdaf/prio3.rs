@@ -7,7 +7,9 @@
 //!
 //! Implementations of `Prio3Aes128Count`, `Prio3Aes128Sum`, and `Prio3Aes128Histogram` as
 //! specified in [[draft-patton-cfrg-vdaf-01], Section 6.4] are provided. This module also provides
-//! additional instantiations of Prio3 that are not in the draft.
+//! additional instantiations of Prio3 that are not in the draft, including variants of these that
+//! use [`PrgCShake128`] instead of [`PrgAes128`], and variants that use [`PrgShake256`] with 32-byte
+//! seeds.
 //!
 //! [BBCG+19]: https://ia.cr/2019/188
 //! [draft-patton-cfrg-vdaf-01]: https://datatracker.ietf.org/doc/html/draft-patton-cfrg-vdaf-01
@@ -16,15 +18,24 @@ use crate::codec::{CodecError, Decode, Encode, ParameterizedDecode};
 use crate::field::{Field128, Field64, FieldElement};
 #[cfg(feature = "multithreaded")]
 use crate::flp::gadgets::ParallelSumMultithreaded;
-use crate::flp::gadgets::{BlindPolyEval, ParallelSum, ParallelSumGadget};
-use crate::flp::types::{Count, CountVec, Histogram, Sum};
-use crate::flp::Type;
+use crate::flp::gadgets::{BlindPolyEval, Mul, ParallelSum, ParallelSumGadget};
+use crate::flp::types::{
+    BoundedSum, CategoricalHistogram, Count, CountVec, CountVecWithWeight,
+    FixedPointBoundedL2VecSum, Histogram, MeanVariance, SignedSum, Sum, SumVec,
+};
+use crate::flp::{QueryScratch, Type};
 use crate::prng::Prng;
-use crate::vdaf::prg::{Prg, PrgAes128, RandSource, Seed};
+use crate::vdaf::prg::{Prg, PrgAes128, PrgCShake128, PrgShake256, RandSource, Seed};
+#[cfg(any(feature = "test-vector", test))]
+use crate::vdaf::test_vector::{test_vec_field_vec, TestVectorVdaf};
 use crate::vdaf::{
-    Aggregatable, AggregateShare, Aggregator, Client, Collector, OutputShare, PrepareTransition,
-    Share, ShareDecodingParameter, Vdaf, VdafError,
+    Aggregatable, AggregateShare, Aggregator, Client, Collector, OutputShare, PrepareError,
+    PrepareTransition, Share, ShareDecodingParameter, Vdaf, VdafError,
 };
+#[cfg(feature = "multithreaded")]
+use rayon::prelude::*;
+#[cfg(any(feature = "test-vector", test))]
+use serde::{de::DeserializeOwned, Serialize};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
 use std::io::Cursor;
@@ -38,16 +49,43 @@ use std::marker::PhantomData;
 // draft-irtf-cfrg-vdaf-00.
 const VERS_PRIO3: &[u8] = b"vdaf-00 prio3";
 
+// Identifier of each Prio3 type, bound into the verification parameters derived by
+// `Prio3::setup_from_master_key`.
+const ID_COUNT: u32 = 0x00000000;
+const ID_SUM: u32 = 0x00000001;
+const ID_HISTOGRAM: u32 = 0x00000002;
+const ID_COUNT_VEC: u32 = 0x00000003;
+const ID_COUNT_VEC_WITH_WEIGHT: u32 = 0x00000004;
+const ID_COUNT_VEC_WITH_EXACT_WEIGHT: u32 = 0x00000005;
+const ID_BOUNDED_SUM: u32 = 0x00000006;
+const ID_SIGNED_SUM: u32 = 0x00000007;
+const ID_MEAN_VARIANCE: u32 = 0x00000008;
+const ID_SUM_VEC: u32 = 0x00000009;
+const ID_FIXED_POINT_BOUNDED_L2_VEC_SUM: u32 = 0x0000000a;
+const ID_CATEGORICAL_HISTOGRAM: u32 = 0x0000000b;
+
 /// The count type. Each measurement is an integer in `[0,2)` and the aggregate is the sum.
 pub type Prio3Aes128Count = Prio3<Count<Field64>, Prio3Result<u64>, PrgAes128, 16>;
 
-impl Prio3Aes128Count {
+/// Like [`Prio3Aes128Count`] except that [`PrgCShake128`] is used instead of [`PrgAes128`].
+pub type Prio3CShake128Count = Prio3<Count<Field64>, Prio3Result<u64>, PrgCShake128, 16>;
+
+/// Like [`Prio3Aes128Count`] except that [`PrgShake256`] is used with 32-byte seeds for a 256-bit
+/// security level.
+pub type Prio3Shake256Count = Prio3<Count<Field64>, Prio3Result<u64>, PrgShake256, 32>;
+
+impl<P, const L: usize> Prio3<Count<Field64>, Prio3Result<u64>, P, L>
+where
+    P: Prg<L>,
+{
     /// Construct an instance of this VDAF with the given suite and the given number of aggregators.
     pub fn new(num_aggregators: u8) -> Result<Self, VdafError> {
         check_num_aggregators(num_aggregators)?;
 
         Ok(Prio3 {
             num_aggregators,
+            num_proofs: 1,
+            id: ID_COUNT,
             typ: Count::new(),
             phantom: PhantomData,
         })
@@ -87,24 +125,91 @@ where
 
         Ok(Prio3 {
             num_aggregators,
+            num_proofs: 1,
+            id: ID_COUNT_VEC,
             typ: CountVec::new(len),
             phantom: PhantomData,
         })
     }
 }
 
+/// The k-hot count-vector type. Each measurement is a vector of integers in `[0,2)` with at most
+/// (or exactly) `weight` ones and the aggregate is the element-wise sum.
+pub type Prio3Aes128CountVecWithWeight = Prio3<
+    CountVecWithWeight<Field128, ParallelSum<Field128, BlindPolyEval<Field128>>>,
+    Prio3ResultVec<u64>,
+    PrgAes128,
+    16,
+>;
+
+/// Like [`Prio3Aes128CountVecWithWeight`] except this type uses multithreading to improve sharding
+/// and preparation time. As with [`Prio3Aes128CountVecMultithreaded`], the improvement is only
+/// noticeable for very large input lengths.
+#[cfg(feature = "multithreaded")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multithreaded")))]
+pub type Prio3Aes128CountVecWithWeightMultithreaded = Prio3<
+    CountVecWithWeight<Field128, ParallelSumMultithreaded<Field128, BlindPolyEval<Field128>>>,
+    Prio3ResultVec<u64>,
+    PrgAes128,
+    16,
+>;
+
+impl<S, P, const L: usize> Prio3<CountVecWithWeight<Field128, S>, Prio3ResultVec<u64>, P, L>
+where
+    S: 'static + ParallelSumGadget<Field128, BlindPolyEval<Field128>> + Eq,
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite and the given number of
+    /// aggregators. `len` defines the length of each measurement and `weight` the maximum number
+    /// of ones.
+    pub fn new(num_aggregators: u8, len: usize, weight: usize) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_COUNT_VEC_WITH_WEIGHT,
+            typ: CountVecWithWeight::new(len, weight)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Like [`Self::new`] except that each measurement must have exactly `weight` ones.
+    pub fn new_exact(num_aggregators: u8, len: usize, weight: usize) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_COUNT_VEC_WITH_EXACT_WEIGHT,
+            typ: CountVecWithWeight::new_exact(len, weight)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
 /// The sum type. Each measurement is an integer in `[0,2^bits)` for some `0 < bits < 64` and the
 /// aggregate is the sum.
 pub type Prio3Aes128Sum = Prio3<Sum<Field128>, Prio3Result<u64>, PrgAes128, 16>;
 
-impl Prio3Aes128Sum {
+/// Like [`Prio3Aes128Sum`] except that [`PrgCShake128`] is used instead of [`PrgAes128`].
+pub type Prio3CShake128Sum = Prio3<Sum<Field128>, Prio3Result<u64>, PrgCShake128, 16>;
+
+/// Like [`Prio3Aes128Sum`] except that [`PrgShake256`] is used with 32-byte seeds for a 256-bit
+/// security level.
+pub type Prio3Shake256Sum = Prio3<Sum<Field128>, Prio3Result<u64>, PrgShake256, 32>;
+
+impl<P, const L: usize> Prio3<Sum<Field128>, Prio3Result<u64>, P, L>
+where
+    P: Prg<L>,
+{
     /// Construct an instance of this VDAF with the given suite, number of aggregators and required
     /// bit length. The bit length must not exceed 64.
     pub fn new(num_aggregators: u8, bits: u32) -> Result<Self, VdafError> {
         check_num_aggregators(num_aggregators)?;
 
         if bits > 64 {
-            return Err(VdafError::Uncategorized(format!(
+            return Err(VdafError::InvalidParameter(format!(
                 "bit length ({}) exceeds limit for aggregate type (64)",
                 bits
             )));
@@ -112,17 +217,221 @@ impl Prio3Aes128Sum {
 
         Ok(Prio3 {
             num_aggregators,
+            num_proofs: 1,
+            id: ID_SUM,
             typ: Sum::new(bits as usize)?,
             phantom: PhantomData,
         })
     }
 }
 
+/// The bounded sum type. Each measurement is an integer in `[min, max]` and the aggregate is the
+/// sum. Unlike [`Prio3Aes128Sum`], the bounds need not be powers of two.
+pub type Prio3Aes128BoundedSum = Prio3<BoundedSum<Field128>, Prio3Result<u64>, PrgAes128, 16>;
+
+impl<P, const L: usize> Prio3<BoundedSum<Field128>, Prio3Result<u64>, P, L>
+where
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite, number of aggregators and
+    /// inclusive bounds on each measurement.
+    pub fn new(num_aggregators: u8, min: u64, max: u64) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_BOUNDED_SUM,
+            typ: BoundedSum::new(min as u128, max as u128)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// The signed sum type. Each measurement is an integer in `[-2^(bits-1),2^(bits-1))` for some
+/// `0 < bits <= 64` and the aggregate is the sum.
+pub type Prio3Aes128SignedSum = Prio3<SignedSum<Field128>, Prio3Result<i64>, PrgAes128, 16>;
+
+impl<P, const L: usize> Prio3<SignedSum<Field128>, Prio3Result<i64>, P, L>
+where
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite, number of aggregators and required
+    /// bit length. The bit length must not exceed 64.
+    pub fn new(num_aggregators: u8, bits: u32) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        if bits > 64 {
+            return Err(VdafError::InvalidParameter(format!(
+                "bit length ({}) exceeds limit for aggregate type (64)",
+                bits
+            )));
+        }
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_SIGNED_SUM,
+            typ: SignedSum::new(bits as usize)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// The mean-and-variance type. Each measurement is an integer in `[0,2^bits)` for some
+/// `0 < bits <= 32` and the aggregate is the number of measurements, their mean and their variance.
+pub type Prio3Aes128MeanVariance =
+    Prio3<MeanVariance<Field128>, Prio3ResultMeanVariance, PrgAes128, 16>;
+
+impl<P, const L: usize> Prio3<MeanVariance<Field128>, Prio3ResultMeanVariance, P, L>
+where
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite, number of aggregators and required
+    /// bit length. The bit length must not exceed 32.
+    pub fn new(num_aggregators: u8, bits: u32) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        if bits > 32 {
+            return Err(VdafError::InvalidParameter(format!(
+                "bit length ({}) exceeds limit for aggregate type (32)",
+                bits
+            )));
+        }
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_MEAN_VARIANCE,
+            typ: MeanVariance::new(bits as usize)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// The sum-vector type. Each measurement is a vector of integers in `[0,2^bits)` for some
+/// `0 < bits <= 64` and the aggregate is the element-wise sum.
+pub type Prio3Aes128SumVec = Prio3<
+    SumVec<Field128, ParallelSum<Field128, BlindPolyEval<Field128>>>,
+    Prio3ResultVec<u64>,
+    PrgAes128,
+    16,
+>;
+
+/// Like [`Prio3Aes128SumVec`] except this type uses multithreading to improve sharding and
+/// preparation time. As with [`Prio3Aes128CountVecMultithreaded`], the improvement is only
+/// noticeable for very large input lengths.
+#[cfg(feature = "multithreaded")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multithreaded")))]
+pub type Prio3Aes128SumVecMultithreaded = Prio3<
+    SumVec<Field128, ParallelSumMultithreaded<Field128, BlindPolyEval<Field128>>>,
+    Prio3ResultVec<u64>,
+    PrgAes128,
+    16,
+>;
+
+impl<S, P, const L: usize> Prio3<SumVec<Field128, S>, Prio3ResultVec<u64>, P, L>
+where
+    S: 'static + ParallelSumGadget<Field128, BlindPolyEval<Field128>> + Eq,
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite, number of aggregators, bit length
+    /// of each entry and length of each measurement. The bit length must not exceed 64.
+    pub fn new(num_aggregators: u8, bits: u32, len: usize) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        if bits > 64 {
+            return Err(VdafError::InvalidParameter(format!(
+                "bit length ({}) exceeds limit for aggregate type (64)",
+                bits
+            )));
+        }
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_SUM_VEC,
+            typ: SumVec::new(bits as usize, len)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// The fixed-point vector type. Each measurement is a vector of `len` real numbers in `[-1, 1)`
+/// whose L2 norm is at most `1` and the aggregate is the element-wise sum. Each entry is encoded as
+/// a fixed-point number with `bits - 1` fractional bits.
+pub type Prio3Aes128FixedPointBoundedL2VecSum = Prio3<
+    FixedPointBoundedL2VecSum<
+        Field128,
+        ParallelSum<Field128, BlindPolyEval<Field128>>,
+        ParallelSum<Field128, Mul<Field128>>,
+    >,
+    Prio3ResultVec<f64>,
+    PrgAes128,
+    16,
+>;
+
+/// Like [`Prio3Aes128FixedPointBoundedL2VecSum`] except this type uses multithreading to improve
+/// sharding and preparation time. This is recommended for very long vectors, such as gradient
+/// updates in federated learning.
+#[cfg(feature = "multithreaded")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multithreaded")))]
+pub type Prio3Aes128FixedPointBoundedL2VecSumMultithreaded = Prio3<
+    FixedPointBoundedL2VecSum<
+        Field128,
+        ParallelSumMultithreaded<Field128, BlindPolyEval<Field128>>,
+        ParallelSumMultithreaded<Field128, Mul<Field128>>,
+    >,
+    Prio3ResultVec<f64>,
+    PrgAes128,
+    16,
+>;
+
+impl<SPoly, SMul, P, const L: usize>
+    Prio3<FixedPointBoundedL2VecSum<Field128, SPoly, SMul>, Prio3ResultVec<f64>, P, L>
+where
+    SPoly: 'static + ParallelSumGadget<Field128, BlindPolyEval<Field128>> + Eq,
+    SMul: 'static + ParallelSumGadget<Field128, Mul<Field128>> + Eq,
+    P: Prg<L>,
+{
+    /// Construct an instance of this VDAF with the given suite, number of aggregators, bit length
+    /// of each entry and length of each measurement. The bit length must not exceed 32.
+    pub fn new(num_aggregators: u8, bits: u32, len: usize) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        if bits > 32 {
+            return Err(VdafError::InvalidParameter(format!(
+                "bit length ({}) exceeds limit for aggregate type (32)",
+                bits
+            )));
+        }
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_FIXED_POINT_BOUNDED_L2_VEC_SUM,
+            typ: FixedPointBoundedL2VecSum::new(bits as usize, len)?,
+            phantom: PhantomData,
+        })
+    }
+}
+
 /// the histogram type. Each measurement is an unsigned, 64-bit integer and the result is a
 /// histogram representation of the measurement.
 pub type Prio3Aes128Histogram = Prio3<Histogram<Field128>, Prio3ResultVec<u64>, PrgAes128, 16>;
 
-impl Prio3Aes128Histogram {
+/// Like [`Prio3Aes128Histogram`] except that [`PrgCShake128`] is used instead of [`PrgAes128`].
+pub type Prio3CShake128Histogram =
+    Prio3<Histogram<Field128>, Prio3ResultVec<u64>, PrgCShake128, 16>;
+
+/// Like [`Prio3Aes128Histogram`] except that [`PrgShake256`] is used with 32-byte seeds for a 256-bit
+/// security level.
+pub type Prio3Shake256Histogram = Prio3<Histogram<Field128>, Prio3ResultVec<u64>, PrgShake256, 32>;
+
+impl<P, const L: usize> Prio3<Histogram<Field128>, Prio3ResultVec<u64>, P, L>
+where
+    P: Prg<L>,
+{
     /// Constructs an instance of this VDAF with the given suite, number of aggregators, and
     /// desired histogram bucket boundaries.
     pub fn new(num_aggregators: u8, buckets: &[u64]) -> Result<Self, VdafError> {
@@ -132,12 +441,47 @@ impl Prio3Aes128Histogram {
 
         Ok(Prio3 {
             num_aggregators,
+            num_proofs: 1,
+            id: ID_HISTOGRAM,
             typ: Histogram::<Field128>::new(buckets)?,
             phantom: PhantomData,
         })
     }
 }
 
+/// The categorical histogram type. Each measurement is the index of one of a list of labelled
+/// buckets and the result is the number of measurements in each bucket, keyed by label.
+pub type Prio3Aes128CategoricalHistogram =
+    Prio3<CategoricalHistogram<Field128>, Prio3ResultCategoricalHistogram, PrgAes128, 16>;
+
+impl<P, const L: usize> Prio3<CategoricalHistogram<Field128>, Prio3ResultCategoricalHistogram, P, L>
+where
+    P: Prg<L>,
+{
+    /// Constructs an instance of this VDAF with the given suite, number of aggregators, and
+    /// bucket labels.
+    pub fn new(num_aggregators: u8, labels: &[&str]) -> Result<Self, VdafError> {
+        check_num_aggregators(num_aggregators)?;
+
+        let labels = labels.iter().map(|label| label.to_string()).collect();
+
+        Ok(Prio3 {
+            num_aggregators,
+            num_proofs: 1,
+            id: ID_CATEGORICAL_HISTOGRAM,
+            typ: CategoricalHistogram::<Field128>::new(labels)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the measurement corresponding to the bucket with the given label.
+    pub fn bucket_index(&self, label: &str) -> Result<usize, VdafError> {
+        self.typ.bucket_index(label).ok_or_else(|| {
+            VdafError::InvalidMeasurement(format!("unknown bucket label: {:?}", label))
+        })
+    }
+}
+
 /// Aggregate result for singleton data types.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Prio3Result<T: Eq>(pub T);
@@ -147,14 +491,47 @@ impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3Result<u64> {
 
     fn try_from(data: AggregateShare<F>) -> Result<Self, VdafError> {
         if data.0.len() != 1 {
-            return Err(VdafError::Uncategorized(format!(
+            return Err(VdafError::Aggregate(format!(
                 "unexpected aggregate length for count type: got {}; want 1",
                 data.0.len()
             )));
         }
 
         let out: u64 = F::Integer::from(data.0[0]).try_into().map_err(|err| {
-            VdafError::Uncategorized(format!("result too large for output type: {:?}", err))
+            VdafError::Aggregate(format!("result too large for output type: {:?}", err))
+        })?;
+
+        Ok(Prio3Result(out))
+    }
+}
+
+impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3Result<i64> {
+    type Error = VdafError;
+
+    fn try_from(data: AggregateShare<F>) -> Result<Self, VdafError> {
+        if data.0.len() != 2 {
+            return Err(VdafError::Aggregate(format!(
+                "unexpected aggregate length for signed sum type: got {}; want 2",
+                data.0.len()
+            )));
+        }
+
+        // The aggregate consists of the sum of the offset measurements and the sum of the offsets,
+        // i.e., the offset times the number of measurements. The difference is the sum of the
+        // measurements, where field elements larger than `p/2` represent negative numbers.
+        let sum = data.0[0] - data.0[1];
+        let half = F::modulus() / F::Integer::try_from(2).unwrap();
+        let (magnitude, sign) = if F::Integer::from(sum) > half {
+            (-sum, -1)
+        } else {
+            (sum, 1)
+        };
+
+        let magnitude: u64 = F::Integer::from(magnitude).try_into().map_err(|err| {
+            VdafError::Aggregate(format!("result too large for output type: {:?}", err))
+        })?;
+        let out = i64::try_from(sign * i128::from(magnitude)).map_err(|err| {
+            VdafError::Aggregate(format!("result too large for output type: {:?}", err))
         })?;
 
         Ok(Prio3Result(out))
@@ -163,7 +540,7 @@ impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3Result<u64> {
 
 /// Aggregate result for vector data types.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Prio3ResultVec<T: Eq>(pub Vec<T>);
+pub struct Prio3ResultVec<T>(pub Vec<T>);
 
 impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3ResultVec<u64> {
     type Error = VdafError;
@@ -172,7 +549,7 @@ impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3ResultVec<u64> {
         let mut out = Vec::with_capacity(data.0.len());
         for elem in data.0.into_iter() {
             out.push(F::Integer::from(elem).try_into().map_err(|err| {
-                VdafError::Uncategorized(format!("result too large for output type: {:?}", err))
+                VdafError::Aggregate(format!("result too large for output type: {:?}", err))
             })?);
         }
 
@@ -180,14 +557,81 @@ impl<F: FieldElement> TryFrom<AggregateShare<F>> for Prio3ResultVec<u64> {
     }
 }
 
+/// Aggregate result for the mean-and-variance type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prio3ResultMeanVariance {
+    /// The number of measurements.
+    pub count: u64,
+
+    /// The mean of the measurements.
+    pub mean: f64,
+
+    /// The (population) variance of the measurements.
+    pub variance: f64,
+}
+
+impl TryFrom<AggregateShare<Field128>> for Prio3ResultMeanVariance {
+    type Error = VdafError;
+
+    fn try_from(data: AggregateShare<Field128>) -> Result<Self, VdafError> {
+        if data.0.len() != 3 {
+            return Err(VdafError::Aggregate(format!(
+                "unexpected aggregate length for mean-and-variance type: got {}; want 3",
+                data.0.len()
+            )));
+        }
+
+        let sum = u128::from(data.0[0]);
+        let sum_of_squares = u128::from(data.0[1]);
+        let count = u64::try_from(u128::from(data.0[2])).map_err(|err| {
+            VdafError::Aggregate(format!("result too large for output type: {:?}", err))
+        })?;
+        if count == 0 {
+            return Err(VdafError::Aggregate(
+                "mean and variance are undefined for zero measurements".to_string(),
+            ));
+        }
+
+        // Computing the variance as `E[x^2] - mean^2` in floating point loses precision when the
+        // measurements are large, since both terms are then close to each other. Instead, write
+        // `sum = q * count + r` and use the identity
+        //
+        //   count * sum_of_squares - sum^2 = count * (sum_of_squares - q * sum) - r * sum,
+        //
+        // whose first term is computed exactly in integers and does not exceed `sum_of_squares`.
+        let n = u128::from(count);
+        let (q, r) = (sum / n, sum % n);
+        let centered = q
+            .checked_mul(sum)
+            .and_then(|q_sum| sum_of_squares.checked_sub(q_sum))
+            .ok_or_else(|| {
+                VdafError::Aggregate(
+                    "sum of squares is inconsistent with the sum of the measurements".to_string(),
+                )
+            })?;
+
+        let count_f = count as f64;
+        let mean = sum as f64 / count_f;
+        Ok(Prio3ResultMeanVariance {
+            count,
+            mean,
+            variance: centered as f64 / count_f - (r as f64 / count_f) * mean,
+        })
+    }
+}
+
+/// Aggregate result for the categorical histogram type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prio3ResultCategoricalHistogram(pub Vec<(String, u64)>);
+
 fn check_num_aggregators(num_aggregators: u8) -> Result<(), VdafError> {
     if num_aggregators == 0 {
-        return Err(VdafError::Uncategorized(format!(
+        return Err(VdafError::NumAggregators(format!(
             "at least one aggregator is required; got {}",
             num_aggregators
         )));
     } else if num_aggregators > 254 {
-        return Err(VdafError::Uncategorized(format!(
+        return Err(VdafError::NumAggregators(format!(
             "number of aggregators must not exceed 254; got {}",
             num_aggregators
         )));
@@ -196,6 +640,16 @@ fn check_num_aggregators(num_aggregators: u8) -> Result<(), VdafError> {
     Ok(())
 }
 
+fn check_num_proofs(num_proofs: u8) -> Result<(), VdafError> {
+    if num_proofs == 0 {
+        return Err(VdafError::InvalidParameter(
+            "at least one proof is required".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 /// The base type for prio3.
 #[derive(Clone, Debug)]
 pub struct Prio3<T, A, P, const L: usize>
@@ -205,6 +659,8 @@ where
     P: Prg<L>,
 {
     num_aggregators: u8,
+    num_proofs: u8,
+    id: u32,
     typ: T,
     phantom: PhantomData<(A, P)>,
 }
@@ -225,160 +681,305 @@ where
         self.typ.verifier_len()
     }
 
-    fn setup_with_rand_source(
+    /// Set the number of FLP proofs generated by the client and checked by the aggregators.
+    ///
+    /// Each proof is generated with independent prove, joint, and query randomness. Using more
+    /// than one proof reduces the soundness error of the FLP, which is useful for types over small
+    /// fields such as [`Field64`], at the cost of larger proof shares and verifier messages. The
+    /// default is one proof.
+    pub fn with_num_proofs(mut self, num_proofs: u8) -> Result<Self, VdafError> {
+        check_num_proofs(num_proofs)?;
+        self.num_proofs = num_proofs;
+        Ok(self)
+    }
+
+    /// The number of FLP proofs generated for each measurement.
+    pub fn num_proofs(&self) -> u8 {
+        self.num_proofs
+    }
+
+    // Length of the (uncompressed) proof share, i.e., the concatenation of each proof.
+    fn proofs_len(&self) -> usize {
+        self.typ.proof_len() * self.num_proofs as usize
+    }
+
+    // Length of the verifier share, i.e., the concatenation of each verifier message.
+    fn verifiers_len(&self) -> usize {
+        self.typ.verifier_len() * self.num_proofs as usize
+    }
+
+    /// Like [`Vdaf::setup`], except that the verification parameters are derived from a long-lived
+    /// `master_key` and a `task_id` using the PRG. Aggregators that share the master key derive
+    /// matching verification parameters for a task without exchanging them, and the parameters
+    /// derived for distinct tasks are independent of one another. The derivation also binds the
+    /// type of the instance, its input length, number of aggregators and number of proofs, so
+    /// instances that differ in any of these never share verification parameters.
+    pub fn setup_from_master_key(
         &self,
-        rand_source: RandSource,
+        master_key: &Seed<L>,
+        task_id: &[u8],
     ) -> Result<((), Vec<Prio3VerifyParam<L>>), VdafError> {
-        let query_rand_init = Seed::from_rand_source(rand_source)?;
+        let query_rand_init = self.derive_query_rand_init(master_key, task_id);
         Ok((
             (),
             (0..self.num_aggregators)
-                .map(|aggregator_id| Prio3VerifyParam {
-                    query_rand_init: query_rand_init.clone(),
-                    aggregator_id,
-                    input_len: self.typ.input_len(),
-                    proof_len: self.typ.proof_len(),
-                    verifier_len: self.typ.verifier_len(),
-                    joint_rand_len: self.typ.joint_rand_len(),
-                })
+                .map(|aggregator_id| self.verify_param(query_rand_init.clone(), aggregator_id))
                 .collect(),
         ))
     }
 
-    fn shard_with_rand_source(
+    /// Derives the verification parameter of the aggregator with the given ID from `master_key`
+    /// and `task_id`, as in [`Prio3::setup_from_master_key`]. Returns the verification parameter
+    /// and its encoding.
+    pub fn derive_verify_param(
         &self,
-        _public_param: &(),
-        measurement: &T::Measurement,
-        rand_source: RandSource,
-    ) -> Result<Vec<Prio3InputShare<T::Field, L>>, VdafError> {
-        let mut info = [0; VERS_PRIO3.len() + 1];
-        info[..VERS_PRIO3.len()].clone_from_slice(VERS_PRIO3);
+        master_key: &Seed<L>,
+        task_id: &[u8],
+        aggregator_id: u8,
+    ) -> Result<(Prio3VerifyParam<L>, Vec<u8>), VdafError> {
+        if aggregator_id >= self.num_aggregators {
+            return Err(VdafError::InvalidParameter(format!(
+                "aggregator ID ({}) must be less than the number of aggregators ({})",
+                aggregator_id, self.num_aggregators
+            )));
+        }
 
-        let num_aggregators = self.num_aggregators;
-        let input = self.typ.encode(measurement)?;
+        let verify_param = self.verify_param(
+            self.derive_query_rand_init(master_key, task_id),
+            aggregator_id,
+        );
+        let encoded = verify_param.get_encoded();
+        Ok((verify_param, encoded))
+    }
 
-        // Generate the input shares and compute the joint randomness.
-        let mut helper_shares = Vec::with_capacity(num_aggregators as usize - 1);
-        let mut leader_input_share = input.clone();
-        let mut joint_rand_seed = Seed::uninitialized();
-        for aggregator_id in 1..num_aggregators {
-            let mut helper = HelperShare::from_rand_source(rand_source)?;
+    fn derive_query_rand_init(&self, master_key: &Seed<L>, task_id: &[u8]) -> Seed<L> {
+        let mut deriver = P::init(master_key);
+        deriver.update(VERS_PRIO3);
+        deriver.update(b" verify param");
+        deriver.update(&self.id.to_be_bytes());
+        deriver.update(&(self.typ.input_len() as u64).to_be_bytes());
+        deriver.update(&[self.num_aggregators, self.num_proofs]);
+        deriver.update(task_id);
+        deriver.into_seed()
+    }
 
-            let mut deriver = P::init(&helper.joint_rand_param.blind);
-            deriver.update(&[aggregator_id]);
-            info[VERS_PRIO3.len()] = aggregator_id;
-            let prng: Prng<T::Field, _> =
-                Prng::from_seed_stream(P::seed_stream(&helper.input_share, &info));
-            for (x, y) in leader_input_share
-                .iter_mut()
-                .zip(prng)
-                .take(self.typ.input_len())
-            {
-                *x -= y;
-                deriver.update(&y.into());
-            }
-
-            helper.joint_rand_param.seed_hint = deriver.into_seed();
-            joint_rand_seed.xor_accumulate(&helper.joint_rand_param.seed_hint);
-
-            helper_shares.push(helper);
-        }
-
-        let leader_blind = Seed::from_rand_source(rand_source)?;
-
-        let mut deriver = P::init(&leader_blind);
-        deriver.update(&[0]); // ID of the leader
-        for x in leader_input_share.iter() {
-            deriver.update(&(*x).into());
+    fn verify_param(&self, query_rand_init: Seed<L>, aggregator_id: u8) -> Prio3VerifyParam<L> {
+        Prio3VerifyParam {
+            query_rand_init,
+            aggregator_id,
+            input_len: self.typ.input_len(),
+            proof_len: self.proofs_len(),
+            verifier_len: self.verifiers_len(),
+            joint_rand_len: self.typ.joint_rand_len(),
         }
+    }
 
-        let mut leader_joint_rand_seed_hint = deriver.into_seed();
-        joint_rand_seed.xor_accumulate(&leader_joint_rand_seed_hint);
+    fn prepare_init_with_scratch(
+        &self,
+        scratch: &mut PrepareScratch<T::Field>,
+        verify_param: &Prio3VerifyParam<L>,
+        nonce: &[u8],
+        msg: &Prio3InputShare<T::Field, L>,
+    ) -> Result<Prio3PrepareStep<T::Field, L>, VdafError> {
+        let mut info = [0; VERS_PRIO3.len() + 1];
+        info[..VERS_PRIO3.len()].clone_from_slice(VERS_PRIO3);
+        info[VERS_PRIO3.len()] = verify_param.aggregator_id;
 
-        // Run the proof-generation algorithm.
-        let prng: Prng<T::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&joint_rand_seed, VERS_PRIO3));
-        let joint_rand: Vec<T::Field> = prng.take(self.typ.joint_rand_len()).collect();
-        let prng: Prng<T::Field, _> = Prng::from_seed_stream(P::seed_stream(
-            &Seed::from_rand_source(rand_source)?,
-            VERS_PRIO3,
-        ));
-        let prove_rand: Vec<T::Field> = prng.take(self.typ.prove_rand_len()).collect();
-        let mut leader_proof_share = self.typ.prove(&input, &prove_rand, &joint_rand)?;
+        let mut deriver = P::init(&verify_param.query_rand_init);
+        deriver.update(&[255]);
+        deriver.update(nonce);
+        let query_rand_seed = deriver.into_seed();
 
-        // Generate the proof shares and finalize the joint randomness seed hints.
-        for (j, helper) in helper_shares.iter_mut().enumerate() {
-            info[VERS_PRIO3.len()] = j as u8 + 1;
-            let prng: Prng<T::Field, _> =
-                Prng::from_seed_stream(P::seed_stream(&helper.proof_share, &info));
-            for (x, y) in leader_proof_share
-                .iter_mut()
-                .zip(prng)
-                .take(self.typ.proof_len())
-            {
-                *x -= y;
+        // Check that the leader's shares have the expected length and that the parameters for
+        // computing the joint randomness are present if needed.
+        if let Share::Leader(ref data) = msg.input_share {
+            if data.len() != self.typ.input_len() {
+                return Err(PrepareError::LengthMismatch {
+                    what: "input share",
+                    got: data.len(),
+                    want: self.typ.input_len(),
+                }
+                .into());
+            }
+        }
+        if let Share::Leader(ref data) = msg.proof_share {
+            if data.len() != self.proofs_len() {
+                return Err(PrepareError::LengthMismatch {
+                    what: "proof share",
+                    got: data.len(),
+                    want: self.proofs_len(),
+                }
+                .into());
             }
-
-            helper
-                .joint_rand_param
-                .seed_hint
-                .xor_accumulate(&joint_rand_seed);
         }
+        let joint_rand_param = match (self.typ.joint_rand_len() > 0, &msg.joint_rand_param) {
+            (true, Some(joint_rand_param)) => Some(joint_rand_param),
+            (false, None) => None,
+            _ => return Err(PrepareError::JointRandMismatch.into()),
+        };
 
-        leader_joint_rand_seed_hint.xor_accumulate(&joint_rand_seed);
+        // Create a reference to the (expanded) input share.
+        let input_share = match msg.input_share {
+            Share::Leader(ref data) => data,
+            Share::Helper(ref seed) => {
+                let prng: Prng<T::Field, _> = Prng::from_seed_stream(P::seed_stream(seed, &info));
+                scratch.input_share.clear();
+                scratch.input_share.extend(prng.take(self.typ.input_len()));
+                &scratch.input_share
+            }
+        };
 
-        let leader_joint_rand_param = if self.typ.joint_rand_len() > 0 {
-            Some(JointRandParam {
-                seed_hint: leader_joint_rand_seed_hint,
-                blind: leader_blind,
-            })
-        } else {
-            None
+        // Create a reference to the (expanded) proof share.
+        let proof_share = match msg.proof_share {
+            Share::Leader(ref data) => data,
+            Share::Helper(ref seed) => {
+                let prng: Prng<T::Field, _> = Prng::from_seed_stream(P::seed_stream(seed, &info));
+                scratch.proof_share.clear();
+                scratch.proof_share.extend(prng.take(self.proofs_len()));
+                &scratch.proof_share
+            }
         };
 
-        // Prep the output messages.
-        let mut out = Vec::with_capacity(num_aggregators as usize);
-        out.push(Prio3InputShare {
-            input_share: Share::Leader(leader_input_share),
-            proof_share: Share::Leader(leader_proof_share),
-            joint_rand_param: leader_joint_rand_param,
-        });
+        // Compute the joint randomness.
+        let num_proofs = self.num_proofs as usize;
+        scratch.joint_rand.clear();
+        let (joint_rand_seed, joint_rand_seed_share) =
+            if let Some(joint_rand_param) = joint_rand_param {
+                let mut deriver = P::init(&joint_rand_param.blind);
+                deriver.update(&[verify_param.aggregator_id]);
+                for x in input_share {
+                    deriver.update(&(*x).into());
+                }
+                let joint_rand_seed_share = deriver.into_seed();
 
-        for helper in helper_shares.into_iter() {
-            let helper_joint_rand_param = if self.typ.joint_rand_len() > 0 {
-                Some(helper.joint_rand_param)
+                let mut joint_rand_seed = Seed::uninitialized();
+                joint_rand_seed.xor(&joint_rand_param.seed_hint, &joint_rand_seed_share);
+
+                let prng: Prng<T::Field, _> =
+                    Prng::from_seed_stream(P::seed_stream(&joint_rand_seed, VERS_PRIO3));
+                scratch
+                    .joint_rand
+                    .extend(prng.take(self.typ.joint_rand_len() * num_proofs));
+                (Some(joint_rand_seed), Some(joint_rand_seed_share))
             } else {
-                None
+                (None, None)
             };
 
-            out.push(Prio3InputShare {
-                input_share: Share::Helper(helper.input_share),
-                proof_share: Share::Helper(helper.proof_share),
-                joint_rand_param: helper_joint_rand_param,
-            });
+        // Compute the query randomness.
+        let prng: Prng<T::Field, _> =
+            Prng::from_seed_stream(P::seed_stream(&query_rand_seed, VERS_PRIO3));
+        scratch.query_rand.clear();
+        scratch
+            .query_rand
+            .extend(prng.take(self.typ.query_rand_len() * num_proofs));
+
+        // Run the query-generation algorithm for each proof.
+        let mut verifier_share = Vec::with_capacity(self.verifiers_len());
+        for i in 0..num_proofs {
+            verifier_share.append(&mut self.typ.query_with_scratch(
+                &mut scratch.query,
+                input_share,
+                chunk(proof_share, self.typ.proof_len(), i),
+                chunk(&scratch.query_rand, self.typ.query_rand_len(), i),
+                chunk(&scratch.joint_rand, self.typ.joint_rand_len(), i),
+                self.num_aggregators as usize,
+            )?);
         }
 
-        Ok(out)
+        Ok(Prio3PrepareStep {
+            input_share: msg.input_share.clone(),
+            joint_rand_seed,
+            aggregator_id: verify_param.aggregator_id,
+            verifier_len: verifier_share.len(),
+            state: PrepareStep::Ready(Prio3PrepareMessage {
+                verifier: verifier_share,
+                joint_rand_seed: joint_rand_seed_share,
+            }),
+        })
     }
 
-    #[cfg(test)]
-    pub(crate) fn test_vec_setup(&self) -> Result<((), Vec<Prio3VerifyParam<L>>), VdafError> {
-        self.setup_with_rand_source(|buf| {
-            buf.fill(1);
-            Ok(())
-        })
+    // Runs `prepare_init` followed by the first call to `prepare_step` on a single report of a
+    // batch.
+    #[allow(clippy::type_complexity)]
+    fn prepare_init_report(
+        &self,
+        scratch: &mut PrepareScratch<T::Field>,
+        verify_param: &Prio3VerifyParam<L>,
+        nonce: &[u8],
+        msg: &Prio3InputShare<T::Field, L>,
+    ) -> PrepareTransition<
+        Prio3PrepareStep<T::Field, L>,
+        Prio3PrepareMessage<T::Field, L>,
+        OutputShare<T::Field>,
+    > {
+        match self.prepare_init_with_scratch(scratch, verify_param, nonce, msg) {
+            // The first step only moves the verifier message out of the state.
+            Ok(mut step) => match std::mem::replace(&mut step.state, PrepareStep::Waiting) {
+                PrepareStep::Ready(verifier_msg) => PrepareTransition::Continue(step, verifier_msg),
+                PrepareStep::Waiting => unreachable!(),
+            },
+            Err(err) => PrepareTransition::Fail(err),
+        }
+    }
+}
+
+#[cfg(feature = "multithreaded")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multithreaded")))]
+impl<T, A, P, const L: usize> Prio3<T, A, P, L>
+where
+    T: Type + Sync,
+    T::Field: Send + Sync,
+    A: Clone + Debug + Sync + Send,
+    P: Prg<L> + Sync,
+{
+    /// Like [`Aggregator::prepare_init_batch`], except that the reports are processed in parallel.
+    /// The output is in the same order as the reports.
+    #[allow(clippy::type_complexity)]
+    pub fn prepare_init_batch_multithreaded<N: AsRef<[u8]> + Sync>(
+        &self,
+        verify_param: &Prio3VerifyParam<L>,
+        _agg_param: &(),
+        reports: &[(N, Prio3InputShare<T::Field, L>)],
+    ) -> Vec<
+        PrepareTransition<
+            Prio3PrepareStep<T::Field, L>,
+            Prio3PrepareMessage<T::Field, L>,
+            OutputShare<T::Field>,
+        >,
+    > {
+        reports
+            .par_iter()
+            .map_init(PrepareScratch::new, |scratch, (nonce, input_share)| {
+                self.prepare_init_report(scratch, verify_param, nonce.as_ref(), input_share)
+            })
+            .collect()
     }
 
-    #[cfg(test)]
-    pub(crate) fn test_vec_shard(
+    /// Like [`Aggregator::prepare_step_batch`], except that the reports are processed in parallel.
+    /// The output is in the same order as the inputs.
+    #[allow(clippy::type_complexity)]
+    pub fn prepare_step_batch_multithreaded<M>(
         &self,
-        _public_param: &(),
-        measurement: &T::Measurement,
-    ) -> Result<Vec<Prio3InputShare<T::Field, L>>, VdafError> {
-        self.shard_with_rand_source(&(), measurement, |buf| {
-            buf.fill(1);
-            Ok(())
-        })
+        inputs: M,
+    ) -> Vec<
+        PrepareTransition<
+            Prio3PrepareStep<T::Field, L>,
+            Prio3PrepareMessage<T::Field, L>,
+            OutputShare<T::Field>,
+        >,
+    >
+    where
+        M: IntoParallelIterator<
+            Item = (
+                Prio3PrepareStep<T::Field, L>,
+                Option<Prio3PrepareMessage<T::Field, L>>,
+            ),
+        >,
+    {
+        inputs
+            .into_par_iter()
+            .map(|(step, msg)| self.prepare_step(step, msg))
+            .collect()
     }
 }
 
@@ -397,8 +998,17 @@ where
     type OutputShare = OutputShare<T::Field>;
     type AggregateShare = AggregateShare<T::Field>;
 
-    fn setup(&self) -> Result<((), Vec<Prio3VerifyParam<L>>), VdafError> {
-        self.setup_with_rand_source(getrandom::getrandom)
+    fn setup_with_rng(
+        &self,
+        rng: &mut dyn RandSource,
+    ) -> Result<((), Vec<Prio3VerifyParam<L>>), VdafError> {
+        let query_rand_init = Seed::from_rand_source(rng)?;
+        Ok((
+            (),
+            (0..self.num_aggregators)
+                .map(|aggregator_id| self.verify_param(query_rand_init.clone(), aggregator_id))
+                .collect(),
+        ))
     }
 
     fn num_aggregators(&self) -> usize {
@@ -449,8 +1059,8 @@ where
             query_rand_init: Seed::decode(bytes)?,
             aggregator_id: u8::decode(bytes)?,
             input_len: vdaf.typ.input_len(),
-            proof_len: vdaf.typ.proof_len(),
-            verifier_len: vdaf.typ.verifier_len(),
+            proof_len: vdaf.proofs_len(),
+            verifier_len: vdaf.verifiers_len(),
             joint_rand_len: vdaf.typ.joint_rand_len(),
         })
     }
@@ -580,21 +1190,134 @@ where
     A: Clone + Debug + Sync + Send,
     P: Prg<L>,
 {
-    fn shard(
+    fn shard_with_rng(
         &self,
         _public_param: &(),
         measurement: &T::Measurement,
+        rng: &mut dyn RandSource,
     ) -> Result<Vec<Prio3InputShare<T::Field, L>>, VdafError> {
-        self.shard_with_rand_source(&(), measurement, getrandom::getrandom)
+        let mut info = [0; VERS_PRIO3.len() + 1];
+        info[..VERS_PRIO3.len()].clone_from_slice(VERS_PRIO3);
+
+        let num_aggregators = self.num_aggregators;
+        let input = self.typ.encode(measurement)?;
+
+        // Generate the input shares and compute the joint randomness.
+        let mut helper_shares = Vec::with_capacity(num_aggregators as usize - 1);
+        let mut leader_input_share = input.clone();
+        let mut joint_rand_seed = Seed::uninitialized();
+        for aggregator_id in 1..num_aggregators {
+            let mut helper = HelperShare::from_rand_source(rng)?;
+
+            let mut deriver = P::init(&helper.joint_rand_param.blind);
+            deriver.update(&[aggregator_id]);
+            info[VERS_PRIO3.len()] = aggregator_id;
+            let prng: Prng<T::Field, _> =
+                Prng::from_seed_stream(P::seed_stream(&helper.input_share, &info));
+            for (x, y) in leader_input_share
+                .iter_mut()
+                .zip(prng)
+                .take(self.typ.input_len())
+            {
+                *x -= y;
+                deriver.update(&y.into());
+            }
+
+            helper.joint_rand_param.seed_hint = deriver.into_seed();
+            joint_rand_seed.xor_accumulate(&helper.joint_rand_param.seed_hint);
+
+            helper_shares.push(helper);
+        }
+
+        let leader_blind = Seed::from_rand_source(rng)?;
+
+        let mut deriver = P::init(&leader_blind);
+        deriver.update(&[0]); // ID of the leader
+        for x in leader_input_share.iter() {
+            deriver.update(&(*x).into());
+        }
+
+        let mut leader_joint_rand_seed_hint = deriver.into_seed();
+        joint_rand_seed.xor_accumulate(&leader_joint_rand_seed_hint);
+
+        // Run the proof-generation algorithm once for each proof. Each proof uses its own chunk
+        // of the prove and joint randomness.
+        let num_proofs = self.num_proofs as usize;
+        let prng: Prng<T::Field, _> =
+            Prng::from_seed_stream(P::seed_stream(&joint_rand_seed, VERS_PRIO3));
+        let joint_rand: Vec<T::Field> = prng.take(self.typ.joint_rand_len() * num_proofs).collect();
+        let prng: Prng<T::Field, _> =
+            Prng::from_seed_stream(P::seed_stream(&Seed::from_rand_source(rng)?, VERS_PRIO3));
+        let prove_rand: Vec<T::Field> = prng.take(self.typ.prove_rand_len() * num_proofs).collect();
+        let mut leader_proof_share = Vec::with_capacity(self.proofs_len());
+        for i in 0..num_proofs {
+            leader_proof_share.append(&mut self.typ.prove(
+                &input,
+                chunk(&prove_rand, self.typ.prove_rand_len(), i),
+                chunk(&joint_rand, self.typ.joint_rand_len(), i),
+            )?);
+        }
+
+        // Generate the proof shares and finalize the joint randomness seed hints.
+        for (j, helper) in helper_shares.iter_mut().enumerate() {
+            info[VERS_PRIO3.len()] = j as u8 + 1;
+            let prng: Prng<T::Field, _> =
+                Prng::from_seed_stream(P::seed_stream(&helper.proof_share, &info));
+            for (x, y) in leader_proof_share
+                .iter_mut()
+                .zip(prng)
+                .take(self.proofs_len())
+            {
+                *x -= y;
+            }
+
+            helper
+                .joint_rand_param
+                .seed_hint
+                .xor_accumulate(&joint_rand_seed);
+        }
+
+        leader_joint_rand_seed_hint.xor_accumulate(&joint_rand_seed);
+
+        let leader_joint_rand_param = if self.typ.joint_rand_len() > 0 {
+            Some(JointRandParam {
+                seed_hint: leader_joint_rand_seed_hint,
+                blind: leader_blind,
+            })
+        } else {
+            None
+        };
+
+        // Prep the output messages.
+        let mut out = Vec::with_capacity(num_aggregators as usize);
+        out.push(Prio3InputShare {
+            input_share: Share::Leader(leader_input_share),
+            proof_share: Share::Leader(leader_proof_share),
+            joint_rand_param: leader_joint_rand_param,
+        });
+
+        for helper in helper_shares.into_iter() {
+            let helper_joint_rand_param = if self.typ.joint_rand_len() > 0 {
+                Some(helper.joint_rand_param)
+            } else {
+                None
+            };
+
+            out.push(Prio3InputShare {
+                input_share: Share::Helper(helper.input_share),
+                proof_share: Share::Helper(helper.proof_share),
+                joint_rand_param: helper_joint_rand_param,
+            });
+        }
+
+        Ok(out)
     }
 }
 
 /// State of each aggregator during the preparation phase.
 ///
-/// Serialization traits [`Encode` and `ParameterizedDecoded`] are implemented for this type.
-/// However, the prepare step may only be encoded in the "waiting" state (i.e., after completing
-/// the first call to [`Prio3::prepare_step`]). In particular, the [`Prio3PrepareStep::encode`]
-/// panics if called in the "ready" state (i.e., on the output of [`Prio3::prepare_init`]).
+/// Serialization traits [`Encode`] and [`ParameterizedDecode`] are implemented for this type, so
+/// that the state can be persisted between rounds of the preparation phase.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Prio3PrepareStep<F, const L: usize> {
     input_share: Share<F, L>,
@@ -604,50 +1327,72 @@ pub struct Prio3PrepareStep<F, const L: usize> {
     state: PrepareStep<F, L>,
 }
 
+/// A [`Prio3PrepareStep`] is encoded as a byte indicating its state, `0` for ready and `1` for
+/// waiting, followed by the input share, the joint randomness seed (if any) and, in the ready
+/// state, the verifier message.
+///
+/// The leading state byte was added so that the waiting state can be persisted too. States encoded
+/// by previous releases, which could only be in the ready state, do not decode.
 impl<F: FieldElement, const L: usize> Encode for Prio3PrepareStep<F, L> {
-    /// Append the encoded form of this object to the end of `bytes`, growing the vector as needed.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the step is not in the "waiting" state. See [`Prio3PrepareStep`] for details.
     fn encode(&self, bytes: &mut Vec<u8>) {
-        if let PrepareStep::Ready(..) = self.state {
-            panic!("encooding of ready state is not permitted (see documentation for details)");
+        match self.state {
+            PrepareStep::Ready(..) => 0u8.encode(bytes),
+            PrepareStep::Waiting => 1u8.encode(bytes),
         }
         self.input_share.encode(bytes);
         if let Some(ref seed) = self.joint_rand_seed {
             seed.encode(bytes);
         }
+        if let PrepareStep::Ready(ref msg) = self.state {
+            msg.encode(bytes);
+        }
     }
 }
 
-impl<F: FieldElement, const L: usize> ParameterizedDecode<Prio3VerifyParam<L>>
-    for Prio3PrepareStep<F, L>
+impl<'a, T, A, P, const L: usize>
+    ParameterizedDecode<(&'a Prio3<T, A, P, L>, &'a Prio3VerifyParam<L>)>
+    for Prio3PrepareStep<T::Field, L>
+where
+    T: Type,
+    A: Clone + Debug,
+    P: Prg<L>,
 {
     fn decode_with_param(
-        verify_param: &Prio3VerifyParam<L>,
+        (vdaf, verify_param): &(&'a Prio3<T, A, P, L>, &'a Prio3VerifyParam<L>),
         bytes: &mut Cursor<&[u8]>,
     ) -> Result<Self, CodecError> {
+        let is_ready = match u8::decode(bytes)? {
+            0 => true,
+            1 => false,
+            _ => return Err(CodecError::UnexpectedValue),
+        };
+
         let share_decoder = if verify_param.aggregator_id == 0 {
-            ShareDecodingParameter::Leader(verify_param.input_len)
+            ShareDecodingParameter::Leader(vdaf.typ.input_len())
         } else {
             ShareDecodingParameter::Helper
         };
         let input_share = Share::decode_with_param(&share_decoder, bytes)?;
 
-        let joint_rand_seed = if verify_param.joint_rand_len > 0 {
+        let joint_rand_seed = if vdaf.typ.joint_rand_len() > 0 {
             Some(Seed::decode(bytes)?)
         } else {
             None
         };
 
-        Ok(Self {
+        let mut step = Self {
             input_share,
             joint_rand_seed,
             aggregator_id: verify_param.aggregator_id,
-            verifier_len: verify_param.verifier_len,
+            verifier_len: vdaf.verifiers_len(),
             state: PrepareStep::Waiting,
-        })
+        };
+
+        if is_ready {
+            step.state = PrepareStep::Ready(Prio3PrepareMessage::decode_with_param(&step, bytes)?);
+        }
+
+        Ok(step)
     }
 }
 
@@ -669,114 +1414,55 @@ where
         nonce: &[u8],
         msg: &Prio3InputShare<T::Field, L>,
     ) -> Result<Prio3PrepareStep<T::Field, L>, VdafError> {
-        let mut info = [0; VERS_PRIO3.len() + 1];
-        info[..VERS_PRIO3.len()].clone_from_slice(VERS_PRIO3);
-        info[VERS_PRIO3.len()] = verify_param.aggregator_id;
-
-        let mut deriver = P::init(&verify_param.query_rand_init);
-        deriver.update(&[255]);
-        deriver.update(nonce);
-        let query_rand_seed = deriver.into_seed();
-
-        // Create a reference to the (expanded) input share.
-        let expanded_input_share: Option<Vec<T::Field>> = match msg.input_share {
-            Share::Leader(_) => None,
-            Share::Helper(ref seed) => {
-                let prng = Prng::from_seed_stream(P::seed_stream(seed, &info));
-                Some(prng.take(self.typ.input_len()).collect())
-            }
-        };
-        let input_share = match msg.input_share {
-            Share::Leader(ref data) => data,
-            Share::Helper(_) => expanded_input_share.as_ref().unwrap(),
-        };
-
-        // Create a reference to the (expanded) proof share.
-        let expanded_proof_share: Option<Vec<T::Field>> = match msg.proof_share {
-            Share::Leader(_) => None,
-            Share::Helper(ref seed) => {
-                let prng = Prng::from_seed_stream(P::seed_stream(seed, &info));
-                Some(prng.take(self.typ.proof_len()).collect())
-            }
-        };
-        let proof_share = match msg.proof_share {
-            Share::Leader(ref data) => data,
-            Share::Helper(_) => expanded_proof_share.as_ref().unwrap(),
-        };
-
-        // Compute the joint randomness.
-        let (joint_rand_seed, joint_rand_seed_share, joint_rand) = if self.typ.joint_rand_len() > 0
-        {
-            let mut deriver = P::init(&msg.joint_rand_param.as_ref().unwrap().blind);
-            deriver.update(&[verify_param.aggregator_id]);
-            for x in input_share {
-                deriver.update(&(*x).into());
-            }
-            let joint_rand_seed_share = deriver.into_seed();
-
-            let mut joint_rand_seed = Seed::uninitialized();
-            joint_rand_seed.xor(
-                &msg.joint_rand_param.as_ref().unwrap().seed_hint,
-                &joint_rand_seed_share,
-            );
-
-            let prng: Prng<T::Field, _> =
-                Prng::from_seed_stream(P::seed_stream(&joint_rand_seed, VERS_PRIO3));
-            (
-                Some(joint_rand_seed),
-                Some(joint_rand_seed_share),
-                prng.take(self.typ.joint_rand_len()).collect(),
-            )
-        } else {
-            (None, None, Vec::new())
-        };
-
-        // Compute the query randomness.
-        let prng: Prng<T::Field, _> =
-            Prng::from_seed_stream(P::seed_stream(&query_rand_seed, VERS_PRIO3));
-        let query_rand: Vec<T::Field> = prng.take(self.typ.query_rand_len()).collect();
-
-        // Run the query-generation algorithm.
-        let verifier_share = self.typ.query(
-            input_share,
-            proof_share,
-            &query_rand,
-            &joint_rand,
-            self.num_aggregators as usize,
-        )?;
+        self.prepare_init_with_scratch(&mut PrepareScratch::new(), verify_param, nonce, msg)
+    }
 
-        Ok(Prio3PrepareStep {
-            input_share: msg.input_share.clone(),
-            joint_rand_seed,
-            aggregator_id: verify_param.aggregator_id,
-            verifier_len: verifier_share.len(),
-            state: PrepareStep::Ready(Prio3PrepareMessage {
-                verifier: verifier_share,
-                joint_rand_seed: joint_rand_seed_share,
-            }),
-        })
+    /// Begins the Prep process for a batch of reports. The buffers used to query the proofs are
+    /// reused across the reports.
+    fn prepare_init_batch<N: AsRef<[u8]>>(
+        &self,
+        verify_param: &Prio3VerifyParam<L>,
+        _agg_param: &(),
+        reports: &[(N, Prio3InputShare<T::Field, L>)],
+    ) -> Vec<
+        PrepareTransition<
+            Prio3PrepareStep<T::Field, L>,
+            Prio3PrepareMessage<T::Field, L>,
+            OutputShare<T::Field>,
+        >,
+    > {
+        let mut scratch = PrepareScratch::new();
+        reports
+            .iter()
+            .map(|(nonce, input_share)| {
+                self.prepare_init_report(&mut scratch, verify_param, nonce.as_ref(), input_share)
+            })
+            .collect()
     }
 
     fn prepare_preprocess<M: IntoIterator<Item = Prio3PrepareMessage<T::Field, L>>>(
         &self,
         inputs: M,
     ) -> Result<Self::PrepareMessage, VdafError> {
-        let mut verifier = vec![T::Field::zero(); self.typ.verifier_len()];
+        let mut verifier = vec![T::Field::zero(); self.verifiers_len()];
         let mut joint_rand_seed = Seed::uninitialized();
         let mut count = 0;
         for share in inputs.into_iter() {
             count += 1;
 
             if share.verifier.len() != verifier.len() {
-                return Err(VdafError::Uncategorized(format!(
-                    "unexpected verifier share length: got {}; want {}",
-                    share.verifier.len(),
-                    verifier.len(),
-                )));
+                return Err(PrepareError::LengthMismatch {
+                    what: "verifier share",
+                    got: share.verifier.len(),
+                    want: verifier.len(),
+                }
+                .into());
             }
 
             if self.typ.joint_rand_len() > 0 {
-                let joint_rand_seed_share = share.joint_rand_seed.unwrap();
+                let joint_rand_seed_share = share
+                    .joint_rand_seed
+                    .ok_or(PrepareError::JointRandMismatch)?;
                 joint_rand_seed.xor_accumulate(&joint_rand_seed_share);
             }
 
@@ -786,10 +1472,11 @@ where
         }
 
         if count != self.num_aggregators {
-            return Err(VdafError::Uncategorized(format!(
-                "unexpected message count: got {}; want {}",
-                count, self.num_aggregators,
-            )));
+            return Err(PrepareError::MessageCount {
+                got: count as usize,
+                want: self.num_aggregators as usize,
+            }
+            .into());
         }
 
         let joint_rand_seed = if self.typ.joint_rand_len() > 0 {
@@ -824,27 +1511,35 @@ where
             (PrepareStep::Waiting, Some(msg)) => {
                 if self.typ.joint_rand_len() > 0 {
                     // Check that the joint randomness was correct.
-                    if step.joint_rand_seed.as_ref().unwrap()
-                        != msg.joint_rand_seed.as_ref().unwrap()
+                    if step.joint_rand_seed.is_none() || step.joint_rand_seed != msg.joint_rand_seed
                     {
-                        return PrepareTransition::Fail(VdafError::Uncategorized(
-                            "joint randomness mismatch".to_string(),
-                        ));
+                        return PrepareTransition::Fail(PrepareError::JointRandMismatch.into());
                     }
                 }
 
-                // Check the proof.
-                let res = match self.typ.decide(&msg.verifier) {
-                    Ok(res) => res,
-                    Err(err) => {
-                        return PrepareTransition::Fail(VdafError::from(err));
-                    }
-                };
+                // Check each proof.
+                if msg.verifier.len() != self.verifiers_len() {
+                    return PrepareTransition::Fail(
+                        PrepareError::LengthMismatch {
+                            what: "verifier",
+                            got: msg.verifier.len(),
+                            want: self.verifiers_len(),
+                        }
+                        .into(),
+                    );
+                }
 
-                if !res {
-                    return PrepareTransition::Fail(VdafError::Uncategorized(
-                        "proof check failed".to_string(),
-                    ));
+                for verifier in msg.verifier.chunks(self.typ.verifier_len()) {
+                    let res = match self.typ.decide(verifier) {
+                        Ok(res) => res,
+                        Err(err) => {
+                            return PrepareTransition::Fail(VdafError::from(err));
+                        }
+                    };
+
+                    if !res {
+                        return PrepareTransition::Fail(PrepareError::VerifyFailed.into());
+                    }
                 }
 
                 // Compute the output share.
@@ -868,9 +1563,7 @@ where
 
                 PrepareTransition::Finish(output_share)
             }
-            _ => PrepareTransition::Fail(VdafError::Uncategorized(
-                "invalid state transition".to_string(),
-            )),
+            _ => PrepareTransition::Fail(PrepareError::InvalidStateTransition.into()),
         }
     }
 
@@ -892,7 +1585,7 @@ where
 impl<T, A, P, const L: usize> Collector for Prio3<T, A, P, L>
 where
     T: Type,
-    A: Clone + Debug + Sync + Send + TryFrom<AggregateShare<T::Field>, Error = VdafError> + Eq,
+    A: Clone + Debug + Sync + Send + TryFrom<AggregateShare<T::Field>, Error = VdafError>,
     P: Prg<L>,
 {
     /// Combines aggregate shares into the aggregate result.
@@ -910,6 +1603,150 @@ where
     }
 }
 
+// The aggregate result is represented in test vectors as the sum of the aggregate shares. This is
+// shared by the instances whose aggregate result is converted from the aggregate share and those
+// with their own `Collector` implementation.
+#[cfg(any(feature = "test-vector", test))]
+macro_rules! prio3_test_vector_vdaf_items {
+    ($field:ty, $measurement:ty) => {
+        type TestVecMeasurement = $measurement;
+        type TestVecAggParam = ();
+        type TestVecAggResult = Vec<u128>;
+
+        fn test_vec_verify_param(&self, verify_param: &Prio3VerifyParam<L>) -> (u8, Vec<u8>) {
+            (
+                verify_param.aggregator_id,
+                verify_param.query_rand_init.0.to_vec(),
+            )
+        }
+
+        fn test_vec_measurement(
+            &self,
+            measurement: &$measurement,
+        ) -> Result<$measurement, VdafError> {
+            Ok(measurement.clone())
+        }
+
+        fn test_vec_agg_param(&self, _agg_param: &()) -> Result<(), VdafError> {
+            Ok(())
+        }
+
+        fn test_vec_output_share(&self, output_share: &OutputShare<$field>) -> Vec<u128> {
+            test_vec_field_vec(output_share.as_ref())
+        }
+
+        fn test_vec_agg_share(&self, agg_share: &AggregateShare<$field>) -> Vec<u128> {
+            test_vec_field_vec(agg_share.as_ref())
+        }
+
+        fn test_vec_agg_result(
+            &self,
+            _agg_param: &(),
+            agg_shares: Vec<AggregateShare<$field>>,
+        ) -> Result<Vec<u128>, VdafError> {
+            let mut agg = AggregateShare(vec![<$field>::zero(); self.typ.output_len()]);
+            for agg_share in agg_shares.iter() {
+                agg.merge(agg_share)?;
+            }
+
+            Ok(test_vec_field_vec(agg.as_ref()))
+        }
+    };
+}
+
+#[cfg(any(feature = "test-vector", test))]
+impl<T, A, P, const L: usize> TestVectorVdaf for Prio3<T, A, P, L>
+where
+    T: Type,
+    T::Measurement: PartialEq + Serialize + DeserializeOwned,
+    A: Clone + Debug + Sync + Send + TryFrom<AggregateShare<T::Field>, Error = VdafError>,
+    P: Prg<L>,
+{
+    prio3_test_vector_vdaf_items!(T::Field, T::Measurement);
+}
+
+#[cfg(any(feature = "test-vector", test))]
+impl<SPoly, SMul, P, const L: usize> TestVectorVdaf
+    for Prio3<FixedPointBoundedL2VecSum<Field128, SPoly, SMul>, Prio3ResultVec<f64>, P, L>
+where
+    SPoly: 'static + ParallelSumGadget<Field128, BlindPolyEval<Field128>> + Eq,
+    SMul: 'static + ParallelSumGadget<Field128, Mul<Field128>> + Eq,
+    P: Prg<L>,
+{
+    prio3_test_vector_vdaf_items!(Field128, Vec<f64>);
+}
+
+#[cfg(any(feature = "test-vector", test))]
+impl<P, const L: usize> TestVectorVdaf
+    for Prio3<CategoricalHistogram<Field128>, Prio3ResultCategoricalHistogram, P, L>
+where
+    P: Prg<L>,
+{
+    prio3_test_vector_vdaf_items!(Field128, usize);
+}
+
+impl<SPoly, SMul, P, const L: usize> Collector
+    for Prio3<FixedPointBoundedL2VecSum<Field128, SPoly, SMul>, Prio3ResultVec<f64>, P, L>
+where
+    SPoly: 'static + ParallelSumGadget<Field128, BlindPolyEval<Field128>> + Eq,
+    SMul: 'static + ParallelSumGadget<Field128, Mul<Field128>> + Eq,
+    P: Prg<L>,
+{
+    /// Combines aggregate shares into the aggregate result. Each entry of the aggregate is a
+    /// signed fixed-point number, which is decoded into an `f64`.
+    fn unshard<It: IntoIterator<Item = AggregateShare<Field128>>>(
+        &self,
+        _agg_param: &(),
+        agg_shares: It,
+    ) -> Result<Prio3ResultVec<f64>, VdafError> {
+        let mut agg = AggregateShare(vec![Field128::zero(); self.typ.output_len()]);
+        for agg_share in agg_shares.into_iter() {
+            agg.merge(&agg_share)?;
+        }
+
+        // Field elements larger than `p/2` represent negative numbers.
+        let modulus = Field128::modulus();
+        let scale = (1_u128 << (self.typ.bits() - 1)) as f64;
+        Ok(Prio3ResultVec(
+            agg.0
+                .into_iter()
+                .map(|x| {
+                    let x = u128::from(x);
+                    if x > modulus / 2 {
+                        -((modulus - x) as f64) / scale
+                    } else {
+                        x as f64 / scale
+                    }
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl<P, const L: usize> Collector
+    for Prio3<CategoricalHistogram<Field128>, Prio3ResultCategoricalHistogram, P, L>
+where
+    P: Prg<L>,
+{
+    /// Combines aggregate shares into the aggregate result. The count of each bucket is paired
+    /// with its label.
+    fn unshard<It: IntoIterator<Item = AggregateShare<Field128>>>(
+        &self,
+        _agg_param: &(),
+        agg_shares: It,
+    ) -> Result<Prio3ResultCategoricalHistogram, VdafError> {
+        let mut agg = AggregateShare(vec![Field128::zero(); self.typ.output_len()]);
+        for agg_share in agg_shares.into_iter() {
+            agg.merge(&agg_share)?;
+        }
+
+        let Prio3ResultVec(counts) = Prio3ResultVec::<u64>::try_from(agg)?;
+        Ok(Prio3ResultCategoricalHistogram(
+            self.typ.labels().iter().cloned().zip(counts).collect(),
+        ))
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct JointRandParam<const L: usize> {
     /// The sum of the joint randomness seed shares sent to the other Aggregators.
@@ -919,6 +1756,11 @@ struct JointRandParam<const L: usize> {
     blind: Seed<L>,
 }
 
+// Returns the `i`-th chunk of length `len` of `data`.
+fn chunk<F>(data: &[F], len: usize, i: usize) -> &[F] {
+    &data[i * len..(i + 1) * len]
+}
+
 #[derive(Clone)]
 struct HelperShare<const L: usize> {
     input_share: Seed<L>,
@@ -927,7 +1769,7 @@ struct HelperShare<const L: usize> {
 }
 
 impl<const L: usize> HelperShare<L> {
-    fn from_rand_source(rand_source: RandSource) -> Result<Self, VdafError> {
+    fn from_rand_source(rand_source: &mut dyn RandSource) -> Result<Self, VdafError> {
         Ok(HelperShare {
             input_share: Seed::from_rand_source(rand_source)?,
             proof_share: Seed::from_rand_source(rand_source)?,
@@ -945,11 +1787,38 @@ enum PrepareStep<F, const L: usize> {
     Waiting,
 }
 
+/// Buffers used by an aggregator to begin the preparation of a report, reused across reports.
+#[derive(Debug)]
+struct PrepareScratch<F: FieldElement> {
+    query: QueryScratch<F>,
+    input_share: Vec<F>,
+    proof_share: Vec<F>,
+    joint_rand: Vec<F>,
+    query_rand: Vec<F>,
+}
+
+impl<F: FieldElement> PrepareScratch<F> {
+    fn new() -> Self {
+        Self {
+            query: QueryScratch::new(),
+            input_share: Vec::new(),
+            proof_share: Vec::new(),
+            joint_rand: Vec::new(),
+            query_rand: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod robustness;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::vdaf::{run_vdaf, run_vdaf_prepare};
+    use crate::vdaf::test_vector::{check_test_vector, generate_test_vector};
+    use crate::vdaf::{prg::RngSource, run_vdaf, run_vdaf_prepare};
     use assert_matches::assert_matches;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn test_prio3_count() {
@@ -970,6 +1839,299 @@ mod tests {
         run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares).unwrap();
 
         test_prepare_step_serialization(&prio3, &1).unwrap();
+
+        assert_matches!(Prio3Aes128Count::new(0), Err(VdafError::NumAggregators(_)));
+        assert_matches!(
+            Prio3Aes128Count::new(255),
+            Err(VdafError::NumAggregators(_))
+        );
+    }
+
+    #[test]
+    fn test_prio3_with_rng() {
+        let prio3 = Prio3Aes128Sum::new(2, 8).unwrap();
+
+        // The same seeded RNG yields the same parameters and input shares.
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        let (public_param, verify_params) = prio3.setup_with_rng(&mut rng).unwrap();
+        let input_shares = prio3.shard_with_rng(&public_param, &42, &mut rng).unwrap();
+
+        let mut rng = RngSource(StdRng::seed_from_u64(1337));
+        let (_, got_verify_params) = prio3.setup_with_rng(&mut rng).unwrap();
+        let got_input_shares = prio3.shard_with_rng(&public_param, &42, &mut rng).unwrap();
+        assert_eq!(got_verify_params, verify_params);
+        assert_eq!(got_input_shares, input_shares);
+
+        let out_shares = run_vdaf_prepare(
+            &prio3,
+            &verify_params,
+            &(),
+            b"this is a nonce",
+            input_shares,
+        )
+        .unwrap();
+        let agg_shares = out_shares
+            .into_iter()
+            .map(AggregateShare::from)
+            .collect::<Vec<_>>();
+        assert_eq!(prio3.unshard(&(), agg_shares).unwrap(), Prio3Result(42));
+
+        // A closure can also be used as the source of randomness.
+        let mut counter = 0u8;
+        let mut rand_source = |buf: &mut [u8]| {
+            for x in buf.iter_mut() {
+                *x = counter;
+                counter = counter.wrapping_add(1);
+            }
+            Ok(())
+        };
+        prio3
+            .shard_with_rng(&public_param, &42, &mut rand_source)
+            .unwrap();
+        assert_ne!(counter, 0);
+    }
+
+    #[test]
+    fn test_prio3_setup_from_master_key() {
+        let prio3 = Prio3Aes128Count::new(3).unwrap();
+        let master_key = Seed::generate().unwrap();
+
+        // Each aggregator derives its own verification parameter, which matches the one derived by
+        // the setup and round-trips through its encoding.
+        let (public_param, verify_params) =
+            prio3.setup_from_master_key(&master_key, b"task 1").unwrap();
+        assert_eq!(verify_params.len(), 3);
+        for (aggregator_id, want) in verify_params.iter().enumerate() {
+            let (got, encoded) = prio3
+                .derive_verify_param(&master_key, b"task 1", aggregator_id as u8)
+                .unwrap();
+            assert_eq!(&got, want);
+            assert_eq!(
+                Prio3VerifyParam::get_decoded_with_param(&prio3, &encoded).unwrap(),
+                got
+            );
+        }
+        assert!(prio3
+            .derive_verify_param(&master_key, b"task 1", 3)
+            .is_err());
+
+        // The derived parameters are usable.
+        let input_shares = prio3.shard(&public_param, &1).unwrap();
+        let out_shares = run_vdaf_prepare(
+            &prio3,
+            &verify_params,
+            &(),
+            b"this is a nonce",
+            input_shares,
+        )
+        .unwrap();
+        let agg_shares = out_shares
+            .into_iter()
+            .map(AggregateShare::from)
+            .collect::<Vec<_>>();
+        assert_eq!(prio3.unshard(&(), agg_shares).unwrap(), Prio3Result(1));
+
+        // Distinct tasks and distinct master keys yield distinct parameters.
+        let (_, other_verify_params) = prio3.setup_from_master_key(&master_key, b"task 2").unwrap();
+        assert_ne!(
+            other_verify_params[0].query_rand_init,
+            verify_params[0].query_rand_init
+        );
+        let other_master_key = Seed::generate().unwrap();
+        let (_, other_verify_params) = prio3
+            .setup_from_master_key(&other_master_key, b"task 1")
+            .unwrap();
+        assert_ne!(
+            other_verify_params[0].query_rand_init,
+            verify_params[0].query_rand_init
+        );
+
+        // So do instances with a different type, input length, number of aggregators or number of
+        // proofs.
+        let others = [
+            Prio3Aes128Sum::new(3, 1)
+                .unwrap()
+                .setup_from_master_key(&master_key, b"task 1"),
+            Prio3Aes128CountVec::new(3, 2)
+                .unwrap()
+                .setup_from_master_key(&master_key, b"task 1"),
+            Prio3Aes128CountVec::new(3, 3)
+                .unwrap()
+                .setup_from_master_key(&master_key, b"task 1"),
+            Prio3Aes128Count::new(2)
+                .unwrap()
+                .setup_from_master_key(&master_key, b"task 1"),
+            Prio3Aes128Count::new(3)
+                .unwrap()
+                .with_num_proofs(2)
+                .unwrap()
+                .setup_from_master_key(&master_key, b"task 1"),
+        ];
+        for other in others.iter() {
+            let (_, other_verify_params) = other.as_ref().unwrap();
+            assert_ne!(
+                other_verify_params[0].query_rand_init,
+                verify_params[0].query_rand_init
+            );
+        }
+    }
+
+    #[test]
+    fn test_prio3_prepare_batch() {
+        let prio3 = Prio3Aes128Count::new(2).unwrap();
+        test_prepare_batch(&prio3, &[1, 0, 1, 1, 0], Prio3Result(3)).unwrap();
+
+        let prio3 = Prio3Aes128Sum::new(3, 16)
+            .unwrap()
+            .with_num_proofs(2)
+            .unwrap();
+        test_prepare_batch(
+            &prio3,
+            &[1337, 0, 99, 65535],
+            Prio3Result(1337 + 99 + 65535),
+        )
+        .unwrap();
+
+        let prio3 = Prio3Aes128Histogram::new(2, &[1, 10, 100]).unwrap();
+        test_prepare_batch(&prio3, &[0, 5, 50, 500], Prio3ResultVec(vec![1, 1, 1, 1])).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_prio3_prepare_batch_multithreaded() {
+        let prio3 = Prio3Aes128SumVecMultithreaded::new(2, 8, 16).unwrap();
+        let (_, verify_params) = prio3.setup().unwrap();
+        let reports = (0..16u128)
+            .map(|i| {
+                let input_shares = prio3.shard(&(), &vec![i; 16]).unwrap();
+                (i.to_le_bytes(), input_shares)
+            })
+            .collect::<Vec<_>>();
+
+        let mut steps = Vec::new();
+        let mut msgs = vec![Vec::new(); reports.len()];
+        for (j, verify_param) in verify_params.iter().enumerate() {
+            let batch = reports
+                .iter()
+                .map(|(nonce, input_shares)| (*nonce, input_shares[j].clone()))
+                .collect::<Vec<_>>();
+            let got = prio3.prepare_init_batch_multithreaded(verify_param, &(), &batch);
+            let want = prio3.prepare_init_batch(verify_param, &(), &batch);
+            assert_eq!(got.len(), want.len());
+            let mut aggregator_steps = Vec::new();
+            for (i, (got, want)) in got.into_iter().zip(want).enumerate() {
+                assert_matches!((got, want), (
+                    PrepareTransition::Continue(got_step, got_msg),
+                    PrepareTransition::Continue(want_step, want_msg),
+                ) => {
+                    assert_eq!(got_step, want_step);
+                    assert_eq!(got_msg, want_msg);
+                    aggregator_steps.push(got_step);
+                    msgs[i].push(got_msg);
+                });
+            }
+            steps.push(aggregator_steps);
+        }
+
+        // Each aggregator finishes preparing the batch.
+        let msgs = msgs
+            .into_iter()
+            .map(|msgs| prio3.prepare_preprocess(msgs).unwrap())
+            .collect::<Vec<_>>();
+        for aggregator_steps in steps {
+            let inputs = aggregator_steps
+                .into_iter()
+                .zip(msgs.iter())
+                .map(|(step, msg)| (step, Some(msg.clone())))
+                .collect::<Vec<_>>();
+            let got = prio3.prepare_step_batch_multithreaded(inputs.clone());
+            let want = prio3.prepare_step_batch(inputs);
+            assert_eq!(got.len(), want.len());
+            for (got, want) in got.into_iter().zip(want) {
+                assert_matches!((got, want), (
+                    PrepareTransition::Finish(got),
+                    PrepareTransition::Finish(want),
+                ) => assert_eq!(got, want));
+            }
+        }
+    }
+
+    #[test]
+    fn test_prio3_count_multiple_proofs() {
+        let prio3 = Prio3Aes128Count::new(2)
+            .unwrap()
+            .with_num_proofs(3)
+            .unwrap();
+        assert_eq!(prio3.num_proofs(), 3);
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [1, 0, 0, 1, 1]).unwrap(),
+            Prio3Result(3)
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        // The proof share carries each of the proofs.
+        let input_shares = prio3.shard(&(), &1).unwrap();
+        let proof_len = prio3.typ.proof_len();
+        assert_matches!(input_shares[0].proof_share, Share::Leader(ref data) => {
+            assert_eq!(data.len(), 3 * proof_len);
+        });
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            let got =
+                Prio3InputShare::get_decoded_with_param(verify_param, &input_share.get_encoded())
+                    .unwrap();
+            assert_eq!(&got, input_share);
+        }
+
+        // Tampering with any one of the proofs is detected.
+        for i in 0..3 {
+            let mut input_shares = prio3.shard(&(), &1).unwrap();
+            assert_matches!(input_shares[0].proof_share, Share::Leader(ref mut data) => {
+                data[i * proof_len] += Field64::one();
+            });
+            let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+            assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+        }
+
+        // The proofs are generated with independent randomness.
+        let input_shares = prio3.shard(&(), &1).unwrap();
+        assert_matches!(input_shares[0].proof_share, Share::Leader(ref data) => {
+            assert_ne!(data[..proof_len], data[proof_len..2 * proof_len]);
+        });
+
+        assert_matches!(
+            Prio3Aes128Count::new(2).unwrap().with_num_proofs(0),
+            Err(VdafError::InvalidParameter(_))
+        );
+
+        test_prepare_step_serialization(&prio3, &1).unwrap();
+    }
+
+    #[test]
+    fn test_prio3_sum_multiple_proofs() {
+        let prio3 = Prio3Aes128Sum::new(3, 16)
+            .unwrap()
+            .with_num_proofs(2)
+            .unwrap();
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [0, (1 << 16) - 1, 0, 1, 1]).unwrap(),
+            Prio3Result((1 << 16) + 1)
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &1).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        test_prepare_step_serialization(&prio3, &1).unwrap();
     }
 
     #[test]
@@ -987,7 +2149,7 @@ mod tests {
         let mut input_shares = prio3.shard(&(), &1).unwrap();
         input_shares[0].joint_rand_param.as_mut().unwrap().blind.0[0] ^= 255;
         let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
-        assert_matches!(result, Err(VdafError::Uncategorized(_)));
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
 
         let mut input_shares = prio3.shard(&(), &1).unwrap();
         input_shares[0]
@@ -997,25 +2159,303 @@ mod tests {
             .seed_hint
             .0[0] ^= 255;
         let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
-        assert_matches!(result, Err(VdafError::Uncategorized(_)));
+        assert_matches!(
+            result,
+            Err(VdafError::Prepare(PrepareError::JointRandMismatch))
+        );
 
         let mut input_shares = prio3.shard(&(), &1).unwrap();
         assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
             data[0] += Field128::one();
         });
         let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
-        assert_matches!(result, Err(VdafError::Uncategorized(_)));
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
 
         let mut input_shares = prio3.shard(&(), &1).unwrap();
         assert_matches!(input_shares[0].proof_share, Share::Leader(ref mut data) => {
                 data[0] += Field128::one();
         });
         let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
-        assert_matches!(result, Err(VdafError::Uncategorized(_)));
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
 
         test_prepare_step_serialization(&prio3, &1).unwrap();
     }
 
+    #[test]
+    fn test_prio3_bounded_sum() {
+        let prio3 = Prio3Aes128BoundedSum::new(3, 100, 1000).unwrap();
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [100, 1000, 537, 999]).unwrap(),
+            Prio3Result(2636)
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &537).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &99).unwrap_err();
+        prio3.shard(&(), &1001).unwrap_err();
+        Prio3Aes128BoundedSum::new(2, 10, 9).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &537).unwrap();
+    }
+
+    #[test]
+    fn test_prio3_signed_sum() {
+        let prio3 = Prio3Aes128SignedSum::new(3, 16).unwrap();
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [-100, 1000, -32768, 32767]).unwrap(),
+            Prio3Result(899)
+        );
+
+        assert_eq!(run_vdaf(&prio3, &(), [-7, -5, 3]).unwrap(), Prio3Result(-9));
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &-537).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &-32769).unwrap_err();
+        prio3.shard(&(), &32768).unwrap_err();
+        Prio3Aes128SignedSum::new(2, 65).unwrap_err();
+
+        // The full range of `i64` is supported.
+        let prio3 = Prio3Aes128SignedSum::new(2, 64).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [i64::MIN, i64::MAX]).unwrap(),
+            Prio3Result(-1)
+        );
+        prio3
+            .unshard(&(), [prio3.aggregate(&(), []).unwrap()])
+            .unwrap();
+        run_vdaf(&prio3, &(), [i64::MIN, -1]).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &-537).unwrap();
+    }
+
+    #[test]
+    fn test_prio3_mean_variance() {
+        let prio3 = Prio3Aes128MeanVariance::new(2, 8).unwrap();
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [1, 2, 3, 4]).unwrap(),
+            Prio3ResultMeanVariance {
+                count: 4,
+                mean: 2.5,
+                variance: 1.25,
+            }
+        );
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [255]).unwrap(),
+            Prio3ResultMeanVariance {
+                count: 1,
+                mean: 255.0,
+                variance: 0.0,
+            }
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &3).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[8] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &256).unwrap_err();
+        Prio3Aes128MeanVariance::new(2, 33).unwrap_err();
+
+        // Measurements at the upper bound of the largest bit length neither overflow the sum of
+        // squares nor lose the variance to cancellation.
+        let prio3 = Prio3Aes128MeanVariance::new(2, 32).unwrap();
+        let max = (1 << 32) - 1;
+        let result = run_vdaf(&prio3, &(), [max, max]).unwrap();
+        assert_eq!(result.count, 2);
+        assert_eq!(result.mean, max as f64);
+        assert_eq!(result.variance, 0.0);
+
+        let result = run_vdaf(&prio3, &(), [max, max, max - 1]).unwrap();
+        assert_eq!(result.count, 3);
+        assert!((result.variance - 2.0 / 9.0).abs() < 1e-6);
+
+        // The mean is undefined if there are no measurements.
+        prio3
+            .unshard(&(), [prio3.aggregate(&(), []).unwrap()])
+            .unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &3).unwrap();
+    }
+
+    #[test]
+    fn test_prio3_count_vec_with_weight() {
+        let prio3 = Prio3Aes128CountVecWithWeight::new(2, 4, 2).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [vec![1, 1, 0, 0], vec![0, 0, 0, 1], vec![0, 0, 0, 0]]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![1, 1, 0, 1])
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &vec![1, 0, 0, 0]).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[1] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &vec![1, 1, 1, 0]).unwrap_err();
+        Prio3Aes128CountVecWithWeight::new(2, 4, 5).unwrap_err();
+
+        let prio3 = Prio3Aes128CountVecWithWeight::new_exact(2, 4, 2).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [vec![1, 1, 0, 0], vec![0, 1, 0, 1]]).unwrap(),
+            Prio3ResultVec(vec![1, 2, 0, 1])
+        );
+        prio3.shard(&(), &vec![1, 0, 0, 0]).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &vec![0, 1, 1, 0]).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_prio3_count_vec_with_weight_multithreaded() {
+        let prio3 = Prio3Aes128CountVecWithWeightMultithreaded::new(2, 4, 2).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [vec![1, 1, 0, 0], vec![0, 0, 0, 1], vec![0, 0, 0, 0]]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![1, 1, 0, 1])
+        );
+    }
+
+    #[test]
+    fn test_prio3_sum_vec() {
+        let prio3 = Prio3Aes128SumVec::new(2, 8, 3).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [vec![0, 255, 1], vec![1, 0, 1], vec![255, 255, 255]]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![256, 510, 257])
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &vec![1, 2, 3]).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &vec![256, 0, 0]).unwrap_err();
+        Prio3Aes128SumVec::new(2, 65, 3).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &vec![1, 2, 3]).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_prio3_sum_vec_multithreaded() {
+        let prio3 = Prio3Aes128SumVecMultithreaded::new(2, 8, 3).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [vec![0, 255, 1], vec![1, 0, 1], vec![255, 255, 255]]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![256, 510, 257])
+        );
+    }
+
+    #[test]
+    fn test_prio3_fixed_point_bounded_l2_vec_sum() {
+        let prio3 = Prio3Aes128FixedPointBoundedL2VecSum::new(2, 16, 3).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [
+                    vec![0.5, -0.25, 0.0],
+                    vec![-1.0, 0.0, 0.0],
+                    vec![0.5, 0.5, -0.5]
+                ]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![0.0, 0.25, -0.5])
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &vec![0.5, 0.5, 0.5]).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &vec![0.75, 0.75, 0.0]).unwrap_err();
+        prio3.shard(&(), &vec![1.0, 0.0, 0.0]).unwrap_err();
+        Prio3Aes128FixedPointBoundedL2VecSum::new(2, 33, 3).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &vec![0.5, 0.5, 0.5]).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn test_prio3_fixed_point_bounded_l2_vec_sum_multithreaded() {
+        let prio3 = Prio3Aes128FixedPointBoundedL2VecSumMultithreaded::new(2, 16, 3).unwrap();
+
+        assert_eq!(
+            run_vdaf(
+                &prio3,
+                &(),
+                [
+                    vec![0.5, -0.25, 0.0],
+                    vec![-1.0, 0.0, 0.0],
+                    vec![0.5, 0.5, -0.5]
+                ]
+            )
+            .unwrap(),
+            Prio3ResultVec(vec![0.0, 0.25, -0.5])
+        );
+    }
+
     #[test]
     fn test_prio3_histogram() {
         let prio3 = Prio3Aes128Histogram::new(2, &[0, 10, 20]).unwrap();
@@ -1058,6 +2498,125 @@ mod tests {
         test_prepare_step_serialization(&prio3, &23).unwrap();
     }
 
+    #[test]
+    fn test_prio3_categorical_histogram() {
+        let prio3 =
+            Prio3Aes128CategoricalHistogram::new(2, &["chrome", "firefox", "safari"]).unwrap();
+        let chrome = prio3.bucket_index("chrome").unwrap();
+        let safari = prio3.bucket_index("safari").unwrap();
+        assert_matches!(
+            prio3.bucket_index("edge"),
+            Err(VdafError::InvalidMeasurement(_))
+        );
+
+        assert_eq!(
+            run_vdaf(&prio3, &(), [chrome, safari, chrome]).unwrap(),
+            Prio3ResultCategoricalHistogram(vec![
+                ("chrome".to_string(), 2),
+                ("firefox".to_string(), 0),
+                ("safari".to_string(), 1),
+            ])
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+
+        let mut input_shares = prio3.shard(&(), &safari).unwrap();
+        assert_matches!(input_shares[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        prio3.shard(&(), &3).unwrap_err();
+        Prio3Aes128CategoricalHistogram::new(2, &[]).unwrap_err();
+        Prio3Aes128CategoricalHistogram::new(2, &["a", "a"]).unwrap_err();
+
+        test_prepare_step_serialization(&prio3, &chrome).unwrap();
+    }
+
+    #[test]
+    fn test_vec_prio3_round_trip() {
+        // Instances with their own `Collector` implementation can generate test vectors too.
+        let prio3 = Prio3Aes128FixedPointBoundedL2VecSum::new(2, 16, 2).unwrap();
+        let t = generate_test_vector(&prio3, &(), &[vec![0.25, -0.5], vec![0.25, 0.0]]).unwrap();
+        let t = serde_json::from_str(&serde_json::to_string(&t).unwrap()).unwrap();
+        check_test_vector(&prio3, &t);
+
+        let prio3 = Prio3Aes128CategoricalHistogram::new(2, &["a", "b", "c"]).unwrap();
+        let t = generate_test_vector(&prio3, &(), &[0, 2, 2]).unwrap();
+        assert_eq!(t.agg_result, vec![1, 0, 2]);
+        let t = serde_json::from_str(&serde_json::to_string(&t).unwrap()).unwrap();
+        check_test_vector(&prio3, &t);
+    }
+
+    #[test]
+    fn test_prio3_cshake128() {
+        let prio3 = Prio3CShake128Count::new(2).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [1, 0, 0, 1, 1]).unwrap(),
+            Prio3Result(3)
+        );
+        test_prepare_step_serialization(&prio3, &1).unwrap();
+
+        let prio3 = Prio3CShake128Sum::new(3, 16).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [0, (1 << 16) - 1, 0, 1, 1]).unwrap(),
+            Prio3Result((1 << 16) + 1)
+        );
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+        let mut input_shares = prio3.shard(&(), &1).unwrap();
+        assert_matches!(input_shares[0].proof_share, Share::Leader(ref mut data) => {
+            data[0] += Field128::one();
+        });
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(result, Err(VdafError::Prepare(PrepareError::VerifyFailed)));
+
+        let prio3 = Prio3CShake128Histogram::new(2, &[0, 10, 20]).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [0, 10, 20, 9999]).unwrap(),
+            Prio3ResultVec(vec![1, 1, 1, 1])
+        );
+        test_prepare_step_serialization(&prio3, &23).unwrap();
+    }
+
+    #[test]
+    fn test_prio3_shake256() {
+        let prio3 = Prio3Shake256Count::new(2).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [1, 0, 0, 1, 1]).unwrap(),
+            Prio3Result(3)
+        );
+        test_serialization(&prio3, &1).unwrap();
+        test_serialization(&prio3.with_num_proofs(2).unwrap(), &1).unwrap();
+
+        let prio3 = Prio3Shake256Sum::new(3, 16).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [0, (1 << 16) - 1, 0, 1, 1]).unwrap(),
+            Prio3Result((1 << 16) + 1)
+        );
+        test_serialization(&prio3, &1).unwrap();
+
+        let (_, verify_params) = prio3.setup().unwrap();
+        let nonce = b"This is a good nonce.";
+        let mut input_shares = prio3.shard(&(), &1).unwrap();
+        input_shares[1].joint_rand_param.as_mut().unwrap().blind.0[31] ^= 255;
+        let result = run_vdaf_prepare(&prio3, &verify_params, &(), nonce, input_shares);
+        assert_matches!(
+            result,
+            Err(VdafError::Prepare(PrepareError::JointRandMismatch))
+        );
+
+        let prio3 = Prio3Shake256Histogram::new(2, &[0, 10, 20]).unwrap();
+        assert_eq!(
+            run_vdaf(&prio3, &(), [0, 10, 20, 9999]).unwrap(),
+            Prio3ResultVec(vec![1, 1, 1, 1])
+        );
+        test_serialization(&prio3, &23).unwrap();
+    }
+
     #[test]
     fn test_prio3_input_share() {
         let prio3 = Prio3Aes128Sum::new(5, 16).unwrap();
@@ -1096,7 +2655,8 @@ mod tests {
         }
     }
 
-    fn test_prepare_step_serialization<T, A, P, const L: usize>(
+    // Check that each message type round-trips through its encoding.
+    fn test_serialization<T, A, P, const L: usize>(
         prio3: &Prio3<T, A, P, L>,
         measurement: &T::Measurement,
     ) -> Result<(), VdafError>
@@ -1105,15 +2665,175 @@ mod tests {
         A: Clone + Debug + Sync + Send,
         P: Prg<L>,
     {
-        let (_, verify_param) = prio3.setup()?;
+        let (_, verify_params) = prio3.setup()?;
         let input_shares = prio3.shard(&(), measurement)?;
-        for (verify_param, input_share) in verify_param.iter().zip(input_shares.iter()) {
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            let got = Prio3VerifyParam::get_decoded_with_param(prio3, &verify_param.get_encoded())
+                .expect("failed to decode verify param");
+            assert_eq!(&got, verify_param);
+
+            let encoded = input_share.get_encoded();
+            let got = Prio3InputShare::get_decoded_with_param(verify_param, &encoded)
+                .expect("failed to decode input share");
+            assert_eq!(&got, input_share);
+
             let step = prio3.prepare_init(verify_param, &(), &[], input_share)?;
-            let want = assert_matches!(prio3.prepare_step(step, None), PrepareTransition::Continue(step, _) => step);
-            let got = Prio3PrepareStep::get_decoded_with_param(verify_param, &want.get_encoded())
-                .expect("failed to decode prepare step");
+            let (step, msg) = assert_matches!(
+                prio3.prepare_step(step, None),
+                PrepareTransition::Continue(step, msg) => (step, msg)
+            );
+            let got = Prio3PrepareMessage::get_decoded_with_param(&step, &msg.get_encoded())
+                .expect("failed to decode prepare message");
+            assert_eq!(got, msg);
+        }
+
+        test_prepare_step_serialization(prio3, measurement)
+    }
+
+    // Runs preparation of the measurements as a batch, with the input share of the last report
+    // tampered with. Checks that the batch API agrees with preparing each report on its own, that
+    // only the tampered report is rejected, and that the remaining reports aggregate to `want`.
+    fn test_prepare_batch<T, A, P, const L: usize>(
+        prio3: &Prio3<T, A, P, L>,
+        measurements: &[T::Measurement],
+        want: A,
+    ) -> Result<(), VdafError>
+    where
+        T: Type,
+        A: Clone
+            + Debug
+            + Sync
+            + Send
+            + PartialEq
+            + TryFrom<AggregateShare<T::Field>, Error = VdafError>,
+        P: Prg<L>,
+    {
+        let (public_param, verify_params) = prio3.setup()?;
+        let mut reports = Vec::with_capacity(measurements.len() + 1);
+        for (i, measurement) in measurements.iter().enumerate() {
+            let nonce = (i as u64).to_le_bytes();
+            reports.push((nonce, prio3.shard(&public_param, measurement)?));
+        }
+        let mut tampered = reports[0].1.clone();
+        assert_matches!(tampered[0].input_share, Share::Leader(ref mut data) => {
+            data[0] += T::Field::one();
+        });
+        reports.push((u64::MAX.to_le_bytes(), tampered));
+
+        // Each aggregator begins preparing the batch.
+        let mut steps = vec![Vec::new(); reports.len()];
+        let mut msgs = vec![Vec::new(); reports.len()];
+        for (j, verify_param) in verify_params.iter().enumerate() {
+            let batch = reports
+                .iter()
+                .map(|(nonce, input_shares)| (*nonce, input_shares[j].clone()))
+                .collect::<Vec<_>>();
+            let transitions = prio3.prepare_init_batch(verify_param, &(), &batch);
+            assert_eq!(transitions.len(), batch.len());
+            for (i, transition) in transitions.into_iter().enumerate() {
+                let (nonce, input_share) = &batch[i];
+                let step = prio3.prepare_init(verify_param, &(), nonce, input_share)?;
+                let (want_step, want_msg) = assert_matches!(
+                    prio3.prepare_step(step, None),
+                    PrepareTransition::Continue(step, msg) => (step, msg)
+                );
+                let (step, msg) = assert_matches!(
+                    transition,
+                    PrepareTransition::Continue(step, msg) => (step, msg)
+                );
+                assert_eq!(step, want_step);
+                assert_eq!(msg, want_msg);
+                steps[i].push(step);
+                msgs[i].push(msg);
+            }
+        }
+
+        // Each aggregator finishes preparing the batch.
+        let inbound = msgs
+            .into_iter()
+            .map(|msgs| prio3.prepare_preprocess(msgs))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut agg_shares = Vec::with_capacity(verify_params.len());
+        for j in 0..verify_params.len() {
+            let transitions = prio3.prepare_step_batch(
+                steps
+                    .iter()
+                    .zip(inbound.iter())
+                    .map(|(steps, msg)| (steps[j].clone(), Some(msg.clone()))),
+            );
+            assert_eq!(transitions.len(), reports.len());
+            let mut out_shares = Vec::with_capacity(measurements.len());
+            for (i, transition) in transitions.into_iter().enumerate() {
+                if i < measurements.len() {
+                    out_shares.push(assert_matches!(
+                        transition,
+                        PrepareTransition::Finish(out_share) => out_share
+                    ));
+                } else {
+                    assert_matches!(transition, PrepareTransition::Fail(_));
+                }
+            }
+            agg_shares.push(prio3.aggregate(&(), out_shares)?);
+        }
+
+        assert_eq!(prio3.unshard(&(), agg_shares)?, want);
+        Ok(())
+    }
+
+    fn test_prepare_step_serialization<T, A, P, const L: usize>(
+        prio3: &Prio3<T, A, P, L>,
+        measurement: &T::Measurement,
+    ) -> Result<(), VdafError>
+    where
+        T: Type,
+        A: Clone + Debug + Sync + Send,
+        P: Prg<L>,
+    {
+        let (_, verify_params) = prio3.setup()?;
+        let input_shares = prio3.shard(&(), measurement)?;
+        let mut steps = Vec::with_capacity(verify_params.len());
+        let mut msgs = Vec::with_capacity(verify_params.len());
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            // The step round-trips through its encoding in the "ready" state...
+            let want = prio3.prepare_init(verify_param, &(), &[], input_share)?;
+            let got = Prio3PrepareStep::get_decoded_with_param(
+                &(prio3, verify_param),
+                &want.get_encoded(),
+            )
+            .expect("failed to decode prepare step");
             assert_eq!(got, want);
+
+            // ... and in the "waiting" state.
+            let (want, msg) = assert_matches!(prio3.prepare_step(got, None), PrepareTransition::Continue(step, msg) => (step, msg));
+            let got = Prio3PrepareStep::get_decoded_with_param(
+                &(prio3, verify_param),
+                &want.get_encoded(),
+            )
+            .expect("failed to decode prepare step");
+            assert_eq!(got, want);
+
+            steps.push(got);
+            msgs.push(msg);
         }
+
+        // The decoded steps can be used to finish preparation.
+        let msg = prio3.prepare_preprocess(msgs)?;
+        for step in steps {
+            assert_matches!(
+                prio3.prepare_step(step, Some(msg.clone())),
+                PrepareTransition::Finish(_)
+            );
+        }
+
+        // Unknown states are rejected.
+        let mut encoded = prio3
+            .prepare_init(&verify_params[0], &(), &[], &input_shares[0])?
+            .get_encoded();
+        encoded[0] = 2;
+        assert_matches!(
+            Prio3PrepareStep::get_decoded_with_param(&(prio3, &verify_params[0]), &encoded),
+            Err(CodecError::UnexpectedValue)
+        );
         Ok(())
     }
 }
@@ -0,0 +1,496 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Checks that Prio3 rejects input shares and prepare messages produced by a malicious Client or
+//! tampered with in transit. Each mutation is expected to make preparation fail with a specific
+//! [`Rejection`]; none of them may cause a panic.
+
+use super::*;
+use crate::vdaf::prg::Seed;
+use assert_matches::assert_matches;
+
+/// The reason for which a mutated report is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rejection {
+    /// The FLP verifier rejected the proof. Reported as [`PrepareError::VerifyFailed`].
+    VerifyFailed,
+
+    /// The Aggregators did not derive the same joint randomness, or the parameters needed to
+    /// derive it were missing. Reported as [`PrepareError::JointRandMismatch`].
+    JointRandMismatch,
+
+    /// A share or message has the wrong length. Reported as [`PrepareError::LengthMismatch`].
+    LengthMismatch,
+}
+
+impl Rejection {
+    fn matches(&self, err: &VdafError) -> bool {
+        matches!(
+            (self, err),
+            (
+                Rejection::VerifyFailed,
+                VdafError::Prepare(PrepareError::VerifyFailed)
+            ) | (
+                Rejection::JointRandMismatch,
+                VdafError::Prepare(PrepareError::JointRandMismatch)
+            ) | (
+                Rejection::LengthMismatch,
+                VdafError::Prepare(PrepareError::LengthMismatch { .. })
+            )
+        )
+    }
+}
+
+/// The rejection expected from each Aggregator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Expected {
+    /// Each Aggregator rejects the report for the same reason.
+    All(Rejection),
+
+    /// One Aggregator rejects the report for a different reason than the others. For example, if
+    /// an Aggregator's share of the joint randomness seed is tampered with, then that Aggregator
+    /// derives the same joint randomness as the combined prepare message but fails to verify the
+    /// proof, while the others detect a joint randomness mismatch.
+    Split {
+        aggregator: usize,
+        rejection: Rejection,
+        others: Rejection,
+    },
+}
+
+impl Expected {
+    fn rejection(&self, aggregator: usize) -> Rejection {
+        match *self {
+            Expected::All(rejection) => rejection,
+            Expected::Split {
+                aggregator: tampered,
+                rejection,
+                others,
+            } => {
+                if aggregator == tampered {
+                    rejection
+                } else {
+                    others
+                }
+            }
+        }
+    }
+}
+
+// Rejections expected when the share of `aggregator` used to derive the joint randomness is
+// tampered with.
+fn tampered_joint_rand_share(has_joint_rand: bool, aggregator: usize) -> Expected {
+    if has_joint_rand {
+        Expected::Split {
+            aggregator,
+            rejection: Rejection::VerifyFailed,
+            others: Rejection::JointRandMismatch,
+        }
+    } else {
+        Expected::All(Rejection::VerifyFailed)
+    }
+}
+
+/// A mutation of the input shares of a report.
+type InputShareMutation<F, const L: usize> =
+    fn(&mut [Prio3InputShare<F, L>], &[Prio3InputShare<F, L>]);
+
+/// A mutation of the combined prepare message passed to [`Aggregator::prepare_step`].
+type PrepareMessageMutation<F, const L: usize> = fn(&mut Prio3PrepareMessage<F, L>);
+
+fn flip_seed_bit<const L: usize>(share: &mut Share<impl FieldElement, L>) {
+    assert_matches!(share, Share::Helper(Seed(ref mut seed)) => seed[0] ^= 1);
+}
+
+fn leader_data<F: FieldElement, const L: usize>(share: &mut Share<F, L>) -> &mut Vec<F> {
+    assert_matches!(share, Share::Leader(ref mut data) => data)
+}
+
+// Returns the mutations of the input shares applicable to `prio3`. The second argument of each
+// mutation is the input shares of another report, for a different measurement.
+fn input_share_mutations<T, A, P, const L: usize>(
+    prio3: &Prio3<T, A, P, L>,
+) -> Vec<(&'static str, InputShareMutation<T::Field, L>, Expected)>
+where
+    T: Type,
+    A: Clone + Debug,
+    P: Prg<L>,
+{
+    let has_joint_rand = prio3.typ.joint_rand_len() > 0;
+
+    let mut mutations: Vec<(&'static str, InputShareMutation<T::Field, L>, Expected)> = vec![
+        (
+            "flipped bit in helper input share seed",
+            |shares, _| flip_seed_bit(&mut shares[1].input_share),
+            tampered_joint_rand_share(has_joint_rand, 1),
+        ),
+        (
+            "flipped bit in helper proof share seed",
+            |shares, _| flip_seed_bit(&mut shares[1].proof_share),
+            Expected::All(Rejection::VerifyFailed),
+        ),
+        (
+            "tampered leader input share",
+            |shares, _| leader_data(&mut shares[0].input_share)[0] += T::Field::one(),
+            tampered_joint_rand_share(has_joint_rand, 0),
+        ),
+        (
+            "leader input share too long",
+            |shares, _| leader_data(&mut shares[0].input_share).push(T::Field::zero()),
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "leader input share too short",
+            |shares, _| {
+                leader_data(&mut shares[0].input_share).pop();
+            },
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "leader proof share too long",
+            |shares, _| leader_data(&mut shares[0].proof_share).push(T::Field::zero()),
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "leader proof share too short",
+            |shares, _| {
+                leader_data(&mut shares[0].proof_share).pop();
+            },
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "empty leader proof share",
+            |shares, _| leader_data(&mut shares[0].proof_share).clear(),
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "proof shares for a different measurement",
+            |shares, other| {
+                for (share, other) in shares.iter_mut().zip(other.iter()) {
+                    share.proof_share = other.proof_share.clone();
+                }
+            },
+            Expected::All(Rejection::VerifyFailed),
+        ),
+    ];
+
+    if has_joint_rand {
+        mutations.extend([
+            (
+                "joint randomness seed hint swapped with another report's",
+                (|shares, other| {
+                    for (share, other) in shares.iter_mut().zip(other.iter()) {
+                        share.joint_rand_param.as_mut().unwrap().seed_hint =
+                            other.joint_rand_param.as_ref().unwrap().seed_hint.clone();
+                    }
+                }) as InputShareMutation<T::Field, L>,
+                Expected::All(Rejection::JointRandMismatch),
+            ),
+            (
+                "joint randomness seed hints swapped between aggregators",
+                |shares, _| {
+                    let hint = shares[0]
+                        .joint_rand_param
+                        .as_ref()
+                        .unwrap()
+                        .seed_hint
+                        .clone();
+                    shares[0].joint_rand_param.as_mut().unwrap().seed_hint = shares[1]
+                        .joint_rand_param
+                        .as_ref()
+                        .unwrap()
+                        .seed_hint
+                        .clone();
+                    shares[1].joint_rand_param.as_mut().unwrap().seed_hint = hint;
+                },
+                // With more than two Aggregators, the others derive the correct joint randomness,
+                // but the verifier shares of the first two were computed with the wrong one.
+                Expected::Split {
+                    aggregator: 2,
+                    rejection: Rejection::VerifyFailed,
+                    others: Rejection::JointRandMismatch,
+                },
+            ),
+            (
+                "flipped bit in helper joint randomness blind",
+                |shares, _| shares[1].joint_rand_param.as_mut().unwrap().blind.0[0] ^= 1,
+                tampered_joint_rand_share(has_joint_rand, 1),
+            ),
+            (
+                "missing joint randomness parameter",
+                |shares, _| shares[1].joint_rand_param = None,
+                Expected::All(Rejection::JointRandMismatch),
+            ),
+        ]);
+    } else {
+        mutations.push((
+            "unexpected joint randomness parameter",
+            |shares, _| {
+                shares[0].joint_rand_param = Some(JointRandParam {
+                    seed_hint: Seed::uninitialized(),
+                    blind: Seed::uninitialized(),
+                })
+            },
+            Expected::All(Rejection::JointRandMismatch),
+        ));
+    }
+
+    mutations
+}
+
+// Returns the mutations of the combined prepare message applicable to `prio3`.
+fn prepare_message_mutations<T, A, P, const L: usize>(
+    prio3: &Prio3<T, A, P, L>,
+) -> Vec<(&'static str, PrepareMessageMutation<T::Field, L>, Expected)>
+where
+    T: Type,
+    A: Clone + Debug,
+    P: Prg<L>,
+{
+    let mut mutations: Vec<(&'static str, PrepareMessageMutation<T::Field, L>, Expected)> = vec![
+        (
+            "tampered verifier",
+            |msg| msg.verifier[0] += T::Field::one(),
+            Expected::All(Rejection::VerifyFailed),
+        ),
+        (
+            "verifier too long",
+            |msg| msg.verifier.push(T::Field::zero()),
+            Expected::All(Rejection::LengthMismatch),
+        ),
+        (
+            "verifier too short",
+            |msg| {
+                msg.verifier.pop();
+            },
+            Expected::All(Rejection::LengthMismatch),
+        ),
+    ];
+
+    if prio3.typ.joint_rand_len() > 0 {
+        mutations.extend([
+            (
+                "flipped bit in joint randomness seed",
+                (|msg| msg.joint_rand_seed.as_mut().unwrap().0[0] ^= 1)
+                    as PrepareMessageMutation<T::Field, L>,
+                Expected::All(Rejection::JointRandMismatch),
+            ),
+            (
+                "missing joint randomness seed",
+                |msg| msg.joint_rand_seed = None,
+                Expected::All(Rejection::JointRandMismatch),
+            ),
+        ]);
+    }
+
+    mutations
+}
+
+// Runs preparation of a report, mutating the combined prepare message with
+// `mutate_prepare_message` if present. Returns the ID and error of each Aggregator that failed, if
+// any, and panics if some but not all of the Aggregators recover an output share. The prepare
+// messages are combined by the Leader.
+fn run_prepare<T, A, P, const L: usize>(
+    prio3: &Prio3<T, A, P, L>,
+    verify_params: &[Prio3VerifyParam<L>],
+    input_shares: &[Prio3InputShare<T::Field, L>],
+    mutate_prepare_message: Option<PrepareMessageMutation<T::Field, L>>,
+) -> Result<(), Vec<(usize, VdafError)>>
+where
+    T: Type,
+    A: Clone + Debug + Sync + Send,
+    P: Prg<L>,
+{
+    let nonce = b"this is a nonce";
+    let mut errors = Vec::new();
+    let mut steps = Vec::new();
+    let mut msgs = Vec::new();
+    for (aggregator, (verify_param, input_share)) in
+        verify_params.iter().zip(input_shares.iter()).enumerate()
+    {
+        let mut transitions =
+            prio3.prepare_init_batch(verify_param, &(), &[(nonce, input_share.clone())]);
+        match transitions.pop().unwrap() {
+            PrepareTransition::Continue(step, msg) => {
+                steps.push(step);
+                msgs.push(msg);
+            }
+            PrepareTransition::Finish(_) => panic!("finished without exchanging messages"),
+            PrepareTransition::Fail(err) => errors.push((aggregator, err)),
+        }
+    }
+
+    // An Aggregator that fails to begin preparation does not send a prepare message, so the
+    // remaining Aggregators cannot finish either.
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut msg = match prio3.prepare_preprocess(msgs) {
+        Ok(msg) => msg,
+        Err(err) => return Err(vec![(0, err)]),
+    };
+    if let Some(mutate) = mutate_prepare_message {
+        mutate(&mut msg);
+    }
+
+    let num_aggregators = steps.len();
+    for (aggregator, step) in steps.into_iter().enumerate() {
+        match prio3.prepare_step(step, Some(msg.clone())) {
+            PrepareTransition::Continue(..) => panic!("continued after the last round"),
+            PrepareTransition::Finish(_) => (),
+            PrepareTransition::Fail(err) => errors.push((aggregator, err)),
+        }
+    }
+    match errors.len() {
+        0 => Ok(()),
+        n if n == num_aggregators => Err(errors),
+        _ => panic!("aggregators disagree on the validity of the report"),
+    }
+}
+
+// Checks that each mutation of a report for `measurement` is rejected for the expected reason.
+// `other_measurement` must differ from `measurement`.
+fn check_robustness<T, A, P, const L: usize>(
+    prio3: &Prio3<T, A, P, L>,
+    measurement: &T::Measurement,
+    other_measurement: &T::Measurement,
+) where
+    T: Type,
+    A: Clone + Debug + Sync + Send,
+    P: Prg<L>,
+{
+    let (public_param, verify_params) = prio3.setup().unwrap();
+    let input_shares = prio3.shard(&public_param, measurement).unwrap();
+    let other_input_shares = prio3.shard(&public_param, other_measurement).unwrap();
+
+    // The unmodified report is accepted.
+    run_prepare(prio3, &verify_params, &input_shares, None).unwrap();
+
+    for (name, mutate, want) in input_share_mutations(prio3) {
+        let mut mutated = input_shares.clone();
+        mutate(&mut mutated, &other_input_shares);
+        let errors = run_prepare(prio3, &verify_params, &mutated, None)
+            .err()
+            .unwrap_or_else(|| panic!("{}: not rejected", name));
+        for (aggregator, err) in errors.iter() {
+            let want = want.rejection(*aggregator);
+            assert!(
+                want.matches(err),
+                "{}: aggregator {} got {:?}; want {:?}",
+                name,
+                aggregator,
+                err,
+                want
+            );
+        }
+    }
+
+    for (name, mutate, want) in prepare_message_mutations(prio3) {
+        let errors = run_prepare(prio3, &verify_params, &input_shares, Some(mutate))
+            .err()
+            .unwrap_or_else(|| panic!("{}: not rejected", name));
+        assert_eq!(errors.len(), verify_params.len(), "{}", name);
+        for (aggregator, err) in errors.iter() {
+            let want = want.rejection(*aggregator);
+            assert!(
+                want.matches(err),
+                "{}: aggregator {} got {:?}; want {:?}",
+                name,
+                aggregator,
+                err,
+                want
+            );
+        }
+    }
+}
+
+#[test]
+fn test_robustness_count() {
+    check_robustness(&Prio3Aes128Count::new(2).unwrap(), &1, &0);
+    check_robustness(&Prio3CShake128Count::new(3).unwrap(), &0, &1);
+    check_robustness(&Prio3Shake256Count::new(2).unwrap(), &1, &0);
+}
+
+#[test]
+fn test_robustness_count_vec() {
+    check_robustness(
+        &Prio3Aes128CountVec::new(2, 4).unwrap(),
+        &vec![1, 0, 1, 1],
+        &vec![0, 1, 0, 0],
+    );
+    check_robustness(
+        &Prio3Aes128CountVecWithWeight::new(2, 4, 2).unwrap(),
+        &vec![1, 0, 1, 0],
+        &vec![0, 1, 0, 1],
+    );
+}
+
+#[test]
+#[cfg(feature = "multithreaded")]
+fn test_robustness_multithreaded() {
+    check_robustness(
+        &Prio3Aes128CountVecMultithreaded::new(2, 4).unwrap(),
+        &vec![1, 0, 1, 1],
+        &vec![0, 1, 0, 0],
+    );
+    check_robustness(
+        &Prio3Aes128CountVecWithWeightMultithreaded::new(2, 4, 2).unwrap(),
+        &vec![1, 0, 1, 0],
+        &vec![0, 1, 0, 1],
+    );
+    check_robustness(
+        &Prio3Aes128SumVecMultithreaded::new(2, 4, 3).unwrap(),
+        &vec![1, 15, 7],
+        &vec![0, 2, 3],
+    );
+    check_robustness(
+        &Prio3Aes128FixedPointBoundedL2VecSumMultithreaded::new(2, 16, 3).unwrap(),
+        &vec![0.5, -0.25, 0.0],
+        &vec![0.0, 0.5, 0.5],
+    );
+}
+
+#[test]
+fn test_robustness_sum() {
+    check_robustness(&Prio3Aes128Sum::new(2, 8).unwrap(), &99, &100);
+    check_robustness(&Prio3CShake128Sum::new(2, 8).unwrap(), &255, &0);
+    check_robustness(&Prio3Shake256Sum::new(3, 8).unwrap(), &1, &2);
+    check_robustness(&Prio3Aes128BoundedSum::new(2, 10, 20).unwrap(), &15, &10);
+    check_robustness(&Prio3Aes128SignedSum::new(2, 8).unwrap(), &-100, &100);
+    check_robustness(&Prio3Aes128MeanVariance::new(2, 8).unwrap(), &3, &4);
+}
+
+#[test]
+fn test_robustness_sum_vec() {
+    check_robustness(
+        &Prio3Aes128SumVec::new(2, 4, 3).unwrap(),
+        &vec![1, 15, 7],
+        &vec![0, 2, 3],
+    );
+    check_robustness(
+        &Prio3Aes128FixedPointBoundedL2VecSum::new(2, 16, 3).unwrap(),
+        &vec![0.5, -0.25, 0.0],
+        &vec![0.0, 0.5, 0.5],
+    );
+}
+
+#[test]
+fn test_robustness_histogram() {
+    check_robustness(
+        &Prio3Aes128Histogram::new(2, &[1, 10, 100]).unwrap(),
+        &50,
+        &5,
+    );
+    check_robustness(
+        &Prio3CShake128Histogram::new(2, &[1, 10, 100]).unwrap(),
+        &0,
+        &500,
+    );
+    check_robustness(
+        &Prio3Shake256Histogram::new(3, &[1, 10, 100]).unwrap(),
+        &10,
+        &11,
+    );
+    let prio3 = Prio3Aes128CategoricalHistogram::new(2, &["chrome", "firefox", "safari"]).unwrap();
+    check_robustness(&prio3, &0, &2);
+}
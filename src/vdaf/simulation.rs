@@ -0,0 +1,537 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Module `simulation` runs every phase of a VDAF in-process, with any number of Clients and the
+//! number of Aggregators of the VDAF. Reports may be dropped in transit or tampered with, either
+//! before they reach an Aggregator or during the Prepare process. The simulation records how long
+//! each phase took and how many bytes were exchanged, which is useful for capacity planning, and
+//! the aggregate result can be checked against the aggregate of the plaintext measurements.
+//!
+//! The Prepare process of each report is run by [`run_prepare`], which can also be used on its own
+//! to drive the Aggregators of a single report.
+//!
+//! ```
+//! use prio::vdaf::{
+//!     prio3::{Prio3Aes128Count, Prio3Result},
+//!     simulation::{Fault, Simulation},
+//! };
+//!
+//! let vdaf = Prio3Aes128Count::new(3).unwrap();
+//! let report = Simulation::new(vdaf, ())
+//!     .with_reports([1, 0, 1, 1])
+//!     .with_faulty_report(1, Fault::Drop)
+//!     .with_faulty_report(
+//!         1,
+//!         Fault::TamperInputShare {
+//!             aggregator: 2,
+//!             byte: 0,
+//!         },
+//!     )
+//!     .run()
+//!     .unwrap();
+//!
+//! assert_eq!(report.accepted, vec![0, 1, 2, 3]);
+//! assert_eq!(report.dropped, vec![4]);
+//! assert_eq!(report.rejected.len(), 1);
+//! report
+//!     .check_aggregate(|measurements| Prio3Result(measurements.into_iter().sum()))
+//!     .unwrap();
+//! ```
+
+use crate::codec::{Encode, ParameterizedDecode};
+use crate::vdaf::{run_prepare, Aggregator, Client, Collector, VdafError};
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// A fault injected into a report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The report is lost before it reaches the Aggregators.
+    Drop,
+
+    /// Byte `byte` of the encoded input share sent to Aggregator `aggregator` is flipped. The byte
+    /// index is reduced modulo the length of the input share.
+    TamperInputShare {
+        /// The index of the Aggregator.
+        aggregator: usize,
+
+        /// The index of the byte to flip.
+        byte: usize,
+    },
+
+    /// Byte `byte` of the encoded prepare message sent by Aggregator `aggregator` in round
+    /// `round` is flipped. The byte index is reduced modulo the length of the message.
+    TamperPrepareMessage {
+        /// The index of the Aggregator.
+        aggregator: usize,
+
+        /// The round of the Prepare process, starting from 0.
+        round: usize,
+
+        /// The index of the byte to flip.
+        byte: usize,
+    },
+}
+
+/// The time spent in each phase of a simulation, summed over all Clients and Aggregators.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Time spent generating the parameters of the VDAF.
+    pub setup: Duration,
+
+    /// Time spent by the Clients sharding their measurements.
+    pub shard: Duration,
+
+    /// Time spent by the Aggregators decoding input shares and preparing them.
+    pub prepare: Duration,
+
+    /// Time spent by the Aggregators aggregating output shares.
+    pub aggregate: Duration,
+
+    /// Time spent by the Collector unsharding the aggregate shares.
+    pub unshard: Duration,
+}
+
+/// The number of bytes exchanged in a simulation, summed over all reports that were not dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessageSizes {
+    /// Bytes of input shares sent by the Clients to the Aggregators.
+    pub input_shares: usize,
+
+    /// Bytes of prepare messages broadcast by the Aggregators.
+    pub prepare_messages: usize,
+
+    /// Bytes of aggregate shares sent by the Aggregators to the Collector.
+    pub aggregate_shares: usize,
+}
+
+/// A simulated deployment of a VDAF. Reports are identified by the order in which they are added.
+#[derive(Clone, Debug)]
+pub struct Simulation<V: Client + Aggregator + Collector>
+where
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    vdaf: V,
+    agg_param: V::AggregationParam,
+    reports: Vec<(V::Measurement, Option<Fault>)>,
+}
+
+impl<V: Client + Aggregator + Collector> Simulation<V>
+where
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    /// Constructs a simulation of `vdaf` with aggregation parameter `agg_param` and no reports.
+    pub fn new(vdaf: V, agg_param: V::AggregationParam) -> Self {
+        Self {
+            vdaf,
+            agg_param,
+            reports: Vec::new(),
+        }
+    }
+
+    /// Adds an honest report for each measurement.
+    pub fn with_reports<M: IntoIterator<Item = V::Measurement>>(mut self, measurements: M) -> Self {
+        self.reports.extend(
+            measurements
+                .into_iter()
+                .map(|measurement| (measurement, None)),
+        );
+        self
+    }
+
+    /// Adds a report for `measurement` into which `fault` is injected.
+    pub fn with_faulty_report(mut self, measurement: V::Measurement, fault: Fault) -> Self {
+        self.reports.push((measurement, Some(fault)));
+        self
+    }
+
+    /// Runs the simulation. An error is returned if a phase other than preparation fails, or if a
+    /// fault refers to an Aggregator that does not exist. Reports that fail preparation are
+    /// recorded in [`SimulationReport::rejected`].
+    pub fn run(&self) -> Result<SimulationReport<V>, VdafError> {
+        let num_aggregators = self.vdaf.num_aggregators();
+        for (_, fault) in self.reports.iter() {
+            match fault {
+                Some(Fault::TamperInputShare { aggregator, .. })
+                | Some(Fault::TamperPrepareMessage { aggregator, .. })
+                    if *aggregator >= num_aggregators =>
+                {
+                    return Err(VdafError::InvalidParameter(format!(
+                        "fault refers to aggregator {}, but there are {} aggregators",
+                        aggregator, num_aggregators
+                    )));
+                }
+                _ => (),
+            }
+        }
+
+        let mut timings = PhaseTimings::default();
+        let mut sizes = MessageSizes::default();
+
+        let start = Instant::now();
+        let (public_param, verify_params) = self.vdaf.setup()?;
+        timings.setup = start.elapsed();
+
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        let mut dropped = Vec::new();
+        let mut out_shares = vec![Vec::new(); num_aggregators];
+        for (index, (measurement, fault)) in self.reports.iter().enumerate() {
+            let start = Instant::now();
+            let input_shares = self.vdaf.shard(&public_param, measurement)?;
+            timings.shard += start.elapsed();
+
+            if let Some(Fault::Drop) = fault {
+                dropped.push(index);
+                continue;
+            }
+
+            let mut encoded_input_shares = input_shares
+                .iter()
+                .map(|input_share| input_share.get_encoded())
+                .collect::<Vec<_>>();
+            sizes.input_shares += encoded_input_shares.iter().map(Vec::len).sum::<usize>();
+            if let Some(Fault::TamperInputShare { aggregator, byte }) = fault {
+                flip_byte(&mut encoded_input_shares[*aggregator], *byte);
+            }
+
+            // NOTE The nonce is derived from the index of the report. In use, the Aggregators MUST
+            // ensure that nonces are unique for each report.
+            let nonce = (index as u64).to_be_bytes();
+            let start = Instant::now();
+            let result = self.prepare(
+                &verify_params,
+                &nonce,
+                &encoded_input_shares,
+                fault.as_ref(),
+                &mut sizes,
+            );
+            timings.prepare += start.elapsed();
+
+            match result {
+                Ok(report_out_shares) => {
+                    for (out_share, out) in report_out_shares.into_iter().zip(out_shares.iter_mut())
+                    {
+                        out.push(out_share);
+                    }
+                    accepted.push(index);
+                }
+                Err(err) => rejected.push((index, err)),
+            }
+        }
+
+        let start = Instant::now();
+        let agg_shares = out_shares
+            .into_iter()
+            .map(|out| self.vdaf.aggregate(&self.agg_param, out))
+            .collect::<Result<Vec<_>, _>>()?;
+        timings.aggregate = start.elapsed();
+        sizes.aggregate_shares = agg_shares
+            .iter()
+            .map(|agg_share| Into::<Vec<u8>>::into(agg_share).len())
+            .sum();
+
+        let start = Instant::now();
+        let aggregate_result = self.vdaf.unshard(&self.agg_param, agg_shares)?;
+        timings.unshard = start.elapsed();
+
+        Ok(SimulationReport {
+            aggregate_result,
+            measurements: self
+                .reports
+                .iter()
+                .map(|(measurement, _)| measurement.clone())
+                .collect(),
+            accepted,
+            rejected,
+            dropped,
+            timings,
+            sizes,
+        })
+    }
+
+    // Runs the Prepare process on the encoded input shares of a report and returns the output
+    // share of each Aggregator.
+    fn prepare(
+        &self,
+        verify_params: &[V::VerifyParam],
+        nonce: &[u8],
+        encoded_input_shares: &[Vec<u8>],
+        fault: Option<&Fault>,
+        sizes: &mut MessageSizes,
+    ) -> Result<Vec<V::OutputShare>, VdafError> {
+        let mut states = Vec::with_capacity(verify_params.len());
+        for (verify_param, encoded) in verify_params.iter().zip(encoded_input_shares.iter()) {
+            let input_share = V::InputShare::get_decoded_with_param(verify_param, encoded)?;
+            states.push(self.vdaf.prepare_init(
+                verify_param,
+                &self.agg_param,
+                nonce,
+                &input_share,
+            )?);
+        }
+
+        run_prepare(&self.vdaf, states, |round, aggregator, state, msg| {
+            let mut encoded = msg.get_encoded();
+            sizes.prepare_messages += encoded.len();
+            if let Some(Fault::TamperPrepareMessage {
+                aggregator: faulty,
+                round: faulty_round,
+                byte,
+            }) = fault
+            {
+                if *faulty == aggregator && *faulty_round == round {
+                    flip_byte(&mut encoded, *byte);
+                }
+            }
+            Ok(V::PrepareMessage::get_decoded_with_param(state, &encoded)?)
+        })
+    }
+}
+
+// Flips each bit of the byte at position `byte`, modulo the length of `data`.
+fn flip_byte(data: &mut [u8], byte: usize) {
+    if !data.is_empty() {
+        let len = data.len();
+        data[byte % len] ^= 0xff;
+    }
+}
+
+/// The outcome of a [`Simulation`].
+#[derive(Debug)]
+pub struct SimulationReport<V: Client + Aggregator + Collector>
+where
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    /// The aggregate result computed by the Collector.
+    pub aggregate_result: V::AggregateResult,
+
+    /// The measurement of each report.
+    pub measurements: Vec<V::Measurement>,
+
+    /// The indices of the reports that were aggregated.
+    pub accepted: Vec<usize>,
+
+    /// The indices of the reports that the Aggregators rejected, and the reason for rejecting
+    /// each of them.
+    pub rejected: Vec<(usize, VdafError)>,
+
+    /// The indices of the reports that were dropped before reaching the Aggregators.
+    pub dropped: Vec<usize>,
+
+    /// The time spent in each phase.
+    pub timings: PhaseTimings,
+
+    /// The number of bytes exchanged.
+    pub sizes: MessageSizes,
+}
+
+impl<V: Client + Aggregator + Collector> SimulationReport<V>
+where
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    V::AggregateResult: PartialEq,
+{
+    /// Checks that the aggregate result is equal to `plaintext` applied to the measurements of
+    /// the accepted reports.
+    pub fn check_aggregate<F>(
+        &self,
+        plaintext: F,
+    ) -> Result<(), AggregateMismatch<V::AggregateResult>>
+    where
+        F: FnOnce(Vec<V::Measurement>) -> V::AggregateResult,
+    {
+        let want = plaintext(
+            self.accepted
+                .iter()
+                .map(|index| self.measurements[*index].clone())
+                .collect(),
+        );
+        if self.aggregate_result != want {
+            return Err(AggregateMismatch {
+                got: self.aggregate_result.clone(),
+                want,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by [`SimulationReport::check_aggregate`] if the aggregate result differs
+/// from the aggregate of the plaintext measurements.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("aggregate result mismatch: got {got:?}; want {want:?}")]
+pub struct AggregateMismatch<A: Debug> {
+    /// The aggregate result computed by the Collector.
+    pub got: A,
+
+    /// The aggregate of the plaintext measurements.
+    pub want: A,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{Field128, Field64};
+    use crate::vdaf::poplar1::{IdpfInput, Poplar1, TreeIdpf};
+    use crate::vdaf::prg::PrgAes128;
+    use crate::vdaf::prio3::{
+        Prio3Aes128Count, Prio3Aes128Histogram, Prio3Aes128Sum, Prio3Result, Prio3ResultVec,
+    };
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn test_simulation_prio3() {
+        for num_aggregators in 2..=5 {
+            let vdaf = Prio3Aes128Sum::new(num_aggregators, 16).unwrap();
+            let report = Simulation::new(vdaf, ())
+                .with_reports(0..100)
+                .run()
+                .unwrap();
+            assert_eq!(report.accepted, (0..100).collect::<Vec<_>>());
+            assert!(report.rejected.is_empty());
+            assert!(report.dropped.is_empty());
+            assert_eq!(report.aggregate_result, Prio3Result(4950));
+            report
+                .check_aggregate(|measurements| {
+                    Prio3Result(measurements.into_iter().sum::<u128>() as u64)
+                })
+                .unwrap();
+            assert!(report.sizes.input_shares > 0);
+            assert!(report.sizes.prepare_messages > 0);
+            assert!(report.sizes.aggregate_shares > 0);
+        }
+    }
+
+    #[test]
+    fn test_simulation_faults() {
+        let vdaf = Prio3Aes128Histogram::new(3, &[10, 20]).unwrap();
+        let report = Simulation::new(vdaf, ())
+            .with_reports([5, 15, 25])
+            .with_faulty_report(5, Fault::Drop)
+            .with_faulty_report(
+                15,
+                Fault::TamperInputShare {
+                    aggregator: 0,
+                    byte: 3,
+                },
+            )
+            .with_faulty_report(
+                15,
+                Fault::TamperInputShare {
+                    aggregator: 2,
+                    byte: 0,
+                },
+            )
+            .with_faulty_report(
+                25,
+                Fault::TamperPrepareMessage {
+                    aggregator: 1,
+                    round: 0,
+                    byte: 7,
+                },
+            )
+            // A fault in a round that does not occur has no effect.
+            .with_faulty_report(
+                25,
+                Fault::TamperPrepareMessage {
+                    aggregator: 1,
+                    round: 1,
+                    byte: 7,
+                },
+            )
+            .run()
+            .unwrap();
+        assert_eq!(report.accepted, vec![0, 1, 2, 7]);
+        assert_eq!(report.dropped, vec![3]);
+        assert_eq!(
+            report
+                .rejected
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>(),
+            vec![4, 5, 6]
+        );
+        assert_eq!(report.aggregate_result, Prio3ResultVec(vec![1, 1, 2]));
+
+        // The check passes if the plaintext aggregate of the accepted measurements matches.
+        report
+            .check_aggregate(|measurements| {
+                assert_eq!(measurements, vec![5, 15, 25, 25]);
+                Prio3ResultVec(vec![1, 1, 2])
+            })
+            .unwrap();
+
+        // The check fails if the plaintext aggregate differs.
+        assert_eq!(
+            report
+                .check_aggregate(|_| Prio3ResultVec(vec![1, 1, 1]))
+                .unwrap_err(),
+            AggregateMismatch {
+                got: Prio3ResultVec(vec![1, 1, 2]),
+                want: Prio3ResultVec(vec![1, 1, 1]),
+            }
+        );
+
+        // A fault must refer to an existing Aggregator.
+        let vdaf = Prio3Aes128Count::new(2).unwrap();
+        Simulation::new(vdaf, ())
+            .with_faulty_report(
+                1,
+                Fault::TamperInputShare {
+                    aggregator: 2,
+                    byte: 0,
+                },
+            )
+            .run()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_simulation_poplar1() {
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(8);
+        let mut agg_param = BTreeSet::new();
+        agg_param.insert(IdpfInput::new(&[0], 4).unwrap());
+        agg_param.insert(IdpfInput::new(&[1], 4).unwrap());
+        let measurements = [0u8, 1, 1, 0x11, 2]
+            .iter()
+            .map(|x| IdpfInput::new(&[*x], 8).unwrap())
+            .collect::<Vec<_>>();
+        let report = Simulation::new(vdaf, agg_param.clone())
+            .with_reports(measurements)
+            .with_faulty_report(
+                IdpfInput::new(&[0], 8).unwrap(),
+                Fault::TamperPrepareMessage {
+                    aggregator: 0,
+                    round: 1,
+                    byte: 3,
+                },
+            )
+            .run()
+            .unwrap();
+        assert_eq!(report.accepted, vec![0, 1, 2, 3, 4]);
+        assert_eq!(report.rejected.len(), 1);
+
+        // Count the measurements with each prefix in the aggregation parameter.
+        report
+            .check_aggregate(|measurements| {
+                let mut counts = agg_param
+                    .iter()
+                    .map(|prefix| (prefix.clone(), 0))
+                    .collect::<BTreeMap<_, _>>();
+                for measurement in measurements {
+                    let byte = (0..=255)
+                        .find(|x| IdpfInput::new(&[*x], 8).unwrap() == measurement)
+                        .unwrap();
+                    if let Some(count) = counts.get_mut(&IdpfInput::new(&[byte], 4).unwrap()) {
+                        *count += 1;
+                    }
+                }
+                counts
+            })
+            .unwrap();
+        assert_eq!(
+            report.aggregate_result.values().collect::<Vec<_>>(),
+            [&1, &3]
+        );
+    }
+}
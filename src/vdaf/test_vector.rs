@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Module `test_vector` generates and checks test vectors for VDAFs in the format of
+//! [draft-patton-cfrg-vdaf-00]. A test vector records the output of each algorithm of the VDAF
+//! (setup, shard, prepare, aggregate and unshard) when run with fixed randomness, so that it can
+//! be used to check interoperability with other implementations of the same VDAF.
+//!
+//! [draft-patton-cfrg-vdaf-00]: https://datatracker.ietf.org/doc/draft-patton-cfrg-vdaf/00/
+
+use crate::codec::Encode;
+use crate::field::FieldElement;
+use crate::vdaf::{run_prepare, Aggregator, Client, Collector, Vdaf, VdafError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// The nonce used for each report when generating a test vector.
+pub const TEST_VECTOR_NONCE: [u8; 16] = [1; 16];
+
+/// A VDAF for which test vectors can be generated and checked. The types used to represent the
+/// measurements, the aggregation parameter and the aggregate result in test vectors may differ from
+/// the types used by the VDAF itself.
+///
+/// The public parameter is represented in test vectors as `null`.
+pub trait TestVectorVdaf: Client + Aggregator + Collector + Vdaf<PublicParam = ()>
+where
+    for<'a> &'a Self::AggregateShare: Into<Vec<u8>>,
+{
+    /// The representation of a measurement.
+    type TestVecMeasurement: Clone + Debug + PartialEq + Serialize + DeserializeOwned;
+
+    /// The representation of an aggregation parameter.
+    type TestVecAggParam: Clone + Debug + PartialEq + Serialize + DeserializeOwned;
+
+    /// The representation of an aggregate result.
+    type TestVecAggResult: Clone + Debug + PartialEq + Serialize + DeserializeOwned;
+
+    /// Returns the aggregator ID and the encoded verification key of a verification parameter.
+    fn test_vec_verify_param(&self, verify_param: &Self::VerifyParam) -> (u8, Vec<u8>);
+
+    /// Converts a measurement from its test vector representation.
+    fn test_vec_measurement(
+        &self,
+        measurement: &Self::TestVecMeasurement,
+    ) -> Result<Self::Measurement, VdafError>;
+
+    /// Converts an aggregation parameter from its test vector representation.
+    fn test_vec_agg_param(
+        &self,
+        agg_param: &Self::TestVecAggParam,
+    ) -> Result<Self::AggregationParam, VdafError>;
+
+    /// Returns the integer representation of each field element of an output share.
+    fn test_vec_output_share(&self, output_share: &Self::OutputShare) -> Vec<u128>;
+
+    /// Returns the integer representation of each field element of an aggregate share.
+    fn test_vec_agg_share(&self, agg_share: &Self::AggregateShare) -> Vec<u128>;
+
+    /// Computes the test vector representation of the aggregate result from the aggregate shares.
+    fn test_vec_agg_result(
+        &self,
+        agg_param: &Self::AggregationParam,
+        agg_shares: Vec<Self::AggregateShare>,
+    ) -> Result<Self::TestVecAggResult, VdafError>;
+}
+
+/// A byte string, represented in test vectors as a hex string.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TestVectorBytes(#[serde(with = "hex")] pub Vec<u8>);
+
+impl AsRef<[u8]> for TestVectorBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The preparation of a single report.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TestVectorPrep<M> {
+    /// The measurement.
+    pub measurement: M,
+
+    /// The nonce.
+    pub nonce: TestVectorBytes,
+
+    /// The encoded input share of each aggregator.
+    pub input_shares: Vec<TestVectorBytes>,
+
+    /// For each round of preparation, the encoded prepare message of each aggregator.
+    pub prep_shares: Vec<Vec<TestVectorBytes>>,
+
+    /// The output share of each aggregator.
+    pub out_shares: Vec<Vec<u128>>,
+}
+
+/// A test vector for a VDAF. `M`, `A` and `R` are the representations of the measurements, the
+/// aggregation parameter and the aggregate result respectively (see [`TestVectorVdaf`]).
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TestVector<M, A, R> {
+    /// The public parameter.
+    pub public_param: (),
+
+    /// The aggregator ID and encoded verification key of each aggregator.
+    pub verify_params: Vec<(u8, TestVectorBytes)>,
+
+    /// The aggregation parameter.
+    pub agg_param: A,
+
+    /// The preparation of each report.
+    pub prep: Vec<TestVectorPrep<M>>,
+
+    /// The aggregate share of each aggregator.
+    pub agg_shares: Vec<Vec<u128>>,
+
+    /// The aggregate result.
+    pub agg_result: R,
+}
+
+/// The source of randomness used to generate test vectors.
+fn test_vec_rand_source(buf: &mut [u8]) -> Result<(), getrandom::Error> {
+    buf.fill(1);
+    Ok(())
+}
+
+/// Returns the integer representation of each field element in `data`.
+pub(crate) fn test_vec_field_vec<F: FieldElement>(data: &[F]) -> Vec<u128> {
+    // The integer representation of each field supported by this crate fits in a `u128`.
+    data.iter()
+        .map(|x| x.to_string().parse().unwrap())
+        .collect()
+}
+
+/// Generates a test vector for `vdaf` with the given aggregation parameter and measurements. The
+/// nonce of each report is [`TEST_VECTOR_NONCE`].
+#[allow(clippy::type_complexity)]
+pub fn generate_test_vector<V>(
+    vdaf: &V,
+    agg_param: &V::TestVecAggParam,
+    measurements: &[V::TestVecMeasurement],
+) -> Result<TestVector<V::TestVecMeasurement, V::TestVecAggParam, V::TestVecAggResult>, VdafError>
+where
+    V: TestVectorVdaf,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    run_test_vector(
+        vdaf,
+        agg_param,
+        measurements
+            .iter()
+            .map(|measurement| (measurement.clone(), TEST_VECTOR_NONCE.to_vec())),
+    )
+}
+
+/// Checks that running `vdaf` on the aggregation parameter, measurements and nonces of
+/// `test_vector` produces the test vector.
+///
+/// # Panics
+///
+/// Panics if the VDAF fails or if its output does not match the test vector.
+pub fn check_test_vector<V>(
+    vdaf: &V,
+    test_vector: &TestVector<V::TestVecMeasurement, V::TestVecAggParam, V::TestVecAggResult>,
+) where
+    V: TestVectorVdaf,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+{
+    let got = run_test_vector(
+        vdaf,
+        &test_vector.agg_param,
+        test_vector
+            .prep
+            .iter()
+            .map(|prep| (prep.measurement.clone(), prep.nonce.0.clone())),
+    )
+    .unwrap_or_else(|e| panic!("failed to run test vector: {}", e));
+
+    assert_eq!(
+        got.verify_params, test_vector.verify_params,
+        "verify_params"
+    );
+    assert_eq!(got.prep.len(), test_vector.prep.len(), "prep");
+    for (test_num, (got, want)) in got.prep.iter().zip(test_vector.prep.iter()).enumerate() {
+        assert_eq!(
+            got.input_shares, want.input_shares,
+            "#{} input_shares",
+            test_num
+        );
+        assert_eq!(
+            got.prep_shares, want.prep_shares,
+            "#{} prep_shares",
+            test_num
+        );
+        assert_eq!(got.out_shares, want.out_shares, "#{} out_shares", test_num);
+    }
+    assert_eq!(got.agg_shares, test_vector.agg_shares, "agg_shares");
+    assert_eq!(got.agg_result, test_vector.agg_result, "agg_result");
+}
+
+#[allow(clippy::type_complexity)]
+fn run_test_vector<V, M>(
+    vdaf: &V,
+    test_vec_agg_param: &V::TestVecAggParam,
+    measurements: M,
+) -> Result<TestVector<V::TestVecMeasurement, V::TestVecAggParam, V::TestVecAggResult>, VdafError>
+where
+    V: TestVectorVdaf,
+    for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    M: IntoIterator<Item = (V::TestVecMeasurement, Vec<u8>)>,
+{
+    let (public_param, verify_params) = vdaf.setup_with_rng(&mut test_vec_rand_source)?;
+    let agg_param = vdaf.test_vec_agg_param(test_vec_agg_param)?;
+
+    let mut prep = Vec::new();
+    let mut out_shares = vec![Vec::new(); vdaf.num_aggregators()];
+    for (test_vec_measurement, nonce) in measurements.into_iter() {
+        let measurement = vdaf.test_vec_measurement(&test_vec_measurement)?;
+        let input_shares =
+            vdaf.shard_with_rng(&public_param, &measurement, &mut test_vec_rand_source)?;
+
+        let mut states = Vec::with_capacity(input_shares.len());
+        for (verify_param, input_share) in verify_params.iter().zip(input_shares.iter()) {
+            states.push(vdaf.prepare_init(verify_param, &agg_param, &nonce, input_share)?);
+        }
+
+        let mut prep_shares: Vec<Vec<TestVectorBytes>> = Vec::new();
+        let report_out_shares = run_prepare(vdaf, states, |round, _aggregator, _state, msg| {
+            if round == prep_shares.len() {
+                prep_shares.push(Vec::new());
+            }
+            prep_shares[round].push(TestVectorBytes(msg.get_encoded()));
+            Ok(msg)
+        })?;
+
+        prep.push(TestVectorPrep {
+            measurement: test_vec_measurement,
+            nonce: TestVectorBytes(nonce),
+            input_shares: input_shares
+                .iter()
+                .map(|input_share| TestVectorBytes(input_share.get_encoded()))
+                .collect(),
+            prep_shares,
+            out_shares: report_out_shares
+                .iter()
+                .map(|out_share| vdaf.test_vec_output_share(out_share))
+                .collect(),
+        });
+
+        for (out_share, out) in report_out_shares.into_iter().zip(out_shares.iter_mut()) {
+            out.push(out_share);
+        }
+    }
+
+    let mut agg_shares = Vec::with_capacity(out_shares.len());
+    for out in out_shares.into_iter() {
+        agg_shares.push(vdaf.aggregate(&agg_param, out)?);
+    }
+
+    // Check that the aggregate shares can be unsharded. The representation of the aggregate result
+    // in the test vector may differ from the aggregate result, so it is computed separately.
+    vdaf.unshard(&agg_param, agg_shares.clone())?;
+
+    Ok(TestVector {
+        public_param,
+        verify_params: verify_params
+            .iter()
+            .map(|verify_param| {
+                let (aggregator_id, key) = vdaf.test_vec_verify_param(verify_param);
+                (aggregator_id, TestVectorBytes(key))
+            })
+            .collect(),
+        agg_param: test_vec_agg_param.clone(),
+        prep,
+        agg_shares: agg_shares
+            .iter()
+            .map(|agg_share| vdaf.test_vec_agg_share(agg_share))
+            .collect(),
+        agg_result: vdaf.test_vec_agg_result(&agg_param, agg_shares)?,
+    })
+}
@@ -0,0 +1,720 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Module `topology` implements drivers for the Prepare process in common communication
+//! topologies, so that applications need not wire [`Aggregator::prepare_init`],
+//! [`Aggregator::prepare_step`] and [`Aggregator::prepare_preprocess`] together themselves.
+//!
+//! The ping-pong topology is used by two Aggregators, a Leader and a Helper, that take turns
+//! sending messages to each other. The Leader begins by sending its first prepare message share to
+//! the Helper. Each subsequent message carries the combined prepare message of the previous round,
+//! and, unless the sender has recovered its output share, its prepare message share for the next
+//! round. The party that receives a prepare message share combines it with its own and replies.
+//!
+//! ```
+//! use prio::vdaf::{
+//!     prio3::Prio3Aes128Count,
+//!     topology::{PingPong, PingPongTransition},
+//!     Client, Vdaf,
+//! };
+//!
+//! let vdaf = Prio3Aes128Count::new(2).unwrap();
+//! let (public_param, verify_params) = vdaf.setup().unwrap();
+//! let input_shares = vdaf.shard(&public_param, &1).unwrap();
+//! let nonce = b"this is a nonce";
+//!
+//! // The Leader begins and sends its message to the Helper.
+//! let (leader, msg) =
+//!     match PingPong::leader_init(&vdaf, &verify_params[0], &(), nonce, &input_shares[0])
+//!         .unwrap()
+//!     {
+//!         PingPongTransition::Continue(leader, msg) => (leader, msg),
+//!         PingPongTransition::Finish(..) => unreachable!(),
+//!     };
+//!
+//! // Prio3 takes one round, so the Helper finishes right away and replies to the Leader.
+//! let msg = match PingPong::helper_init(
+//!     &vdaf,
+//!     &verify_params[1],
+//!     &(),
+//!     nonce,
+//!     &input_shares[1],
+//!     &msg,
+//! )
+//! .unwrap()
+//! {
+//!     PingPongTransition::Finish(_helper_out_share, Some(msg)) => msg,
+//!     _ => unreachable!(),
+//! };
+//!
+//! match leader.step(&msg).unwrap() {
+//!     PingPongTransition::Finish(_leader_out_share, None) => (),
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use crate::codec::{
+    decode_u32_items, encode_u32_items, CodecError, Decode, Encode, ParameterizedDecode,
+};
+use crate::vdaf::{Aggregator, PrepareError, PrepareTransition, VdafError};
+use std::fmt::Debug;
+use std::io::Cursor;
+
+/// Errors emitted by this module.
+#[derive(Debug, thiserror::Error)]
+pub enum PingPongError {
+    /// The VDAF failed to prepare the input share.
+    #[error("vdaf error: {0}")]
+    Vdaf(#[from] VdafError),
+
+    /// A message from the peer could not be decoded.
+    #[error("codec error: {0}")]
+    Codec(#[from] CodecError),
+
+    /// The VDAF is not run by exactly two Aggregators.
+    #[error("ping-pong topology requires two aggregators, got {0}")]
+    NumAggregators(usize),
+
+    /// The peer sent a message of a type that was not expected at this point.
+    #[error("unexpected {0} message")]
+    UnexpectedMessage(&'static str),
+
+    /// The peer and this Aggregator disagree on the number of rounds of the Prepare process.
+    #[error("peer {0}")]
+    RoundMismatch(&'static str),
+}
+
+/// The role of an Aggregator in the ping-pong topology.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The Aggregator that sends the first message.
+    Leader,
+
+    /// The Aggregator that responds to the Leader.
+    Helper,
+}
+
+/// A message exchanged by the Aggregators in the ping-pong topology. Prepare messages and prepare
+/// message shares are carried in their encoded form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PingPongMessage {
+    /// The first message sent by the Leader.
+    Initialize {
+        /// The Leader's prepare message share for the first round.
+        prep_share: Vec<u8>,
+    },
+
+    /// A message sent by an Aggregator that has not yet recovered its output share.
+    Continue {
+        /// The prepare message of the previous round.
+        prep_msg: Vec<u8>,
+
+        /// The sender's prepare message share for the next round.
+        prep_share: Vec<u8>,
+    },
+
+    /// The last message, sent by the Aggregator that recovers its output share first.
+    Finish {
+        /// The prepare message of the last round.
+        prep_msg: Vec<u8>,
+    },
+}
+
+impl PingPongMessage {
+    fn name(&self) -> &'static str {
+        match self {
+            PingPongMessage::Initialize { .. } => "initialize",
+            PingPongMessage::Continue { .. } => "continue",
+            PingPongMessage::Finish { .. } => "finish",
+        }
+    }
+}
+
+impl Encode for PingPongMessage {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match self {
+            PingPongMessage::Initialize { prep_share } => {
+                0u8.encode(bytes);
+                encode_opaque(bytes, prep_share);
+            }
+            PingPongMessage::Continue {
+                prep_msg,
+                prep_share,
+            } => {
+                1u8.encode(bytes);
+                encode_opaque(bytes, prep_msg);
+                encode_opaque(bytes, prep_share);
+            }
+            PingPongMessage::Finish { prep_msg } => {
+                2u8.encode(bytes);
+                encode_opaque(bytes, prep_msg);
+            }
+        }
+    }
+}
+
+impl Decode for PingPongMessage {
+    fn decode(bytes: &mut Cursor<&[u8]>) -> Result<Self, CodecError> {
+        match u8::decode(bytes)? {
+            0 => Ok(PingPongMessage::Initialize {
+                prep_share: decode_opaque(bytes)?,
+            }),
+            1 => Ok(PingPongMessage::Continue {
+                prep_msg: decode_opaque(bytes)?,
+                prep_share: decode_opaque(bytes)?,
+            }),
+            2 => Ok(PingPongMessage::Finish {
+                prep_msg: decode_opaque(bytes)?,
+            }),
+            _ => Err(CodecError::UnexpectedValue),
+        }
+    }
+}
+
+// Encodes a byte string with a 4-byte length prefix.
+fn encode_opaque(bytes: &mut Vec<u8>, data: &[u8]) {
+    encode_u32_items(bytes, &(), data);
+}
+
+fn decode_opaque(bytes: &mut Cursor<&[u8]>) -> Result<Vec<u8>, CodecError> {
+    decode_u32_items(&(), bytes)
+}
+
+/// A state transition of an Aggregator in the ping-pong topology.
+#[derive(Debug)]
+pub enum PingPongTransition<'a, A: Aggregator>
+where
+    for<'b> &'b A::AggregateShare: Into<Vec<u8>>,
+{
+    /// Send the encoded message to the peer and pass its reply to [`PingPong::step`].
+    Continue(PingPong<'a, A>, Vec<u8>),
+
+    /// The Aggregator recovered its output share. If a message is present, it must be sent to the
+    /// peer so that the peer can recover its output share as well.
+    Finish(A::OutputShare, Option<Vec<u8>>),
+}
+
+/// An Aggregator waiting for a message from its peer in the ping-pong topology.
+#[derive(Clone, Debug)]
+pub struct PingPong<'a, A: Aggregator>
+where
+    for<'b> &'b A::AggregateShare: Into<Vec<u8>>,
+{
+    vdaf: &'a A,
+    role: Role,
+    step: A::PrepareStep,
+}
+
+impl<'a, A: Aggregator> PingPong<'a, A>
+where
+    for<'b> &'b A::AggregateShare: Into<Vec<u8>>,
+{
+    /// Begins the Prepare process as the Leader. The message returned in
+    /// [`PingPongTransition::Continue`] is sent to the Helper, which passes it to
+    /// [`PingPong::helper_init`].
+    pub fn leader_init(
+        vdaf: &'a A,
+        verify_param: &A::VerifyParam,
+        agg_param: &A::AggregationParam,
+        nonce: &[u8],
+        input_share: &A::InputShare,
+    ) -> Result<PingPongTransition<'a, A>, PingPongError> {
+        let (step, prep_share) = init(vdaf, verify_param, agg_param, nonce, input_share)?;
+        Ok(PingPongTransition::Continue(
+            Self {
+                vdaf,
+                role: Role::Leader,
+                step,
+            },
+            PingPongMessage::Initialize {
+                prep_share: prep_share.get_encoded(),
+            }
+            .get_encoded(),
+        ))
+    }
+
+    /// Begins the Prepare process as the Helper, given the first message sent by the Leader.
+    pub fn helper_init(
+        vdaf: &'a A,
+        verify_param: &A::VerifyParam,
+        agg_param: &A::AggregationParam,
+        nonce: &[u8],
+        input_share: &A::InputShare,
+        inbound: &[u8],
+    ) -> Result<PingPongTransition<'a, A>, PingPongError> {
+        let peer_prep_share = match PingPongMessage::get_decoded(inbound)? {
+            PingPongMessage::Initialize { prep_share } => prep_share,
+            msg => return Err(PingPongError::UnexpectedMessage(msg.name())),
+        };
+
+        let (step, prep_share) = init(vdaf, verify_param, agg_param, nonce, input_share)?;
+        Self {
+            vdaf,
+            role: Role::Helper,
+            step,
+        }
+        .combine_and_step(prep_share, &peer_prep_share)
+    }
+
+    /// The role of this Aggregator.
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// The current state of the Prepare process, which may be encoded in order to persist it
+    /// while waiting for the peer.
+    pub fn prepare_step(&self) -> &A::PrepareStep {
+        &self.step
+    }
+
+    /// Resumes the Prepare process from a state returned by [`PingPong::prepare_step`].
+    pub fn from_prepare_step(vdaf: &'a A, role: Role, step: A::PrepareStep) -> Self {
+        Self { vdaf, role, step }
+    }
+
+    /// Consumes the next message sent by the peer.
+    pub fn step(self, inbound: &[u8]) -> Result<PingPongTransition<'a, A>, PingPongError> {
+        match PingPongMessage::get_decoded(inbound)? {
+            PingPongMessage::Continue {
+                prep_msg,
+                prep_share: peer_prep_share,
+            } => {
+                let prep_msg = A::PrepareMessage::get_decoded_with_param(&self.step, &prep_msg)?;
+                match self.vdaf.prepare_step(self.step, Some(prep_msg)) {
+                    PrepareTransition::Continue(step, prep_share) => Self {
+                        vdaf: self.vdaf,
+                        role: self.role,
+                        step,
+                    }
+                    .combine_and_step(prep_share, &peer_prep_share),
+                    PrepareTransition::Finish(_) => Err(PingPongError::RoundMismatch(
+                        "continued after the last round",
+                    )),
+                    PrepareTransition::Fail(err) => Err(err.into()),
+                }
+            }
+
+            PingPongMessage::Finish { prep_msg } => {
+                let prep_msg = A::PrepareMessage::get_decoded_with_param(&self.step, &prep_msg)?;
+                match self.vdaf.prepare_step(self.step, Some(prep_msg)) {
+                    PrepareTransition::Continue(..) => Err(PingPongError::RoundMismatch(
+                        "finished before the last round",
+                    )),
+                    PrepareTransition::Finish(out_share) => {
+                        Ok(PingPongTransition::Finish(out_share, None))
+                    }
+                    PrepareTransition::Fail(err) => Err(err.into()),
+                }
+            }
+
+            msg => Err(PingPongError::UnexpectedMessage(msg.name())),
+        }
+    }
+
+    // Combines this Aggregator's prepare message share with the peer's and computes the next
+    // state transition. The resulting prepare message is sent to the peer along with the next
+    // prepare message share, if any.
+    fn combine_and_step(
+        self,
+        prep_share: A::PrepareMessage,
+        peer_prep_share: &[u8],
+    ) -> Result<PingPongTransition<'a, A>, PingPongError> {
+        let peer_prep_share =
+            A::PrepareMessage::get_decoded_with_param(&self.step, peer_prep_share)?;
+        let prep_shares = match self.role {
+            Role::Leader => [prep_share, peer_prep_share],
+            Role::Helper => [peer_prep_share, prep_share],
+        };
+        let prep_msg = self.vdaf.prepare_preprocess(prep_shares)?;
+        let encoded_prep_msg = prep_msg.get_encoded();
+
+        match self.vdaf.prepare_step(self.step, Some(prep_msg)) {
+            PrepareTransition::Continue(step, prep_share) => Ok(PingPongTransition::Continue(
+                Self {
+                    vdaf: self.vdaf,
+                    role: self.role,
+                    step,
+                },
+                PingPongMessage::Continue {
+                    prep_msg: encoded_prep_msg,
+                    prep_share: prep_share.get_encoded(),
+                }
+                .get_encoded(),
+            )),
+            PrepareTransition::Finish(out_share) => Ok(PingPongTransition::Finish(
+                out_share,
+                Some(
+                    PingPongMessage::Finish {
+                        prep_msg: encoded_prep_msg,
+                    }
+                    .get_encoded(),
+                ),
+            )),
+            PrepareTransition::Fail(err) => Err(err.into()),
+        }
+    }
+}
+
+// Begins the Prepare process and computes this Aggregator's first prepare message share.
+fn init<A: Aggregator>(
+    vdaf: &A,
+    verify_param: &A::VerifyParam,
+    agg_param: &A::AggregationParam,
+    nonce: &[u8],
+    input_share: &A::InputShare,
+) -> Result<(A::PrepareStep, A::PrepareMessage), PingPongError>
+where
+    for<'a> &'a A::AggregateShare: Into<Vec<u8>>,
+{
+    if vdaf.num_aggregators() != 2 {
+        return Err(PingPongError::NumAggregators(vdaf.num_aggregators()));
+    }
+
+    let step = vdaf.prepare_init(verify_param, agg_param, nonce, input_share)?;
+    match vdaf.prepare_step(step, None) {
+        PrepareTransition::Continue(step, prep_share) => Ok((step, prep_share)),
+        // The ping-pong topology requires at least one round of messages.
+        PrepareTransition::Finish(_) => {
+            Err(VdafError::from(PrepareError::InvalidStateTransition).into())
+        }
+        PrepareTransition::Fail(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{Field128, Field64};
+    use crate::vdaf::poplar1::{IdpfInput, Poplar1, TreeIdpf};
+    use crate::vdaf::prg::PrgAes128;
+    use crate::vdaf::prio3::{Prio3Aes128Count, Prio3Aes128Histogram, Prio3Aes128Sum};
+    use crate::vdaf::{Aggregatable, AggregateShare, Client, Collector, Vdaf};
+    use assert_matches::assert_matches;
+    use std::collections::BTreeSet;
+    use std::sync::mpsc::{channel, Sender};
+
+    // The output of the Prepare process run by `run_ping_pong`.
+    struct PingPongOutput<V: Aggregator>
+    where
+        for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    {
+        leader_out_share: Option<V::OutputShare>,
+        helper_out_share: Option<V::OutputShare>,
+        num_msgs: usize,
+    }
+
+    // Records the transition of one of the parties, sending its message to the peer.
+    fn handle<'a, V>(
+        transition: PingPongTransition<'a, V>,
+        state: &mut Option<PingPong<'a, V>>,
+        out_share: &mut Option<V::OutputShare>,
+        outbox: &Sender<Vec<u8>>,
+        num_msgs: &mut usize,
+    ) where
+        V: Aggregator,
+        for<'b> &'b V::AggregateShare: Into<Vec<u8>>,
+    {
+        let msg = match transition {
+            PingPongTransition::Continue(next, msg) => {
+                *state = Some(next);
+                Some(msg)
+            }
+            PingPongTransition::Finish(output, msg) => {
+                *out_share = Some(output);
+                msg
+            }
+        };
+        if let Some(msg) = msg {
+            outbox.send(msg).unwrap();
+            *num_msgs += 1;
+        }
+    }
+
+    // Runs the Prepare process between a Leader and a Helper that exchange messages over in-memory
+    // channels, until neither of them has a message to process.
+    fn run_ping_pong<V>(
+        vdaf: &V,
+        verify_params: &[V::VerifyParam],
+        agg_param: &V::AggregationParam,
+        nonce: &[u8],
+        input_shares: &[V::InputShare],
+    ) -> Result<PingPongOutput<V>, PingPongError>
+    where
+        V: Aggregator,
+        for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    {
+        let (to_helper, helper_inbox) = channel();
+        let (to_leader, leader_inbox) = channel();
+        let mut leader: Option<PingPong<V>> = None;
+        let mut helper: Option<PingPong<V>> = None;
+        let mut output = PingPongOutput {
+            leader_out_share: None,
+            helper_out_share: None,
+            num_msgs: 0,
+        };
+
+        let transition =
+            PingPong::leader_init(vdaf, &verify_params[0], agg_param, nonce, &input_shares[0])?;
+        handle(
+            transition,
+            &mut leader,
+            &mut output.leader_out_share,
+            &to_helper,
+            &mut output.num_msgs,
+        );
+
+        let mut helper_started = false;
+        while let Ok(inbound) = helper_inbox.try_recv() {
+            let transition = if helper_started {
+                helper.take().unwrap().step(&inbound)?
+            } else {
+                helper_started = true;
+                PingPong::helper_init(
+                    vdaf,
+                    &verify_params[1],
+                    agg_param,
+                    nonce,
+                    &input_shares[1],
+                    &inbound,
+                )?
+            };
+            handle(
+                transition,
+                &mut helper,
+                &mut output.helper_out_share,
+                &to_leader,
+                &mut output.num_msgs,
+            );
+
+            let inbound = match leader_inbox.try_recv() {
+                Ok(inbound) => inbound,
+                Err(_) => break,
+            };
+            let transition = leader.take().unwrap().step(&inbound)?;
+            handle(
+                transition,
+                &mut leader,
+                &mut output.leader_out_share,
+                &to_helper,
+                &mut output.num_msgs,
+            );
+        }
+
+        Ok(output)
+    }
+
+    // Runs the Prepare process on a measurement and checks that both parties recover output shares
+    // that unshard to `want`.
+    fn check_ping_pong<V>(
+        vdaf: &V,
+        agg_param: &V::AggregationParam,
+        measurement: &V::Measurement,
+        want_num_msgs: usize,
+    ) -> V::AggregateResult
+    where
+        V: Client + Aggregator + Collector,
+        for<'a> &'a V::AggregateShare: Into<Vec<u8>>,
+    {
+        let (public_param, verify_params) = vdaf.setup().unwrap();
+        let input_shares = vdaf.shard(&public_param, measurement).unwrap();
+        let output = run_ping_pong(
+            vdaf,
+            &verify_params,
+            agg_param,
+            b"this is a nonce",
+            &input_shares,
+        )
+        .unwrap();
+        assert_eq!(output.num_msgs, want_num_msgs);
+
+        let agg_shares = vec![
+            output.leader_out_share.unwrap(),
+            output.helper_out_share.unwrap(),
+        ]
+        .into_iter()
+        .map(V::AggregateShare::from);
+        vdaf.unshard(agg_param, agg_shares).unwrap()
+    }
+
+    #[test]
+    fn test_ping_pong_prio3() {
+        // Prio3 has a single round, so the Helper finishes first.
+        let prio3 = Prio3Aes128Count::new(2).unwrap();
+        assert_eq!(check_ping_pong(&prio3, &(), &1, 2).0, 1);
+
+        let prio3 = Prio3Aes128Sum::new(2, 16).unwrap();
+        assert_eq!(check_ping_pong(&prio3, &(), &1337, 2).0, 1337);
+
+        let prio3 = Prio3Aes128Histogram::new(2, &[1, 10, 100]).unwrap();
+        assert_eq!(check_ping_pong(&prio3, &(), &50, 2).0, vec![0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_ping_pong_poplar1() {
+        // Poplar1 has two rounds, so the Leader finishes first.
+        let vdaf: Poplar1<TreeIdpf<Field64, Field128, PrgAes128, 16>, PrgAes128, 16> =
+            Poplar1::new(16);
+        let input = IdpfInput::new(b"hi", 16).unwrap();
+        for level in [12, 16] {
+            let prefix = IdpfInput::new(b"hi", level).unwrap();
+            let mut agg_param = BTreeSet::new();
+            agg_param.insert(prefix.clone());
+            agg_param.insert(IdpfInput::new(b"ha", level).unwrap());
+            let result = check_ping_pong(&vdaf, &agg_param, &input, 3);
+            assert_eq!(result.get(&prefix), Some(&1));
+            assert_eq!(result.values().sum::<u64>(), 1);
+        }
+    }
+
+    #[test]
+    fn test_ping_pong_invalid_input() {
+        let prio3 = Prio3Aes128Count::new(2).unwrap();
+        let (public_param, verify_params) = prio3.setup().unwrap();
+
+        // Input shares from different reports do not verify.
+        let mut input_shares = prio3.shard(&public_param, &1).unwrap();
+        input_shares[1] = prio3.shard(&public_param, &1).unwrap().remove(1);
+        let result = run_ping_pong(&prio3, &verify_params, &(), b"nonce", &input_shares);
+        assert_matches!(result.err(), Some(PingPongError::Vdaf(_)));
+    }
+
+    #[test]
+    fn test_ping_pong_unexpected_message() {
+        let prio3 = Prio3Aes128Count::new(2).unwrap();
+        let (public_param, verify_params) = prio3.setup().unwrap();
+        let input_shares = prio3.shard(&public_param, &1).unwrap();
+        let nonce = b"this is a nonce";
+
+        let (leader, msg) = assert_matches!(
+            PingPong::leader_init(&prio3, &verify_params[0], &(), nonce, &input_shares[0]),
+            Ok(PingPongTransition::Continue(leader, msg)) => (leader, msg)
+        );
+        assert_eq!(leader.role(), Role::Leader);
+
+        // The Leader does not accept its own first message.
+        assert_matches!(
+            leader.clone().step(&msg),
+            Err(PingPongError::UnexpectedMessage("initialize"))
+        );
+
+        // A truncated or unknown message cannot be decoded.
+        assert_matches!(
+            PingPong::helper_init(
+                &prio3,
+                &verify_params[1],
+                &(),
+                nonce,
+                &input_shares[1],
+                &msg[..msg.len() - 1],
+            ),
+            Err(PingPongError::Codec(_))
+        );
+        assert_matches!(
+            leader.clone().step(&[3]),
+            Err(PingPongError::Codec(CodecError::UnexpectedValue))
+        );
+
+        // The Helper rejects a message other than the Leader's first one.
+        let finish = PingPongMessage::Finish {
+            prep_msg: Vec::new(),
+        }
+        .get_encoded();
+        assert_matches!(
+            PingPong::helper_init(
+                &prio3,
+                &verify_params[1],
+                &(),
+                nonce,
+                &input_shares[1],
+                &finish,
+            ),
+            Err(PingPongError::UnexpectedMessage("finish"))
+        );
+
+        // The Helper finishes after one round, so a Continue message from it would be rejected
+        // by the Leader.
+        let prep_msg = assert_matches!(
+            PingPong::helper_init(&prio3, &verify_params[1], &(), nonce, &input_shares[1], &msg),
+            Ok(PingPongTransition::Finish(_, Some(msg))) => {
+                assert_matches!(
+                    PingPongMessage::get_decoded(&msg),
+                    Ok(PingPongMessage::Finish { prep_msg }) => prep_msg
+                )
+            }
+        );
+        let cont = PingPongMessage::Continue {
+            prep_msg,
+            prep_share: Vec::new(),
+        }
+        .get_encoded();
+        assert_matches!(leader.step(&cont), Err(PingPongError::RoundMismatch(_)));
+
+        // The topology requires exactly two aggregators.
+        let prio3 = Prio3Aes128Count::new(3).unwrap();
+        let (public_param, verify_params) = prio3.setup().unwrap();
+        let input_shares = prio3.shard(&public_param, &1).unwrap();
+        assert_matches!(
+            PingPong::leader_init(&prio3, &verify_params[0], &(), nonce, &input_shares[0]),
+            Err(PingPongError::NumAggregators(3))
+        );
+    }
+
+    #[test]
+    fn test_ping_pong_resume() {
+        let prio3 = Prio3Aes128Sum::new(2, 8).unwrap();
+        let (public_param, verify_params) = prio3.setup().unwrap();
+        let input_shares = prio3.shard(&public_param, &42).unwrap();
+        let nonce = b"this is a nonce";
+
+        // The Leader persists its state while waiting for the Helper.
+        let (encoded_step, msg) = assert_matches!(
+            PingPong::leader_init(&prio3, &verify_params[0], &(), nonce, &input_shares[0]),
+            Ok(PingPongTransition::Continue(leader, msg)) => {
+                (leader.prepare_step().get_encoded(), msg)
+            }
+        );
+        let (helper_out_share, msg) = assert_matches!(
+            PingPong::helper_init(&prio3, &verify_params[1], &(), nonce, &input_shares[1], &msg),
+            Ok(PingPongTransition::Finish(out_share, Some(msg))) => (out_share, msg)
+        );
+
+        let step = <Prio3Aes128Sum as Aggregator>::PrepareStep::get_decoded_with_param(
+            &(&prio3, &verify_params[0]),
+            &encoded_step,
+        )
+        .unwrap();
+        let leader = PingPong::from_prepare_step(&prio3, Role::Leader, step);
+        let leader_out_share = assert_matches!(
+            leader.step(&msg),
+            Ok(PingPongTransition::Finish(out_share, None)) => out_share
+        );
+
+        let mut agg_share = AggregateShare::from(leader_out_share);
+        agg_share.merge(&helper_out_share.into()).unwrap();
+        assert_eq!(prio3.unshard(&(), [agg_share]).unwrap().0, 42);
+    }
+
+    #[test]
+    fn test_ping_pong_message_serialization() {
+        for msg in [
+            PingPongMessage::Initialize {
+                prep_share: vec![1, 2, 3],
+            },
+            PingPongMessage::Continue {
+                prep_msg: vec![4; 300],
+                prep_share: Vec::new(),
+            },
+            PingPongMessage::Finish {
+                prep_msg: vec![5, 6],
+            },
+        ] {
+            let encoded = msg.get_encoded();
+            assert_eq!(PingPongMessage::get_decoded(&encoded).unwrap(), msg);
+        }
+
+        // The length prefix of a byte string must not overflow the buffer.
+        assert_matches!(
+            PingPongMessage::get_decoded(&[2, 0, 0, 0, 3, 1, 2]),
+            Err(CodecError::LengthPrefixTooBig(3))
+        );
+    }
+}